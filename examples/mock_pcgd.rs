@@ -0,0 +1,38 @@
+use std::env;
+use colored::Colorize;
+use mock_server::MockServer;
+
+/// Cổng giả lập dùng chung với các test, chạy bằng `cargo run --example mock_pcgd`.
+#[path = "../tests/common/mock_server.rs"]
+#[allow(dead_code)]
+mod mock_server;
+
+const DEFAULT_PORT: &str = "8088";
+const DEFAULT_TOKEN: &str = "mock-csrf-token";
+
+/// Giá trị của tham số `--name value`, nếu có.
+fn arg_value(args: &[String], name: &str) -> Option<String> {
+    args.iter()
+        .position(|arg| arg == name)
+        .and_then(|position| args.get(position + 1).cloned())
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let port = arg_value(&args, "--port").unwrap_or_else(|| DEFAULT_PORT.to_owned());
+    let token = arg_value(&args, "--token").unwrap_or_else(|| DEFAULT_TOKEN.to_owned());
+
+    let server = match MockServer::start(&format!("127.0.0.1:{}", port), &token) {
+        Ok(server) => server,
+        Err(error) => {
+            println!("{}", format!("> {}", error).red().bold());
+            return;
+        },
+    };
+
+    println!("{} Cổng PCGD giả lập đang chạy tại {}", ">".green().bold(), server.base_url);
+    println!("{} CSRF token: {}", ">".green().bold(), token);
+    println!("{} Chạy công cụ với --base-url {} (hoặc PCGD_BASE_URL) và một lệnh cURL có token trên.", ">".green().bold(), server.base_url);
+
+    server.wait();
+}
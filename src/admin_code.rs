@@ -0,0 +1,121 @@
+use std::{collections::BTreeSet, fmt, str::FromStr};
+use crate::pcgd_client::{PcgdClient, PcgdError};
+
+/// Mã đơn vị hành chính trong số phiếu: tỉnh, huyện, xã và thôn (có thể thiếu), dạng XX_YYYY_ZZZZZ_N.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AdminCode {
+    province: String,
+    district: String,
+    commune: String,
+    village: Option<String>,
+}
+
+impl AdminCode {
+    pub fn ma_tinh(&self) -> String {
+        self.province.clone()
+    }
+
+    pub fn ma_quanhuyen(&self) -> String {
+        format!("{}_{}", self.province, self.district)
+    }
+
+    pub fn ma_phuongxa(&self) -> String {
+        format!("{}_{}_{}", self.province, self.district, self.commune)
+    }
+
+    /// Mã thôn như trên cổng (VD: 01_001_00001_1), `None` nếu mã chỉ tới cấp xã.
+    pub fn ma_thonxom(&self) -> Option<String> {
+        self.village.as_ref().map(|village| format!("{}_{}", self.ma_phuongxa(), village))
+    }
+
+    /// Mã xã chứa mã này, bỏ phần thôn.
+    pub fn commune(&self) -> AdminCode {
+        AdminCode { village: None, ..self.clone() }
+    }
+
+    /// Phần đầu của mã số phiếu, VD: 01_001_00001_1_.
+    pub fn prefix(&self) -> String {
+        format!("{}_", self)
+    }
+
+    /// Mã thôn của một hộ trong xã này, lấy theo thứ tự: ô mã thôn (mã đầy đủ hoặc chỉ số thôn),
+    /// số phiếu dạng XX_YYYY_ZZZZZ_N_..., rồi tới thôn của chính mã này.
+    pub fn village_for(&self, thon_xom: &str, so_phieu: &str) -> Result<AdminCode, String> {
+        let commune = self.commune();
+        let thon_xom = thon_xom.trim();
+
+        if !thon_xom.is_empty() {
+            let code: AdminCode = if thon_xom.contains('_') {
+                thon_xom.parse()?
+            } else {
+                format!("{}_{}", commune, thon_xom).parse()?
+            };
+
+            if code.village.is_none() || code.commune() != commune {
+                return Err(format!("Mã thôn \"{}\" không thuộc xã {}.", thon_xom, commune));
+            }
+            return Ok(code);
+        }
+
+        let village = so_phieu.trim()
+            .strip_prefix(&commune.prefix())
+            .and_then(|rest| rest.split_once('_'))
+            .map(|(village, _)| village);
+
+        match (village, &self.village) {
+            (Some(village), _) => format!("{}_{}", commune, village).parse(),
+            (None, Some(_)) => Ok(self.clone()),
+            (None, None) => Err(format!("Không xác định được thôn của số phiếu {}: cần cột mã thôn hoặc số phiếu dạng {}N_...", so_phieu.trim(), commune.prefix())),
+        }
+    }
+}
+
+impl FromStr for AdminCode {
+    type Err = String;
+
+    /// Nhận XX_YYYY_ZZZZZ hoặc XX_YYYY_ZZZZZ_N, có thể kèm dấu _ ở cuối như đầu số phiếu.
+    fn from_str(code: &str) -> Result<Self, String> {
+        let code = code.trim();
+        let parts: Vec<&str> = code.trim_end_matches('_').split('_').collect();
+
+        let digits = |part: &str, lengths: &[usize]| lengths.contains(&part.len()) && part.chars().all(|c| c.is_ascii_digit());
+
+        let valid = (parts.len() == 3 || parts.len() == 4)
+            && digits(parts[0], &[2])
+            && digits(parts[1], &[3, 4])
+            && digits(parts[2], &[5])
+            && parts.get(3).map(|village| !village.is_empty() && village.chars().all(|c| c.is_ascii_alphanumeric())).unwrap_or(true);
+
+        if !valid {
+            return Err(format!("Mã \"{}\" không đúng dạng XX_YYYY_ZZZZZ_N (tỉnh_huyện_xã_thôn).", code));
+        }
+
+        Ok(AdminCode {
+            province: parts[0].to_owned(),
+            district: parts[1].to_owned(),
+            commune: parts[2].to_owned(),
+            village: parts.get(3).map(|village| village.to_string()),
+        })
+    }
+}
+
+impl fmt::Display for AdminCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.ma_thonxom() {
+            Some(ma_thonxom) => write!(f, "{}", ma_thonxom),
+            None => write!(f, "{}", self.ma_phuongxa()),
+        }
+    }
+}
+
+/// Các mã thôn đã có phiếu trên cổng trong xã của `code`, lấy từ danh sách phiếu (phieudieutra/lay_phieu).
+/// Cổng không có danh mục đơn vị hành chính nào đã đối chiếu được với request thật, nên đây là cách duy nhất để kiểm tra mã:
+/// thôn có trong danh sách chắc chắn có trên cổng, còn thôn chưa có phiếu nào thì chưa biết mã đúng hay sai.
+pub fn portal_villages(client: &PcgdClient, code: &AdminCode) -> Result<BTreeSet<String>, PcgdError> {
+    let phieu = client.list_phieu(&code.ma_tinh(), &code.ma_quanhuyen(), &code.ma_phuongxa())?;
+
+    Ok(phieu.iter()
+        .map(|row| row.text("ma_thonxom"))
+        .filter(|ma_thonxom| !ma_thonxom.is_empty())
+        .collect())
+}
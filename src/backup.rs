@@ -0,0 +1,133 @@
+use std::{fs, path::{Path, PathBuf}, time::{SystemTime, UNIX_EPOCH}};
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+use crate::household_info::{fill_defaults, ResidentFields};
+use crate::merge::{fill_from_portal, resident_key};
+use crate::pcgd_client::{GridRow, PcgdClient, PcgdError};
+use crate::upload::{add_member, decide, with_retries, Decision, SkippedItem, UploadOptions};
+
+pub const BACKUP_DIR: &str = "backups";
+
+/// Bản sao lưu các dòng lay_doituong của một hộ, ghi lại trước khi xoá thành viên trên cổng.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct MemberBackup {
+    pub so_phieu: String,
+    pub ma_phieu: String,
+    /// Thời điểm sao lưu (mili giây kể từ 1970-01-01 UTC).
+    pub created_at: u128,
+    pub members: Vec<GridRow>,
+}
+
+/// Ghi thành viên hiện có của hộ vào file sao lưu mới trong thư mục sao lưu, trả về đường dẫn file.
+pub fn backup_members(dir: &Path, so_phieu: &str, ma_phieu: &str, members: &[GridRow]) -> Result<PathBuf, String> {
+    let created_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis())
+        .unwrap_or_default();
+
+    let backup = MemberBackup {
+        so_phieu: so_phieu.to_owned(),
+        ma_phieu: ma_phieu.to_owned(),
+        created_at,
+        members: members.to_vec(),
+    };
+
+    fs::create_dir_all(dir)
+        .map_err(|error| format!("Không tạo được thư mục sao lưu {}: {}", dir.display(), error))?;
+
+    let path = dir.join(format!("{}_{}_{}.json", file_safe(so_phieu), file_safe(ma_phieu), created_at));
+    let content = serde_json::to_string_pretty(&backup).unwrap();
+
+    fs::write(&path, content)
+        .map_err(|error| format!("Không ghi được file sao lưu {}: {}", path.display(), error))?;
+
+    Ok(path)
+}
+
+pub fn read_backup(path: &Path) -> Result<MemberBackup, String> {
+    let content = fs::read_to_string(path)
+        .map_err(|error| format!("Không đọc được {}: {}", path.display(), error))?;
+
+    serde_json::from_str(&content)
+        .map_err(|error| format!("File sao lưu {} không hợp lệ: {}", path.display(), error))
+}
+
+/// Số thành viên đã khôi phục, số thành viên vẫn còn trên phiếu và các thành viên chưa khôi phục được.
+#[derive(Debug, Default)]
+pub struct RestoreSummary {
+    pub restored: usize,
+    pub present: usize,
+    pub skipped: Vec<SkippedItem>,
+}
+
+/// Thêm lại các thành viên trong file sao lưu vào phiếu `ma_phieu`, bỏ qua thành viên (cùng họ tên, ngày sinh) vẫn còn trên phiếu.
+/// File sao lưu chỉ có các cột của danh sách thành viên, các trường khác lấy giá trị mặc định như khi ô trong bảng tính để trống.
+/// Lỗi được xử lý theo `options.on_error`; khi dừng hoặc bỏ qua cả hộ, các thành viên còn lại cũng được ghi là bị bỏ qua.
+pub fn restore_members(client: &PcgdClient, backup: &MemberBackup, ma_phieu: &str, options: &UploadOptions) -> RestoreSummary {
+    let mut summary = RestoreSummary::default();
+
+    let present: Vec<String> = match with_retries(options, || client.list_doituong(ma_phieu)) {
+        Ok(members) => members.iter().map(|row| resident_key(&row.text("ho_ten"), &row.text("ngay_sinh"))).collect(),
+        Err(error) => {
+            println!("{}", format!("> Không lấy được thành viên hiện có của phiếu {}: {}", ma_phieu, error).red().bold());
+            summary.skipped.extend(backup.members.iter().map(|row| SkippedItem {
+                so_phieu: backup.so_phieu.clone(),
+                ho_ten: Some(row.text("ho_ten")),
+                reason: error.to_string(),
+            }));
+            return summary;
+        },
+    };
+
+    for (index, row) in backup.members.iter().enumerate() {
+        if present.contains(&resident_key(&row.text("ho_ten"), &row.text("ngay_sinh"))) {
+            summary.present += 1;
+            println!("{}", format!("> \"{}\" vẫn còn trên phiếu {}, bỏ qua.", row.text("ho_ten"), ma_phieu).yellow().bold());
+            continue;
+        }
+
+        let mut resident = ResidentFields::default();
+        fill_from_portal(&mut resident, row);
+        fill_defaults(&mut resident);
+        resident.0.update_ma_phieu(ma_phieu.to_owned());
+
+        loop {
+            let error = match add_member(client, &resident.0, &resident.1, &resident.2, options) {
+                Ok(()) => {
+                    summary.restored += 1;
+                    println!("{}", format!("> Đã khôi phục \"{}\" vào phiếu {}", resident.0.ho_ten, ma_phieu).green().bold());
+                    break;
+                },
+                Err(error) => error,
+            };
+
+            println!("{}", format!("> Có lỗi khi khôi phục \"{}\": {}", resident.0.ho_ten, error).red().bold());
+
+            let decision = if matches!(error, PcgdError::SessionExpired) { Decision::Stop } else { decide(options, true) };
+            match decision {
+                Decision::Retry => continue,
+                Decision::SkipResident => {
+                    summary.skipped.push(SkippedItem { so_phieu: backup.so_phieu.clone(), ho_ten: Some(resident.0.ho_ten.clone()), reason: error.to_string() });
+                    break;
+                },
+                Decision::SkipHousehold | Decision::Stop => {
+                    summary.skipped.push(SkippedItem { so_phieu: backup.so_phieu.clone(), ho_ten: Some(resident.0.ho_ten.clone()), reason: error.to_string() });
+                    summary.skipped.extend(backup.members[index + 1..].iter().map(|row| SkippedItem {
+                        so_phieu: backup.so_phieu.clone(),
+                        ho_ten: Some(row.text("ho_ten")),
+                        reason: "chưa khôi phục vì công việc đã dừng".to_owned(),
+                    }));
+                    return summary;
+                },
+            }
+        }
+    }
+
+    summary
+}
+
+fn file_safe(value: &str) -> String {
+    value.chars()
+        .map(|character| if character.is_ascii_alphanumeric() || character == '-' { character } else { '_' })
+        .collect()
+}
@@ -0,0 +1,184 @@
+use calamine::{Data, DataType};
+
+/// Chuỗi văn bản tự do: bỏ khoảng trắng thừa, số nguyên không kèm phần thập phân,
+/// ô ngày tháng theo dạng dd/mm/yyyy.
+pub fn text(cell: &Data) -> String {
+    match cell {
+        Data::Empty => "".to_owned(),
+        Data::String(value) => value.trim().to_owned(),
+        Data::Int(value) => value.to_string(),
+        Data::Float(value) => format_float(*value),
+        Data::DateTime(_) | Data::DateTimeIso(_) => date(cell),
+        _ => cell.to_string().trim().to_owned(),
+    }
+}
+
+/// Mã số (số phiếu, mã trường, lớp, khối...): số luôn ở dạng nguyên.
+pub fn code(cell: &Data) -> String {
+    match cell {
+        Data::Float(value) if value.fract() != 0.0 => value.to_string(),
+        Data::Float(value) => format!("{}", *value as i64),
+        _ => text(cell),
+    }
+}
+
+/// Năm (năm sinh, năm tốt nghiệp...): ô ngày tháng lấy phần năm, số thực bỏ phần lẻ.
+pub fn year(cell: &Data) -> String {
+    match cell {
+        Data::Float(value) => format!("{}", value.trunc() as i64),
+        Data::DateTime(_) | Data::DateTimeIso(_) => date_parts(cell)
+            .map(|(year, _, _)| year)
+            .unwrap_or_else(|| text(cell)),
+        _ => text(cell),
+    }
+}
+
+/// Ngày tháng theo dạng dd/mm/yyyy, giữ nguyên chuỗi nếu không đọc được.
+pub fn date(cell: &Data) -> String {
+    match cell {
+        Data::DateTime(_) | Data::DateTimeIso(_) => date_parts(cell)
+            .map(|(year, month, day)| format!("{}/{}/{}", day, month, year))
+            .unwrap_or_else(|| cell.to_string()),
+        Data::String(value) => normalize_date_string(value.trim()).unwrap_or_else(|| value.trim().to_owned()),
+        _ => text(cell),
+    }
+}
+
+/// Ngày sinh từ ba cột ngày, tháng, năm. Nếu cột ngày chứa sẵn cả ngày tháng năm thì dùng luôn.
+pub fn birth_date(day: &Data, month: &Data, year_cell: &Data) -> String {
+    if matches!(day, Data::DateTime(_) | Data::DateTimeIso(_)) {
+        return date(day);
+    }
+
+    if let Data::String(value) = day {
+        if let Some(full_date) = normalize_date_string(value.trim()) {
+            return full_date;
+        }
+    }
+
+    format!("{}/{}/{}", two_digits(&code(day)), two_digits(&code(month)), year(year_cell))
+}
+
+/// Số điện thoại: giữ số 0 đầu bị Excel làm mất, bỏ dấu cách, chấm, gạch và đổi +84 về 0.
+pub fn phone(cell: &Data) -> String {
+    let raw = code(cell);
+
+    if raw.chars().any(|character| character.is_alphabetic() || ['/', ',', ';'].contains(&character)) {
+        return raw;
+    }
+
+    let digits: String = raw.chars().filter(|character| character.is_ascii_digit()).collect();
+
+    if digits.is_empty() {
+        raw
+    } else if raw.starts_with("+84") || (digits.starts_with("84") && digits.len() == 11) {
+        format!("0{}", &digits[2..])
+    } else if digits.len() == 9 && !digits.starts_with('0') {
+        format!("0{}", digits)
+    } else {
+        digits
+    }
+}
+
+/// Tách ô ngày tháng thành (năm, tháng, ngày) từ dạng yyyy-mm-dd.
+fn date_parts(cell: &Data) -> Option<(String, String, String)> {
+    let iso_date = cell.as_date()?.to_string();
+    let mut parts = iso_date.splitn(3, '-').map(|part| part.to_owned());
+
+    Some((parts.next()?, parts.next()?, parts.next()?))
+}
+
+fn format_float(value: f64) -> String {
+    if value.fract() == 0.0 && value.abs() < 1e15 {
+        format!("{}", value as i64)
+    } else {
+        value.to_string()
+    }
+}
+
+fn two_digits(value: &str) -> String {
+    if value.len() == 1 {
+        format!("0{}", value)
+    } else {
+        value.to_owned()
+    }
+}
+
+/// Chuẩn hoá chuỗi d/m/yyyy, d-m-yyyy hoặc d.m.yyyy về dd/mm/yyyy.
+fn normalize_date_string(value: &str) -> Option<String> {
+    let parts: Vec<&str> = value.split(['/', '-', '.']).collect();
+
+    if parts.len() != 3 || parts[2].len() != 4 || !parts.iter().all(|part| !part.is_empty() && part.chars().all(|character| character.is_ascii_digit())) {
+        return None;
+    }
+
+    Some(format!("{}/{}/{}", two_digits(parts[0]), two_digits(parts[1]), parts[2]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use calamine::{ExcelDateTime, ExcelDateTimeType};
+
+    fn excel_date(serial: f64) -> Data {
+        Data::DateTime(ExcelDateTime::new(serial, ExcelDateTimeType::DateTime, false))
+    }
+
+    #[test]
+    fn text_trims_strings_and_drops_integer_decimals() {
+        assert_eq!(text(&Data::String("  Nguyễn Văn ".to_owned())), "Nguyễn Văn");
+        assert_eq!(text(&Data::Float(2010.0)), "2010");
+        assert_eq!(text(&Data::Float(2010.5)), "2010.5");
+        assert_eq!(text(&Data::Int(7)), "7");
+        assert_eq!(text(&Data::Empty), "");
+    }
+
+    #[test]
+    fn code_keeps_numbers_whole() {
+        assert_eq!(code(&Data::Float(12.0)), "12");
+        assert_eq!(code(&Data::Int(79)), "79");
+        assert_eq!(code(&Data::String(" 01_001 ".to_owned())), "01_001");
+    }
+
+    #[test]
+    fn year_from_numbers_dates_and_strings() {
+        assert_eq!(year(&Data::Float(2010.0)), "2010");
+        assert_eq!(year(&Data::Float(2010.5)), "2010");
+        assert_eq!(year(&Data::Int(1998)), "1998");
+        assert_eq!(year(&excel_date(40179.0)), "2010");
+        assert_eq!(year(&Data::DateTimeIso("2015-09-05T00:00:00".to_owned())), "2015");
+        assert_eq!(year(&Data::String("2001".to_owned())), "2001");
+    }
+
+    #[test]
+    fn date_from_serials_iso_and_strings() {
+        assert_eq!(date(&excel_date(40179.0)), "01/01/2010");
+        assert_eq!(date(&Data::DateTimeIso("2015-09-05".to_owned())), "05/09/2015");
+        assert_eq!(date(&Data::String("5-9-2015".to_owned())), "05/09/2015");
+        assert_eq!(date(&Data::String("không rõ".to_owned())), "không rõ");
+    }
+
+    #[test]
+    fn birth_date_from_separate_columns() {
+        assert_eq!(birth_date(&Data::Float(5.0), &Data::Int(9), &Data::Float(2015.0)), "05/09/2015");
+        assert_eq!(birth_date(&Data::String("12".to_owned()), &Data::String("11".to_owned()), &Data::String("2001".to_owned())), "12/11/2001");
+    }
+
+    #[test]
+    fn birth_date_from_full_date_in_day_column() {
+        assert_eq!(birth_date(&excel_date(40179.0), &Data::Empty, &Data::Empty), "01/01/2010");
+        assert_eq!(birth_date(&Data::String("1/2/2003".to_owned()), &Data::Empty, &Data::Empty), "01/02/2003");
+    }
+
+    #[test]
+    fn phone_restores_leading_zero() {
+        assert_eq!(phone(&Data::Float(912345678.0)), "0912345678");
+        assert_eq!(phone(&Data::Int(912345678)), "0912345678");
+        assert_eq!(phone(&Data::String("0912 345 678".to_owned())), "0912345678");
+        assert_eq!(phone(&Data::String("0912.345.678".to_owned())), "0912345678");
+        assert_eq!(phone(&Data::String("+84 912 345 678".to_owned())), "0912345678");
+        assert_eq!(phone(&Data::Float(84912345678.0)), "0912345678");
+        assert_eq!(phone(&Data::String("0912345678 / 0987654321".to_owned())), "0912345678 / 0987654321");
+        assert_eq!(phone(&Data::Empty), "");
+    }
+}
@@ -0,0 +1,90 @@
+use std::{collections::HashMap, fs, path::{Path, PathBuf}};
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+pub const CHECKPOINT_FILE: &str = "checkpoint.json";
+
+/// Tiến độ tải lên của một hộ.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct HouseholdProgress {
+    pub ma_phieu: String,
+    /// Vị trí (trong hộ) của các thành viên đã thêm thành công.
+    pub residents_added: Vec<usize>,
+    pub completed: bool,
+}
+
+/// Điểm dừng của các lần tải lên, theo mã băm của file nguồn rồi theo số phiếu.
+pub struct Checkpoint {
+    path: PathBuf,
+    source_hash: String,
+    sources: HashMap<String, HashMap<String, HouseholdProgress>>,
+}
+
+impl Checkpoint {
+    /// Đọc file điểm dừng cho file nguồn (bảng tính hoặc nhật ký), tạo mới nếu chưa có.
+    pub fn load(path: &Path, source_file: &Path) -> Result<Self, String> {
+        let source = fs::read(source_file)
+            .map_err(|error| format!("Không đọc được {}: {}", source_file.display(), error))?;
+        let source_hash = format!("{:x}", Sha256::digest(&source));
+
+        let sources = if path.exists() {
+            let content = fs::read_to_string(path)
+                .map_err(|error| format!("Không đọc được {}: {}", path.display(), error))?;
+            serde_json::from_str(&content)
+                .map_err(|error| format!("File {} không hợp lệ: {}", path.display(), error))?
+        } else {
+            HashMap::new()
+        };
+
+        Ok(Checkpoint {
+            path: path.to_path_buf(),
+            source_hash,
+            sources,
+        })
+    }
+
+    pub fn household(&self, so_phieu: &str) -> Option<&HouseholdProgress> {
+        self.sources.get(&self.source_hash)?.get(so_phieu)
+    }
+
+    /// Số hộ đã có tiến độ được ghi lại cho file nguồn này.
+    pub fn recorded_households(&self) -> usize {
+        self.sources.get(&self.source_hash).map(|households| households.len()).unwrap_or(0)
+    }
+
+    pub fn set_ma_phieu(&mut self, so_phieu: &str, ma_phieu: &str) {
+        self.progress_mut(so_phieu).ma_phieu = ma_phieu.to_owned();
+        self.save();
+    }
+
+    pub fn add_resident(&mut self, so_phieu: &str, index: usize) {
+        self.progress_mut(so_phieu).residents_added.push(index);
+        self.save();
+    }
+
+    pub fn complete(&mut self, so_phieu: &str) {
+        self.progress_mut(so_phieu).completed = true;
+        self.save();
+    }
+
+    fn progress_mut(&mut self, so_phieu: &str) -> &mut HouseholdProgress {
+        self.sources.entry(self.source_hash.clone())
+            .or_default()
+            .entry(so_phieu.to_owned())
+            .or_default()
+    }
+
+    /// Ghi ra file tạm rồi đổi tên, để file điểm dừng không bị hỏng khi máy tắt giữa chừng.
+    fn save(&self) {
+        let temporary_path = self.path.with_extension("json.tmp");
+        let result = serde_json::to_string_pretty(&self.sources)
+            .map_err(|error| error.to_string())
+            .and_then(|content| fs::write(&temporary_path, content).map_err(|error| error.to_string()))
+            .and_then(|_| fs::rename(&temporary_path, &self.path).map_err(|error| error.to_string()));
+
+        if let Err(error) = result {
+            println!("{}", format!("> Không ghi được điểm dừng vào {}: {}", self.path.display(), error).yellow().bold());
+        }
+    }
+}
@@ -0,0 +1,282 @@
+use std::{io::{self, IsTerminal}, path::PathBuf};
+use clap::{Args, Parser, Subcommand};
+use colored::Colorize;
+use pcgd_bulk::admin_code::AdminCode;
+use pcgd_bulk::backup::{read_backup, restore_members};
+use pcgd_bulk::checkpoint::CHECKPOINT_FILE;
+use pcgd_bulk::column_mapping::ColumnMapping;
+use pcgd_bulk::export::export_households;
+use pcgd_bulk::journal::{CSRF_TOKEN_PLACEHOLDER, JOURNAL_FILE};
+use pcgd_bulk::merge::MemberPolicy;
+use pcgd_bulk::pcgd_client::PcgdClient;
+use pcgd_bulk::plan::PLAN_FILE;
+use pcgd_bulk::profile::{Profile, PROFILES_FILE};
+use pcgd_bulk::row_validation::InvalidRowPolicy;
+use pcgd_bulk::session::read_session_file;
+use pcgd_bulk::upload::{print_skipped, ErrorPolicy, UploadOptions};
+use pcgd_bulk::workbook::{journal_replayer, plan_applier, workbook_reader, RunMode, WorkbookJob};
+use crate::connect;
+
+/// Nhập liệu hàng loạt lên cổng PCGD. Chạy không có lệnh con để dùng giao diện hỏi đáp.
+#[derive(Parser)]
+#[command(version)]
+pub struct Cli {
+    /// Địa chỉ cổng PCGD (mặc định lấy từ biến môi trường PCGD_BASE_URL, rồi tới hồ sơ).
+    #[arg(long, global = true)]
+    pub base_url: Option<String>,
+    /// File cấu hình vị trí cột (mặc định theo hồ sơ, hoặc column_mapping.json).
+    #[arg(long, global = true)]
+    pub mapping: Option<PathBuf>,
+    /// Tên hồ sơ xã trong file hồ sơ.
+    #[arg(long, global = true)]
+    pub profile: Option<String>,
+    /// File hồ sơ các xã.
+    #[arg(long, global = true, default_value = PROFILES_FILE)]
+    pub profiles: PathBuf,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Tải các hộ trong bảng tính lên cổng.
+    Upload {
+        #[command(flatten)]
+        workbook: WorkbookArgs,
+        #[command(flatten)]
+        session: SessionArgs,
+        #[command(flatten)]
+        upload: UploadArgs,
+    },
+    /// Chạy thử: chỉ ghi các request ra file nhật ký.
+    DryRun {
+        #[command(flatten)]
+        workbook: WorkbookArgs,
+        /// File nhật ký (JSONL) được ghi ra.
+        #[arg(long, default_value = JOURNAL_FILE)]
+        journal: PathBuf,
+    },
+    /// Gửi file nhật ký đã duyệt lên cổng.
+    Replay {
+        /// File nhật ký (JSONL).
+        journal: PathBuf,
+        #[command(flatten)]
+        session: SessionArgs,
+        #[command(flatten)]
+        upload: UploadArgs,
+    },
+    /// Lập kế hoạch: so sánh bảng tính với các hộ trên cổng.
+    Plan {
+        #[command(flatten)]
+        workbook: WorkbookArgs,
+        #[command(flatten)]
+        session: SessionArgs,
+        /// File kế hoạch được ghi ra.
+        #[arg(long, default_value = PLAN_FILE)]
+        plan: PathBuf,
+        /// Cách xử lý thành viên của hộ đã có trên cổng.
+        #[arg(long, value_enum, default_value_t = MemberPolicy::default())]
+        members: MemberPolicy,
+    },
+    /// Thực hiện kế hoạch đã lập.
+    Apply {
+        #[command(flatten)]
+        session: SessionArgs,
+        /// File kế hoạch.
+        #[arg(long, default_value = PLAN_FILE)]
+        plan: PathBuf,
+        #[command(flatten)]
+        upload: UploadArgs,
+    },
+    /// Khôi phục thành viên từ file sao lưu.
+    Restore {
+        /// File sao lưu (JSON).
+        backup: PathBuf,
+        #[command(flatten)]
+        session: SessionArgs,
+        /// Mã phiếu nhận các thành viên (mặc định là mã phiếu trong file sao lưu).
+        #[arg(long)]
+        ma_phieu: Option<String>,
+        /// Cách xử lý khi thêm lại bị lỗi: ask, stop, skip-resident hoặc retry:N (mặc định theo hồ sơ, hoặc hỏi).
+        #[arg(long)]
+        on_error: Option<ErrorPolicy>,
+        /// Tự đồng ý mọi câu hỏi xác nhận.
+        #[arg(short, long)]
+        yes: bool,
+    },
+    /// Tải các hộ trên cổng về file bảng tính theo mẫu MauNhapLieu.
+    Export {
+        #[command(flatten)]
+        session: SessionArgs,
+        /// Đầu số phiếu của xã hoặc thôn cần xuất (VD: XX_YYYY_ZZZZZ_ hoặc XX_YYYY_ZZZZZ_N_), mặc định theo hồ sơ.
+        #[arg(long)]
+        prefix: Option<String>,
+        /// File XLSX được ghi ra.
+        #[arg(long, default_value = "MauNhapLieu.xlsx")]
+        output: PathBuf,
+    },
+}
+
+#[derive(Args)]
+pub struct WorkbookArgs {
+    /// File bảng tính (XLS, XLSX, XLSM, XLSB, ODS).
+    workbook: PathBuf,
+    /// Ngày điều tra (mặc định theo hồ sơ).
+    #[arg(long)]
+    date: Option<String>,
+    /// Phần đầu của mã số phiếu (VD: XX_YYYY_ZZZZZ_N_, hoặc XX_YYYY_ZZZZZ_ khi thôn lấy theo từng dòng), mặc định theo hồ sơ.
+    #[arg(long)]
+    prefix: Option<String>,
+    /// Cách xử lý dòng lỗi (mặc định theo hồ sơ, hoặc hỏi).
+    #[arg(long, value_enum)]
+    invalid_rows: Option<InvalidRowPolicy>,
+    /// Vẫn chạy khi cổng chưa có phiếu nào của thôn để đối chiếu mã (mặc định hỏi, hoặc dừng khi có --yes).
+    #[arg(long)]
+    allow_unverified_code: bool,
+}
+
+#[derive(Args)]
+pub struct SessionArgs {
+    /// File chứa lệnh cURL hoặc file HAR xuất từ DevTools.
+    #[arg(long)]
+    session: PathBuf,
+}
+
+#[derive(Args)]
+pub struct UploadArgs {
+    /// Cách xử lý thành viên của hộ đã có trên cổng.
+    #[arg(long, value_enum, default_value_t = MemberPolicy::default())]
+    members: MemberPolicy,
+    /// Cách xử lý khi gửi lên cổng bị lỗi: ask, stop, skip-household, skip-resident hoặc retry:N (mặc định theo hồ sơ, hoặc hỏi; dừng khi không chạy trong terminal).
+    #[arg(long)]
+    on_error: Option<ErrorPolicy>,
+    /// Tự đồng ý mọi câu hỏi xác nhận.
+    #[arg(short, long)]
+    yes: bool,
+    /// File điểm dừng để chạy tiếp khi bị ngắt.
+    #[arg(long, default_value = CHECKPOINT_FILE)]
+    checkpoint: PathBuf,
+}
+
+impl UploadArgs {
+    fn options(&self, profile: Option<&Profile>) -> UploadOptions {
+        UploadOptions {
+            assume_yes: self.yes,
+            member_policy: self.members,
+            on_error: error_policy(self.on_error, profile),
+            ..UploadOptions::default()
+        }
+    }
+}
+
+/// Cách xử lý lỗi theo tham số, rồi theo hồ sơ, mặc định là hỏi.
+/// Khi không có người ngồi trước bàn phím (stdin không phải terminal) thì mặc định là dừng để không bị treo ở câu hỏi.
+fn error_policy(from_args: Option<ErrorPolicy>, profile: Option<&Profile>) -> ErrorPolicy {
+    from_args
+        .or_else(|| profile.and_then(|profile| profile.on_error))
+        .unwrap_or_else(|| if io::stdin().is_terminal() { ErrorPolicy::default() } else { ErrorPolicy::Stop })
+}
+
+impl SessionArgs {
+    fn connect(&self, base_url: &str) -> Result<PcgdClient, String> {
+        let session = read_session_file(&self.session, base_url)?;
+        Ok(connect(base_url, &session))
+    }
+}
+
+/// Chạy một lệnh con, không hỏi lại những gì đã có trong tham số.
+pub fn run(command: Command, base_url: &str, mapping: &ColumnMapping, profile: Option<&Profile>) -> Result<(), String> {
+    match command {
+        Command::Upload { workbook, session, upload } => {
+            let client = session.connect(base_url)?;
+            let options = upload.options(profile);
+            run_workbook(workbook, mapping, profile, RunMode::Upload, upload.checkpoint, options, Some(&client))
+        },
+        Command::DryRun { workbook, journal } => {
+            let options = UploadOptions { assume_yes: true, ..UploadOptions::default() };
+            run_workbook(workbook, mapping, profile, RunMode::DryRun(journal), PathBuf::from(CHECKPOINT_FILE), options, None)
+        },
+        Command::Replay { journal, session, upload } => {
+            let client = session.connect(base_url)?;
+            journal_replayer(&journal, &client, &client.pcgd_csrf_token(), &upload.checkpoint, &upload.options(profile)).into_result()
+        },
+        Command::Plan { workbook, session, plan, members } => {
+            let client = session.connect(base_url)?;
+            let options = UploadOptions { assume_yes: true, member_policy: members, ..UploadOptions::default() };
+            run_workbook(workbook, mapping, profile, RunMode::Plan(plan), PathBuf::from(CHECKPOINT_FILE), options, Some(&client))
+        },
+        Command::Apply { session, plan, upload } => {
+            let client = session.connect(base_url)?;
+            plan_applier(&plan, &client, &upload.options(profile)).into_result()
+        },
+        Command::Restore { backup, session, ma_phieu, on_error, yes } => {
+            let backup_data = read_backup(&backup)?;
+            let ma_phieu = ma_phieu.unwrap_or_else(|| backup_data.ma_phieu.clone());
+            let client = session.connect(base_url)?;
+            let options = UploadOptions { assume_yes: yes, on_error: error_policy(on_error, profile), ..UploadOptions::default() };
+            let summary = restore_members(&client, &backup_data, &ma_phieu, &options);
+
+            println!("{}", format!("> Đã khôi phục {}/{} thành viên vào phiếu {}.", summary.restored, backup_data.members.len(), ma_phieu).green().bold());
+            if summary.present > 0 {
+                println!("{}", format!("> {} thành viên vẫn còn trên phiếu nên không thêm lại.", summary.present).yellow().bold());
+            }
+            print_skipped(&summary.skipped);
+
+            if !summary.skipped.is_empty() {
+                return Err(format!("Còn {} thành viên chưa được khôi phục.", summary.skipped.len()));
+            }
+            Ok(())
+        },
+        Command::Export { session, prefix, output } => {
+            let prefix = prefix
+                .or_else(|| profile.map(|profile| profile.admin_code.prefix()))
+                .ok_or("Cần có --prefix hoặc --profile để biết xã cần xuất.")?;
+            let client = session.connect(base_url)?;
+            let summary = export_households(&client, &prefix, mapping, &output)?;
+
+            println!("{}", format!("> Đã xuất {} hộ với {} thành viên ra {}.", summary.households, summary.residents, output.display()).green().bold());
+            Ok(())
+        },
+    }
+}
+
+fn run_workbook(
+    args: WorkbookArgs,
+    mapping: &ColumnMapping,
+    profile: Option<&Profile>,
+    mode: RunMode,
+    checkpoint_file: PathBuf,
+    options: UploadOptions,
+    client: Option<&PcgdClient>,
+) -> Result<(), String> {
+    let ngay_dieutra = args.date
+        .or_else(|| profile.and_then(|profile| profile.ngay_dieutra.clone()))
+        .ok_or("Cần có --date hoặc hồ sơ có ngày điều tra.")?;
+
+    let admin_code: AdminCode = match (args.prefix, profile) {
+        (Some(prefix), _) => prefix.parse()?,
+        (None, Some(profile)) => profile.admin_code.clone(),
+        (None, None) => return Err("Cần có --prefix hoặc --profile để biết mã xã, thôn của các hộ.".to_owned()),
+    };
+
+    let invalid_rows = args.invalid_rows
+        .or_else(|| profile.and_then(|profile| profile.invalid_rows))
+        .unwrap_or_default();
+
+    let job = WorkbookJob {
+        file: args.workbook,
+        mapping,
+        mode,
+        ngay_dieutra,
+        admin_code,
+        pcgd_csrf_token: client.map(PcgdClient::pcgd_csrf_token).unwrap_or_else(|| CSRF_TOKEN_PLACEHOLDER.to_owned()),
+        checkpoint_file,
+        options,
+        invalid_rows,
+        allow_unverified_code: args.allow_unverified_code,
+    };
+
+    workbook_reader(&job, client)
+        .map_err(|error| format!("Không đọc được file bảng tính: {}", error))?
+        .into_result()
+}
@@ -0,0 +1,433 @@
+use std::{collections::HashMap, fs, path::Path};
+use calamine::{Data, Range};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use unidecode::unidecode;
+
+pub const COLUMN_MAPPING_FILE: &str = "column_mapping.json";
+
+/// Số dòng đầu sheet được quét để tìm tiêu đề cột.
+const HEADER_SCAN_ROWS: usize = 10;
+
+/// Tiêu đề cột (đã bỏ dấu, viết thường) ứng với từng trường, ưu tiên theo thứ tự.
+/// Ô tiêu đề phải trùng khớp cả ô, các tiêu đề chung chung như "Ngày", "Lớp" không được dùng.
+const HEADER_ALIASES: [(&str, &[&str]); 32] = [
+    ("ho_dem", &["ho dem", "ho va dem", "ho va ten dem"]),
+    ("ten", &["ten"]),
+    ("ngay_sinh", &["ngay sinh"]),
+    ("thang_sinh", &["thang sinh"]),
+    ("nam_sinh", &["nam sinh"]),
+    ("gioi_tinh_nu", &["gioi tinh nu"]),
+    ("dan_toc", &["dan toc"]),
+    ("ton_giao", &["ton giao"]),
+    ("dien_uu_tien", &["dien uu tien", "uu tien"]),
+    ("dia_chi", &["dia chi"]),
+    ("so_phieu", &["so phieu", "ma so phieu"]),
+    ("dien_cu_tru", &["dien cu tru"]),
+    ("tinh_trang_cu_tru", &["tinh trang cu tru"]),
+    ("khoi", &["khoi", "do tuoi khoi"]),
+    ("lophoc", &["lop hoc"]),
+    ("ma_truong", &["ma truong"]),
+    ("cap_tn", &["cap tot nghiep", "tot nghiep cap", "cap tn"]),
+    ("hoc_bo_tuc", &["hoc bo tuc", "bo tuc"]),
+    ("tn_nam", &["nam tot nghiep", "nam tn"]),
+    ("bac_tn_nghe", &["bac tot nghiep nghe", "bac nghe", "tn nghe"]),
+    ("nam_tn_nghe", &["nam tot nghiep nghe", "nam tn nghe"]),
+    ("bohoc_lop", &["bo hoc lop", "lop bo hoc"]),
+    ("bohoc_nam", &["bo hoc nam", "nam bo hoc"]),
+    ("hoc_xmc_lop", &["hoc xmc lop", "lop xmc", "lop xoa mu chu"]),
+    ("congnhan_xmc", &["cong nhan xmc", "cong nhan xoa mu chu"]),
+    ("tai_mu_chu", &["tai mu chu"]),
+    ("hoan_canh_db", &["hoan canh dac biet", "hoan canh db"]),
+    ("chi_tiet_hoan_canh_db", &["chi tiet hoan canh dac biet", "chi tiet hoan canh"]),
+    ("qh_chu_ho", &["quan he voi chu ho", "quan he chu ho", "qh voi chu ho"]),
+    ("ho_ten_cha", &["ho ten cha me", "ho ten cha", "ho ten cha hoac me"]),
+    ("dien_thoai", &["dien thoai", "so dien thoai"]),
+    ("ghi_chu", &["ghi chu"]),
+];
+
+/// Tiêu đề của cột mã thôn (không bắt buộc), dùng khi một bảng tính có hộ của nhiều thôn.
+/// Không dùng tiêu đề "Thôn" vì cột đó thường là tên thôn chứ không phải mã.
+const THON_XOM_ALIASES: [&str; 3] = ["ma thon xom", "ma thon", "thon xom"];
+
+/// Tiêu đề nhóm các cột khuyết tật, các cột con nằm liền nhau bắt đầu từ cột này.
+const KHUYET_TAT_ALIASES: [&str; 2] = ["khuyet tat", "loai khuyet tat"];
+
+/// Vị trí cột (tính từ 0) của từng trường trong sheet MauNhapLieu.
+/// Các trường không có trong file cấu hình sẽ lấy theo mẫu mặc định.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ColumnMapping {
+    /// Tự dò vị trí cột theo tiêu đề (mặc định bật), đặt `false` để dùng các vị trí cố định bên dưới.
+    pub detect_headers: bool,
+    /// Số dòng tiêu đề khi không dò theo tiêu đề.
+    pub header_rows: usize,
+    /// Tiêu đề bổ sung cho từng trường, được thử trước tiêu đề có sẵn.
+    pub header_aliases: HashMap<String, Vec<String>>,
+    pub ho_dem: usize,
+    pub ten: usize,
+    pub ngay_sinh: usize,
+    pub thang_sinh: usize,
+    pub nam_sinh: usize,
+    pub gioi_tinh_nu: usize,
+    pub dan_toc: usize,
+    pub ton_giao: usize,
+    pub dien_uu_tien: usize,
+    pub dia_chi: usize,
+    pub so_phieu: usize,
+    pub dien_cu_tru: usize,
+    pub tinh_trang_cu_tru: usize,
+    pub khoi: usize,
+    pub lophoc: usize,
+    pub ma_truong: usize,
+    pub cap_tn: usize,
+    pub hoc_bo_tuc: usize,
+    pub tn_nam: usize,
+    pub bac_tn_nghe: usize,
+    pub nam_tn_nghe: usize,
+    pub bohoc_lop: usize,
+    pub bohoc_nam: usize,
+    pub hoc_xmc_lop: usize,
+    pub congnhan_xmc: usize,
+    pub tai_mu_chu: usize,
+    /// Các cột khuyết tật theo thứ tự mã 1, 2, 3...
+    pub khuyet_tat: Vec<usize>,
+    pub hoan_canh_db: usize,
+    pub chi_tiet_hoan_canh_db: usize,
+    pub qh_chu_ho: usize,
+    pub ho_ten_cha: usize,
+    pub dien_thoai: usize,
+    pub ghi_chu: usize,
+    /// Cột mã thôn của từng hộ (mã đầy đủ hoặc chỉ số thôn), không có thì lấy thôn theo số phiếu.
+    pub thon_xom: Option<usize>,
+}
+
+impl Default for ColumnMapping {
+    fn default() -> Self {
+        ColumnMapping {
+            detect_headers: true,
+            header_rows: 4,
+            header_aliases: HashMap::new(),
+            ho_dem: 2,
+            ten: 3,
+            ngay_sinh: 4,
+            thang_sinh: 5,
+            nam_sinh: 6,
+            gioi_tinh_nu: 7,
+            dan_toc: 8,
+            ton_giao: 9,
+            dien_uu_tien: 10,
+            dia_chi: 13,
+            so_phieu: 14,
+            dien_cu_tru: 15,
+            tinh_trang_cu_tru: 16,
+            khoi: 17,
+            lophoc: 18,
+            ma_truong: 21,
+            cap_tn: 22,
+            hoc_bo_tuc: 23,
+            tn_nam: 24,
+            bac_tn_nghe: 25,
+            nam_tn_nghe: 26,
+            bohoc_lop: 30,
+            bohoc_nam: 31,
+            hoc_xmc_lop: 32,
+            congnhan_xmc: 33,
+            tai_mu_chu: 34,
+            khuyet_tat: (33..44).collect(),
+            hoan_canh_db: 45,
+            chi_tiet_hoan_canh_db: 46,
+            qh_chu_ho: 47,
+            ho_ten_cha: 48,
+            dien_thoai: 49,
+            ghi_chu: 50,
+            thon_xom: None,
+        }
+    }
+}
+
+impl ColumnMapping {
+    /// Đọc cấu hình cột từ file JSON, trả về mẫu mặc định nếu file không tồn tại.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        if !path.exists() {
+            return Ok(ColumnMapping::default());
+        }
+
+        let content = fs::read_to_string(path)
+            .map_err(|error| format!("Không đọc được {}: {}", path.display(), error))?;
+
+        serde_json::from_str(&content)
+            .map_err(|error| format!("File {} không hợp lệ: {}", path.display(), error))
+    }
+
+    /// Danh sách (tên trường, vị trí cột) của mọi cột được đọc.
+    pub fn fields(&self) -> Vec<(String, usize)> {
+        let positions = serde_json::to_value(self).unwrap();
+
+        HEADER_ALIASES.iter()
+            .filter_map(|(field, _)| positions[*field].as_u64().map(|column| (field.to_string(), column as usize)))
+            .chain(self.khuyet_tat.iter().enumerate().map(|(ma_kt, column)| (format!("khuyet_tat_{}", ma_kt + 1), *column)))
+            .chain(self.thon_xom.map(|column| ("thon_xom".to_owned(), column)))
+            .collect()
+    }
+
+    /// Dò vị trí các cột theo tiêu đề trong sheet, trả về bảng cột và dòng bắt đầu dữ liệu.
+    pub fn detect(&self, range: &Range<Data>) -> Result<(ColumnMapping, usize), String> {
+        let header_cells: Vec<(usize, usize, String)> = range.rows()
+            .take(HEADER_SCAN_ROWS)
+            .enumerate()
+            .flat_map(|(row, cells)| {
+                cells.iter()
+                    .enumerate()
+                    .map(move |(column, cell)| (row, column, normalize_header(&cell.to_string())))
+            })
+            .filter(|(_, _, text)| !text.is_empty())
+            .collect();
+
+        let mut positions = serde_json::to_value(self).unwrap();
+        let mut missing: Vec<&str> = vec![];
+        let mut ambiguous: Vec<String> = vec![];
+        let mut last_header_row = 0;
+
+        for (field, builtin_aliases) in HEADER_ALIASES.iter() {
+            let custom_aliases: Vec<String> = self.header_aliases.get(*field)
+                .map(|aliases| aliases.iter().map(|alias| normalize_header(alias)).collect())
+                .unwrap_or_default();
+            let aliases = custom_aliases.iter()
+                .map(|alias| alias.as_str())
+                .chain(builtin_aliases.iter().copied());
+
+            let mut found = false;
+
+            for alias in aliases {
+                let matches: Vec<&(usize, usize, String)> = header_cells.iter()
+                    .filter(|(_, _, text)| text == alias)
+                    .collect();
+
+                if matches.len() == 1 {
+                    positions[*field] = Value::from(matches[0].1);
+                    last_header_row = last_header_row.max(matches[0].0);
+                    found = true;
+                    break;
+                }
+
+                if matches.len() > 1 {
+                    let columns: Vec<String> = matches.iter().map(|cell| column_letter(cell.1)).collect();
+                    ambiguous.push(format!("{} (\"{}\" ở các cột {})", field, alias, columns.join(", ")));
+                    found = true;
+                    break;
+                }
+            }
+
+            if !found {
+                missing.push(field);
+            }
+        }
+
+        let thon_xom_aliases: Vec<String> = self.header_aliases.get("thon_xom")
+            .map(|aliases| aliases.iter().map(|alias| normalize_header(alias)).collect())
+            .unwrap_or_default();
+        let thon_xom = thon_xom_aliases.iter()
+            .map(|alias| alias.as_str())
+            .chain(THON_XOM_ALIASES)
+            .map(|alias| header_cells.iter().filter(|(_, _, text)| text == alias).collect::<Vec<_>>())
+            .find(|matches| !matches.is_empty());
+
+        match thon_xom.as_deref() {
+            Some([(row, column, _)]) => {
+                positions["thon_xom"] = Value::from(*column);
+                last_header_row = last_header_row.max(*row);
+            },
+            Some(matches) => {
+                let columns: Vec<String> = matches.iter().map(|cell| column_letter(cell.1)).collect();
+                ambiguous.push(format!("thon_xom (\"{}\" ở các cột {})", matches[0].2, columns.join(", ")));
+            },
+            None => {},
+        }
+
+        let khuyet_tat: Vec<&(usize, usize, String)> = header_cells.iter()
+            .filter(|(_, _, text)| KHUYET_TAT_ALIASES.contains(&text.as_str()))
+            .collect();
+
+        match khuyet_tat.as_slice() {
+            [(row, column, _)] => {
+                positions["khuyet_tat"] = Value::from((*column..*column + self.khuyet_tat.len()).collect::<Vec<usize>>());
+                last_header_row = last_header_row.max(*row);
+            },
+            [] => missing.push("khuyet_tat"),
+            matches => {
+                let columns: Vec<String> = matches.iter().map(|cell| column_letter(cell.1)).collect();
+                ambiguous.push(format!("khuyet_tat (\"{}\" ở các cột {})", matches[0].2, columns.join(", ")));
+            },
+        }
+
+        if !missing.is_empty() || !ambiguous.is_empty() {
+            let mut report = vec![];
+            if !missing.is_empty() {
+                report.push(format!("Không tìm thấy tiêu đề cho: {}", missing.join(", ")));
+            }
+            if !ambiguous.is_empty() {
+                report.push(format!("Tiêu đề bị trùng: {}", ambiguous.join("; ")));
+            }
+            return Err(report.join("\n"));
+        }
+
+        let mapping: ColumnMapping = serde_json::from_value(positions).unwrap();
+
+        let data_start = range.rows()
+            .enumerate()
+            .skip(last_header_row + 1)
+            .find(|(_, cells)| !is_numbering_row(cells))
+            .map(|(row, _)| row)
+            .unwrap_or(range.height());
+
+        Ok((mapping, data_start))
+    }
+}
+
+/// Bỏ dấu, viết thường và gộp khoảng trắng để so khớp tiêu đề.
+fn normalize_header(text: &str) -> String {
+    unidecode(text)
+        .to_lowercase()
+        .split(|character: char| !character.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<&str>>()
+        .join(" ")
+}
+
+/// Dòng đánh số thứ tự cột (1, 2, 3...) ngay dưới tiêu đề của mẫu.
+fn is_numbering_row(cells: &[Data]) -> bool {
+    let filled: Vec<&Data> = cells.iter().filter(|cell| **cell != Data::Empty).collect();
+
+    !filled.is_empty() && filled.iter().all(|cell| {
+        matches!(cell, Data::Int(_) | Data::Float(_))
+            || normalize_header(&cell.to_string()).chars().all(|character| character.is_ascii_digit())
+    })
+}
+
+/// Đổi vị trí cột (tính từ 0) sang tên cột Excel: 0 -> A, 26 -> AA.
+pub fn column_letter(index: usize) -> String {
+    let mut index = index + 1;
+    let mut letters = vec![];
+
+    while index > 0 {
+        let remainder = (index - 1) % 26;
+        letters.insert(0, (b'A' + remainder as u8) as char);
+        index = (index - 1) / 26;
+    }
+
+    letters.into_iter().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TITLES: [&str; 32] = [
+        "Họ đệm", "Tên", "Ngày sinh", "Tháng sinh", "Năm sinh", "Giới tính nữ", "Dân tộc", "Tôn giáo",
+        "Diện ưu tiên", "Địa chỉ", "Số phiếu", "Diện cư trú", "Tình trạng cư trú", "Khối", "Lớp học",
+        "Mã trường", "Cấp tốt nghiệp", "Học bổ túc", "Năm tốt nghiệp", "Bậc tốt nghiệp nghề",
+        "Năm tốt nghiệp nghề", "Bỏ học lớp", "Bỏ học năm", "Học XMC lớp", "Công nhận XMC", "Tái mù chữ",
+        "Hoàn cảnh đặc biệt", "Chi tiết hoàn cảnh đặc biệt", "Quan hệ với chủ hộ", "Họ tên cha mẹ",
+        "Điện thoại", "Ghi chú",
+    ];
+
+    /// Sheet có dòng tiêu đề ở dòng 2 (sau một dòng tên mẫu), dòng đánh số và một dòng dữ liệu.
+    fn sheet(titles: &[&str]) -> Range<Data> {
+        let mut range = Range::new((0, 0), (4, titles.len() as u32 + 12));
+        range.set_value((0, 0), Data::String("MẪU NHẬP LIỆU".to_owned()));
+
+        for (column, title) in titles.iter().enumerate() {
+            range.set_value((1, column as u32 + 1), Data::String(title.to_string()));
+            range.set_value((2, column as u32 + 1), Data::Int(column as i64 + 1));
+        }
+
+        range.set_value((3, 1), Data::String("Nguyễn Văn".to_owned()));
+        range
+    }
+
+    #[test]
+    fn detect_finds_columns_and_data_start() {
+        let mut titles = TITLES.to_vec();
+        titles.push("Khuyết tật");
+
+        let (mapping, data_start) = ColumnMapping::default().detect(&sheet(&titles)).unwrap();
+
+        assert_eq!((mapping.ho_dem, mapping.ten, mapping.so_phieu, mapping.ghi_chu), (1, 2, 11, 32));
+        assert_eq!(mapping.khuyet_tat, (33..44).collect::<Vec<usize>>());
+        assert_eq!(mapping.thon_xom, None);
+        assert_eq!(data_start, 3);
+    }
+
+    #[test]
+    fn detect_reports_missing_titles() {
+        let titles: Vec<&str> = TITLES.iter().copied().filter(|title| *title != "Số phiếu").collect();
+
+        let error = ColumnMapping::default().detect(&sheet(&titles)).unwrap_err();
+
+        assert!(error.contains("Không tìm thấy tiêu đề cho: so_phieu"), "{}", error);
+    }
+
+    #[test]
+    fn detect_reports_ambiguous_titles() {
+        let mut titles = TITLES.to_vec();
+        titles.push("Tên");
+
+        let error = ColumnMapping::default().detect(&sheet(&titles)).unwrap_err();
+
+        assert!(error.contains("ten (\"ten\" ở các cột C, AH)"), "{}", error);
+    }
+
+    #[test]
+    fn detect_does_not_bind_generic_titles() {
+        let titles: Vec<&str> = TITLES.iter()
+            .map(|title| match *title {
+                "Ngày sinh" => "Ngày",
+                "Lớp học" => "Lớp",
+                other => other,
+            })
+            .collect();
+
+        let error = ColumnMapping::default().detect(&sheet(&titles)).unwrap_err();
+
+        assert!(error.contains("ngay_sinh"), "{}", error);
+        assert!(error.contains("lophoc"), "{}", error);
+    }
+
+    #[test]
+    fn detect_prefers_custom_aliases() {
+        let titles: Vec<&str> = TITLES.iter()
+            .map(|title| if *title == "Giới tính nữ" { "Nữ" } else { title })
+            .chain(["Khuyết tật"])
+            .collect();
+        let mut mapping = ColumnMapping::default();
+        mapping.header_aliases.insert("gioi_tinh_nu".to_owned(), vec!["Nữ".to_owned()]);
+
+        let (mapping, _) = mapping.detect(&sheet(&titles)).unwrap();
+
+        assert_eq!(mapping.gioi_tinh_nu, 6);
+    }
+
+    #[test]
+    fn detect_reports_missing_khuyet_tat_group() {
+        let error = ColumnMapping::default().detect(&sheet(&TITLES)).unwrap_err();
+
+        assert!(error.contains("Không tìm thấy tiêu đề cho: khuyet_tat"), "{}", error);
+    }
+
+    #[test]
+    fn detect_does_not_take_village_names_as_codes() {
+        let mut titles = TITLES.to_vec();
+        titles.push("Khuyết tật");
+        titles.push("Thôn");
+
+        let (mapping, _) = ColumnMapping::default().detect(&sheet(&titles)).unwrap();
+
+        assert_eq!(mapping.thon_xom, None);
+    }
+
+    #[test]
+    fn detect_is_on_by_default() {
+        assert!(ColumnMapping::default().detect_headers);
+    }
+}
@@ -0,0 +1,426 @@
+use regex::Regex;
+use serde_json::{Deserializer, Value};
+
+/// Một request copy từ tab Network của trình duyệt.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct CopiedRequest {
+    pub url: String,
+    pub method: String,
+    /// Các header theo thứ tự trong lệnh, giữ nguyên tên.
+    pub headers: Vec<(String, String)>,
+    /// Cookie lấy từ header Cookie, tham số -b của cURL hoặc cookie của WebSession PowerShell.
+    pub cookies: Vec<String>,
+    pub body: String,
+}
+
+/// Kiểu lệnh copy từ trình duyệt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CopyFormat {
+    /// "Copy as cURL (bash)".
+    Bash,
+    /// "Copy as cURL (cmd)", dùng ^ để thoát ký tự.
+    Cmd,
+    /// "Copy as PowerShell" (Invoke-WebRequest).
+    PowerShell,
+    /// "Copy as fetch" hoặc "Copy as fetch (Node.js)".
+    Fetch,
+}
+
+/// Tham số cURL có kèm giá trị nhưng không dùng tới.
+const IGNORED_CURL_OPTIONS: [&str; 10] = ["-A", "--user-agent", "-e", "--referer", "-u", "--user", "-o", "--output", "--connect-timeout", "-m"];
+
+/// Tham số cURL chứa dữ liệu gửi đi.
+const DATA_OPTIONS: [&str; 6] = ["-d", "--data", "--data-raw", "--data-binary", "--data-ascii", "--data-urlencode"];
+
+impl CopiedRequest {
+    /// Giá trị header đầu tiên có tên (không phân biệt hoa thường).
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Cookie gộp lại thành một header Cookie.
+    pub fn cookie_header(&self) -> String {
+        self.cookies.iter()
+            .map(|cookie| cookie.trim().trim_end_matches(';').trim())
+            .filter(|cookie| !cookie.is_empty())
+            .collect::<Vec<&str>>()
+            .join("; ")
+    }
+
+    /// Giá trị một trường trong body dạng form hoặc trong query của địa chỉ.
+    pub fn form_value(&self, field: &str) -> Option<String> {
+        let query = self.url.split_once('?').map(|(_, query)| query).unwrap_or("");
+
+        form_urlencoded::parse(self.body.as_bytes())
+            .chain(form_urlencoded::parse(query.as_bytes()))
+            .find(|(name, _)| name == field)
+            .map(|(_, value)| value.into_owned())
+    }
+}
+
+/// Nhận diện kiểu lệnh theo nội dung.
+pub fn detect_format(text: &str) -> CopyFormat {
+    let trimmed = text.trim_start();
+
+    if trimmed.starts_with("fetch(") || trimmed.starts_with("await fetch(") {
+        CopyFormat::Fetch
+    } else if trimmed.contains("Invoke-WebRequest") || trimmed.contains("Invoke-RestMethod") {
+        CopyFormat::PowerShell
+    } else if trimmed.contains("^\"") || trimmed.lines().any(|line| line.trim_end().ends_with(" ^")) {
+        CopyFormat::Cmd
+    } else {
+        CopyFormat::Bash
+    }
+}
+
+/// Đọc lệnh copy từ trình duyệt ở bất kỳ kiểu nào được hỗ trợ.
+pub fn parse_copied_request(text: &str) -> Result<CopiedRequest, String> {
+    if text.trim().is_empty() {
+        return Err("Lệnh cURL trống.".to_owned());
+    }
+
+    match detect_format(text) {
+        CopyFormat::Bash => parse_curl_words(bash_words(text)?),
+        CopyFormat::Cmd => parse_curl_words(cmd_words(text)?),
+        CopyFormat::PowerShell => parse_powershell(text),
+        CopyFormat::Fetch => parse_fetch(text),
+    }
+}
+
+/// Các tham số của lệnh cURL sau khi đã tách từ.
+fn parse_curl_words(words: Vec<String>) -> Result<CopiedRequest, String> {
+    let mut words = words.into_iter();
+
+    match words.next() {
+        Some(program) if program == "curl" || program.ends_with("curl.exe") || program.ends_with("/curl") => {},
+        Some(program) => return Err(format!("Lệnh phải bắt đầu bằng curl, nhận được \"{}\".", program)),
+        None => return Err("Lệnh cURL trống.".to_owned()),
+    }
+
+    let mut request = CopiedRequest::default();
+    let mut data: Vec<String> = vec![];
+
+    while let Some(word) = words.next() {
+        let option = word.as_str();
+        let mut value = |name: &str| words.next().ok_or_else(|| format!("Tham số {} thiếu giá trị.", name));
+
+        match option {
+            "-H" | "--header" => {
+                let header = value(option)?;
+                match header.split_once(':') {
+                    Some((name, header_value)) if name.trim().eq_ignore_ascii_case("cookie") => request.cookies.push(header_value.trim().to_owned()),
+                    Some((name, header_value)) => request.headers.push((name.trim().to_owned(), header_value.trim().to_owned())),
+                    None => return Err(format!("Header \"{}\" không đúng dạng Tên: giá trị.", header)),
+                }
+            },
+            "-b" | "--cookie" => {
+                let cookie = value(option)?;
+                if !cookie.contains('=') {
+                    return Err(format!("Tham số {} \"{}\" là file cookie, cần dán trực tiếp giá trị cookie.", option, cookie));
+                }
+                request.cookies.push(cookie);
+            },
+            "-X" | "--request" => request.method = value(option)?,
+            "--url" => request.url = value(option)?,
+            option if DATA_OPTIONS.contains(&option) => data.push(value(option)?),
+            option if IGNORED_CURL_OPTIONS.contains(&option) => { value(option)?; },
+            option if option.starts_with('-') => {},
+            url => request.url = url.to_owned(),
+        }
+    }
+
+    if request.url.is_empty() {
+        return Err("Không tìm thấy địa chỉ trong lệnh cURL.".to_owned());
+    }
+
+    request.body = data.join("&");
+    if request.method.is_empty() {
+        request.method = if request.body.is_empty() { "GET" } else { "POST" }.to_owned();
+    }
+
+    Ok(request)
+}
+
+/// Tách từ như bash: nháy đơn, nháy kép, $'...' và dấu \ nối dòng.
+fn bash_words(text: &str) -> Result<Vec<String>, String> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut words: Vec<String> = vec![];
+    let mut current = String::new();
+    let mut in_word = false;
+    let mut index = 0;
+
+    while index < chars.len() {
+        let character = chars[index];
+
+        match character {
+            '\\' => {
+                match chars.get(index + 1) {
+                    Some('\n') => {},
+                    Some('\r') if chars.get(index + 2) == Some(&'\n') => index += 1,
+                    Some(escaped) => {
+                        current.push(*escaped);
+                        in_word = true;
+                    },
+                    None => {},
+                }
+                index += 1;
+            },
+            '\'' => {
+                let end = find_char(&chars, index + 1, '\'').ok_or("Thiếu dấu ' đóng trong lệnh cURL.")?;
+                current.extend(&chars[index + 1..end]);
+                in_word = true;
+                index = end;
+            },
+            '$' if chars.get(index + 1) == Some(&'\'') => {
+                index = ansi_c_quoted(&chars, index + 2, &mut current)?;
+                in_word = true;
+            },
+            '"' => {
+                index += 1;
+                loop {
+                    match chars.get(index) {
+                        Some('"') => break,
+                        Some('\\') if matches!(chars.get(index + 1), Some('$' | '`' | '"' | '\\')) => {
+                            current.push(chars[index + 1]);
+                            index += 1;
+                        },
+                        Some('\\') if chars.get(index + 1) == Some(&'\n') => index += 1,
+                        Some(other) => current.push(*other),
+                        None => return Err("Thiếu dấu \" đóng trong lệnh cURL.".to_owned()),
+                    }
+                    index += 1;
+                }
+                in_word = true;
+            },
+            character if character.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut current));
+                    in_word = false;
+                }
+            },
+            character => {
+                current.push(character);
+                in_word = true;
+            },
+        }
+
+        index += 1;
+    }
+
+    if in_word {
+        words.push(current);
+    }
+
+    Ok(words)
+}
+
+/// Đọc chuỗi $'...' của bash từ vị trí sau dấu nháy mở, trả về vị trí dấu nháy đóng.
+fn ansi_c_quoted(chars: &[char], mut index: usize, current: &mut String) -> Result<usize, String> {
+    loop {
+        match chars.get(index) {
+            Some('\'') => return Ok(index),
+            Some('\\') => {
+                index += 1;
+                match chars.get(index) {
+                    Some('n') => current.push('\n'),
+                    Some('r') => current.push('\r'),
+                    Some('t') => current.push('\t'),
+                    Some(hex @ ('x' | 'u')) => {
+                        let length = if *hex == 'x' { 2 } else { 4 };
+                        let digits: String = chars.iter().skip(index + 1).take(length).collect();
+                        let code = u32::from_str_radix(&digits, 16).ok().and_then(char::from_u32)
+                            .ok_or_else(|| format!("Mã thoát \\{}{} không hợp lệ trong lệnh cURL.", hex, digits))?;
+                        current.push(code);
+                        index += length;
+                    },
+                    Some(other) => current.push(*other),
+                    None => return Err("Thiếu dấu ' đóng trong lệnh cURL.".to_owned()),
+                }
+            },
+            Some(other) => current.push(*other),
+            None => return Err("Thiếu dấu ' đóng trong lệnh cURL.".to_owned()),
+        }
+        index += 1;
+    }
+}
+
+/// Tách từ như cmd.exe rồi như bộ đọc tham số của Windows: bỏ dấu ^, nháy kép và \" theo quy tắc của MS CRT.
+fn cmd_words(text: &str) -> Result<Vec<String>, String> {
+    let mut unescaped = String::new();
+    let mut chars = text.chars().peekable();
+
+    while let Some(character) = chars.next() {
+        match character {
+            '^' => match chars.next() {
+                Some('\r') => { chars.next_if_eq(&'\n'); },
+                Some('\n') | None => {},
+                Some(escaped) => unescaped.push(escaped),
+            },
+            character => unescaped.push(character),
+        }
+    }
+
+    let chars: Vec<char> = unescaped.chars().collect();
+    let mut words: Vec<String> = vec![];
+    let mut current = String::new();
+    let mut in_word = false;
+    let mut in_quotes = false;
+    let mut index = 0;
+
+    while index < chars.len() {
+        match chars[index] {
+            '\\' => {
+                let backslashes = chars[index..].iter().take_while(|character| **character == '\\').count();
+                index += backslashes;
+
+                if chars.get(index) == Some(&'"') {
+                    current.push_str(&"\\".repeat(backslashes / 2));
+                    if backslashes % 2 == 1 {
+                        current.push('"');
+                        index += 1;
+                    }
+                } else {
+                    current.push_str(&"\\".repeat(backslashes));
+                }
+                in_word = true;
+                continue;
+            },
+            '"' => {
+                in_quotes = !in_quotes;
+                in_word = true;
+            },
+            character if character.is_whitespace() && !in_quotes => {
+                if in_word {
+                    words.push(std::mem::take(&mut current));
+                    in_word = false;
+                }
+            },
+            character => {
+                current.push(character);
+                in_word = true;
+            },
+        }
+        index += 1;
+    }
+
+    if in_quotes {
+        return Err("Thiếu dấu \" đóng trong lệnh cURL (cmd).".to_owned());
+    }
+    if in_word {
+        words.push(current);
+    }
+
+    Ok(words)
+}
+
+/// Đọc lệnh Invoke-WebRequest của "Copy as PowerShell".
+fn parse_powershell(text: &str) -> Result<CopiedRequest, String> {
+    let string = r#"("(?:[^"`]|`.)*"|'(?:[^']|'')*')"#;
+    let parameter = |name: &str| Regex::new(&format!(r"(?is)-{}\s+{}", name, string)).unwrap()
+        .captures(text)
+        .map(|captures| powershell_string(&captures[1]));
+
+    let mut request = CopiedRequest {
+        url: parameter("Uri").ok_or("Không tìm thấy -Uri trong lệnh PowerShell.")?,
+        method: parameter("Method").unwrap_or_else(|| "GET".to_owned()),
+        body: parameter("Body").unwrap_or_default(),
+        ..CopiedRequest::default()
+    };
+
+    if let Some(content_type) = parameter("ContentType") {
+        request.headers.push(("Content-Type".to_owned(), content_type));
+    }
+
+    let cookie = Regex::new(&format!(r"(?is)New-Object\s+System\.Net\.Cookie\(\s*{}\s*,\s*{}", string, string)).unwrap();
+    for captures in cookie.captures_iter(text) {
+        request.cookies.push(format!("{}={}", powershell_string(&captures[1]), powershell_string(&captures[2])));
+    }
+
+    if let Some(headers) = Regex::new(r"(?s)-Headers\s+@\{(.*?)\}").unwrap().captures(text) {
+        let pair = Regex::new(&format!(r"(?s){}\s*=\s*{}", string, string)).unwrap();
+        for captures in pair.captures_iter(&headers[1]) {
+            let (name, value) = (powershell_string(&captures[1]), powershell_string(&captures[2]));
+            if name.eq_ignore_ascii_case("cookie") {
+                request.cookies.push(value);
+            } else {
+                request.headers.push((name, value));
+            }
+        }
+    }
+
+    Ok(request)
+}
+
+/// Bỏ dấu nháy và ký tự thoát ` của chuỗi PowerShell.
+fn powershell_string(quoted: &str) -> String {
+    let inner = &quoted[1..quoted.len() - 1];
+
+    if quoted.starts_with('\'') {
+        return inner.replace("''", "'");
+    }
+
+    let mut unescaped = String::new();
+    let mut chars = inner.chars();
+    while let Some(character) = chars.next() {
+        match character {
+            '`' => match chars.next() {
+                Some('n') => unescaped.push('\n'),
+                Some('r') => unescaped.push('\r'),
+                Some('t') => unescaped.push('\t'),
+                Some(escaped) => unescaped.push(escaped),
+                None => {},
+            },
+            character => unescaped.push(character),
+        }
+    }
+    unescaped
+}
+
+/// Đọc lệnh fetch("địa chỉ", { ... }) của "Copy as fetch", phần tham số là JSON.
+fn parse_fetch(text: &str) -> Result<CopiedRequest, String> {
+    let start = text.find("fetch(").ok_or("Không tìm thấy fetch( trong lệnh.")? + "fetch(".len();
+    let mut values = Deserializer::from_str(&text[start..]).into_iter::<Value>();
+
+    let url = match values.next() {
+        Some(Ok(Value::String(url))) => url,
+        _ => return Err("Tham số đầu của fetch phải là địa chỉ trong dấu nháy kép.".to_owned()),
+    };
+
+    let rest = text[start..][values.byte_offset()..].trim_start();
+    let options = match rest.strip_prefix(',') {
+        Some(rest) => Deserializer::from_str(rest).into_iter::<Value>().next()
+            .ok_or("Thiếu tham số thứ hai của fetch.")?
+            .map_err(|error| format!("Tham số thứ hai của fetch không đúng JSON: {}", error))?,
+        None => Value::Null,
+    };
+
+    let mut request = CopiedRequest {
+        url,
+        method: options["method"].as_str().unwrap_or("GET").to_owned(),
+        body: options["body"].as_str().unwrap_or_default().to_owned(),
+        ..CopiedRequest::default()
+    };
+
+    if let Some(headers) = options["headers"].as_object() {
+        for (name, value) in headers {
+            let value = value.as_str().unwrap_or_default().to_owned();
+            if name.eq_ignore_ascii_case("cookie") {
+                request.cookies.push(value);
+            } else {
+                request.headers.push((name.clone(), value));
+            }
+        }
+    }
+
+    if request.cookies.is_empty() {
+        return Err("Lệnh \"Copy as fetch\" không chứa cookie. Hãy dùng \"Copy as fetch (Node.js)\" hoặc \"Copy as cURL\".".to_owned());
+    }
+
+    Ok(request)
+}
+
+fn find_char(chars: &[char], from: usize, target: char) -> Option<usize> {
+    chars.iter().skip(from).position(|character| *character == target).map(|position| from + position)
+}
@@ -0,0 +1,267 @@
+use std::path::Path;
+use colored::Colorize;
+use rust_xlsxwriter::{Workbook, XlsxError};
+use serde_json::Value;
+use crate::admin_code::AdminCode;
+use crate::column_mapping::ColumnMapping;
+use crate::merge::{normalize_birth_date, resident_key};
+use crate::pcgd_client::{GridRow, PcgdClient};
+
+/// Tiêu đề từng cột khi xuất file; vị trí cột lấy theo cấu hình (mẫu MauNhapLieu).
+const TITLES: [(&str, &str); 32] = [
+    ("ho_dem", "Họ đệm"),
+    ("ten", "Tên"),
+    ("ngay_sinh", "Ngày sinh"),
+    ("thang_sinh", "Tháng sinh"),
+    ("nam_sinh", "Năm sinh"),
+    ("gioi_tinh_nu", "Giới tính nữ"),
+    ("dan_toc", "Dân tộc"),
+    ("ton_giao", "Tôn giáo"),
+    ("dien_uu_tien", "Diện ưu tiên"),
+    ("dia_chi", "Địa chỉ"),
+    ("so_phieu", "Số phiếu"),
+    ("dien_cu_tru", "Diện cư trú"),
+    ("tinh_trang_cu_tru", "Tình trạng cư trú"),
+    ("khoi", "Khối"),
+    ("lophoc", "Lớp học"),
+    ("ma_truong", "Mã trường"),
+    ("cap_tn", "Cấp tốt nghiệp"),
+    ("hoc_bo_tuc", "Học bổ túc"),
+    ("tn_nam", "Năm tốt nghiệp"),
+    ("bac_tn_nghe", "Bậc tốt nghiệp nghề"),
+    ("nam_tn_nghe", "Năm tốt nghiệp nghề"),
+    ("bohoc_lop", "Bỏ học lớp"),
+    ("bohoc_nam", "Bỏ học năm"),
+    ("hoc_xmc_lop", "Học XMC lớp"),
+    ("congnhan_xmc", "Công nhận XMC"),
+    ("tai_mu_chu", "Tái mù chữ"),
+    ("hoan_canh_db", "Hoàn cảnh đặc biệt"),
+    ("chi_tiet_hoan_canh_db", "Chi tiết hoàn cảnh đặc biệt"),
+    ("qh_chu_ho", "Quan hệ với chủ hộ"),
+    ("ho_ten_cha", "Họ tên cha mẹ"),
+    ("dien_thoai", "Điện thoại"),
+    ("ghi_chu", "Ghi chú"),
+];
+
+/// Các trường của phiếu (chủ hộ) được ghi vào mọi dòng của hộ.
+const HOUSEHOLD_FIELDS: [&str; 4] = ["so_phieu", "dia_chi", "dien_cu_tru", "tinh_trang_cu_tru"];
+
+/// Trường được nhập bằng dấu X trong bảng tính: (trường trong bảng tính, trường trên cổng, giá trị trên cổng).
+const X_MARKS: [(&str, &str, &str); 2] = [("gioi_tinh_nu", "gioi_tinh", "2"), ("hoc_bo_tuc", "hoc_bo_tuc", "1")];
+
+/// Trường học vấn mà bảng tính để trống còn cổng lưu là "0".
+const ZERO_FIELDS: [&str; 5] = ["bac_tn_nghe", "bohoc_lop", "hoc_xmc_lop", "congnhan_xmc", "tai_mu_chu"];
+
+/// Trường trong bảng tính có tên khác trên cổng.
+const PORTAL_FIELDS: [(&str, &str); 2] = [("dan_toc", "ma_dantoc"), ("lophoc", "lophoc_2024")];
+
+/// Hoàn cảnh đặc biệt theo mã trên cổng.
+const HOAN_CANH_DB: [(&str, &str); 3] = [("1", "Chuyển đến"), ("2", "Chuyển đi"), ("3", "Chết")];
+
+pub struct ExportSummary {
+    pub households: usize,
+    pub residents: usize,
+}
+
+enum Cell {
+    Text(String),
+    Number(f64),
+}
+
+/// Xuất các hộ của một xã (đầu số phiếu XX_YYYY_ZZZZZ_) hoặc một thôn (XX_YYYY_ZZZZZ_N_) trên cổng
+/// ra file XLSX theo mẫu MauNhapLieu, để sửa rồi tải lên lại.
+pub fn export_households(client: &PcgdClient, preflix_so_phieu: &str, mapping: &ColumnMapping, file: &Path) -> Result<ExportSummary, String> {
+    let admin_code: AdminCode = preflix_so_phieu.parse()?;
+    let ma_thonxom = admin_code.ma_thonxom();
+
+    println!("{} Đang tải danh sách phiếu...", ">".green().bold());
+
+    let phieu: Vec<GridRow> = client.list_phieu(&admin_code.ma_tinh(), &admin_code.ma_quanhuyen(), &admin_code.ma_phuongxa())
+        .map_err(|error| error.to_string())?
+        .into_iter()
+        .filter(|row| ma_thonxom.as_ref().map(|ma_thonxom| row.in_village(ma_thonxom)).unwrap_or(true))
+        .collect();
+
+    let without_village: Vec<String> = phieu.iter()
+        .filter(|row| ma_thonxom.is_some() && row.text("ma_thonxom").is_empty())
+        .map(|row| row.text("so_phieu"))
+        .collect();
+    if !without_village.is_empty() {
+        println!("{} {} phiếu trên cổng không có mã thôn, được xuất kèm như khi tải lên và lập kế hoạch: {}", ">".yellow().bold(), without_village.len(), without_village.join(", "));
+    }
+
+    let mut rows: Vec<Vec<Cell>> = vec![];
+
+    for (index, row) in phieu.iter().enumerate() {
+        println!("{} [{}/{}] Đang tải thành viên của hộ {}...", ">".green().bold(), index + 1, phieu.len(), row.text("so_phieu"));
+
+        let members = client.list_doituong(&row.id).map_err(|error| error.to_string())?;
+        rows.extend(household_rows(row, members, mapping));
+    }
+
+    let summary = ExportSummary { households: phieu.len(), residents: rows.len() };
+    println!("{} Danh sách thành viên trên cổng chỉ có các cột hiển thị trong danh sách, các cột khác của thành viên được để trống.", ">".yellow().bold());
+
+    write_sheet(file, mapping, rows)
+        .map_err(|error| format!("Không ghi được file {}: {}", file.display(), error))?;
+
+    Ok(summary)
+}
+
+/// Các dòng của một hộ: chủ hộ trước, sau đó là các thành viên còn lại theo thứ tự trên cổng.
+fn household_rows(phieu: &GridRow, mut members: Vec<GridRow>, layout: &ColumnMapping) -> Vec<Vec<Cell>> {
+    let owner_key = resident_key(&format!("{} {}", phieu.text("chuho_hodem"), phieu.text("chuho_ten")), "");
+    let owner_position = members.iter()
+        .position(|member| member.text("qh_chu_ho").to_lowercase() == "chủ hộ")
+        .or_else(|| members.iter().position(|member| resident_key(&member.text("ho_ten"), "") == owner_key));
+
+    let owner = match owner_position {
+        Some(position) => members.remove(position),
+        None => GridRow {
+            id: String::new(),
+            fields: [
+                ("ho_ten".to_owned(), Value::from(format!("{} {}", phieu.text("chuho_hodem"), phieu.text("chuho_ten")))),
+                ("dien_thoai".to_owned(), Value::from(phieu.text("dien_thoai"))),
+            ].into_iter().collect(),
+        },
+    };
+
+    let mut owner_row = member_row(phieu, &owner, layout);
+    owner_row[layout.qh_chu_ho] = Cell::Text("Chủ hộ".to_owned());
+    if owner.text("ghi_chu").is_empty() {
+        owner_row[layout.ghi_chu] = Cell::Text(phieu.text("ghi_chu"));
+    }
+    if owner.text("dien_thoai").is_empty() {
+        owner_row[layout.dien_thoai] = Cell::Text(phieu.text("dien_thoai"));
+    }
+
+    std::iter::once(owner_row)
+        .chain(members.iter().map(|member| member_row(phieu, member, layout)))
+        .collect()
+}
+
+fn member_row(phieu: &GridRow, member: &GridRow, layout: &ColumnMapping) -> Vec<Cell> {
+    let width = layout.fields().iter().map(|(_, column)| column + 1).max().unwrap_or(0);
+    let mut row: Vec<Cell> = (0..width).map(|_| Cell::Text(String::new())).collect();
+    let positions = serde_json::to_value(layout).unwrap();
+    let column = |field: &str| positions[field].as_u64().unwrap() as usize;
+
+    for (field, _) in TITLES.iter() {
+        let portal_field = PORTAL_FIELDS.iter()
+            .find(|(sheet_field, _)| sheet_field == field)
+            .map(|(_, portal_field)| *portal_field)
+            .unwrap_or(*field);
+
+        let value = if HOUSEHOLD_FIELDS.contains(field) {
+            phieu.text(field)
+        } else {
+            member.text(portal_field)
+        };
+
+        let value = if ZERO_FIELDS.contains(field) && value == "0" { String::new() } else { value };
+        row[column(field)] = Cell::Text(value);
+    }
+
+    let ho_ten = member.text("ho_ten");
+    let (ho_dem, ten) = ho_ten.rsplit_once(' ').unwrap_or(("", &ho_ten));
+    row[layout.ho_dem] = Cell::Text(ho_dem.trim().to_owned());
+    row[layout.ten] = Cell::Text(ten.to_owned());
+
+    let ngay_sinh = normalize_birth_date(&member.text("ngay_sinh"));
+    let parts: Vec<f64> = ngay_sinh.split('/').filter_map(|part| part.parse().ok()).collect();
+    if let [day, month, year] = parts.as_slice() {
+        row[layout.ngay_sinh] = Cell::Number(*day);
+        row[layout.thang_sinh] = Cell::Number(*month);
+        row[layout.nam_sinh] = Cell::Number(*year);
+    } else {
+        row[layout.ngay_sinh] = Cell::Text(ngay_sinh);
+    }
+
+    for (field, portal_field, marked) in X_MARKS.iter() {
+        row[column(field)] = Cell::Text(if member.text(portal_field) == *marked { "X" } else { "" }.to_owned());
+    }
+
+    row[layout.khoi] = Cell::Text(khoi_text(&member.text("khoi")));
+
+    let hoan_canh_db = member.text("hoan_canh_db");
+    row[layout.hoan_canh_db] = Cell::Text(HOAN_CANH_DB.iter()
+        .find(|(code, _)| *code == hoan_canh_db)
+        .map(|(_, text)| text.to_string())
+        .unwrap_or_default());
+
+    let khuyet_tat_benh = member.text("khuyet_tat_benh");
+    for ma_kt in khuyet_tat_benh.split(',').filter_map(|ma_kt| ma_kt.trim().parse::<usize>().ok()) {
+        if let Some(kt) = ma_kt.checked_sub(1).and_then(|index| layout.khuyet_tat.get(index)) {
+            row[*kt] = Cell::Text("X".to_owned());
+        }
+    }
+
+    row
+}
+
+/// Khối trên cổng ("t5", "k1") về dạng nhập trong bảng tính ("5 tuổi", "1").
+fn khoi_text(khoi: &str) -> String {
+    match (khoi.chars().next(), khoi.get(1..)) {
+        (Some('t'), Some(age)) if !age.is_empty() => format!("{} tuổi", age),
+        (Some('k'), Some(grade)) if !grade.is_empty() => grade.to_owned(),
+        _ => khoi.to_owned(),
+    }
+}
+
+/// Ghi sheet MauNhapLieu: các trường đúng vị trí cột của mẫu, dưới phần tiêu đề nhiều dòng như mẫu
+/// (tên mẫu và nhóm khuyết tật, tiêu đề cột, mã khuyết tật, số thứ tự cột), dữ liệu bắt đầu sau `header_rows` dòng.
+/// Cấu hình có ít hơn 4 dòng tiêu đề thì chỉ ghi dòng tiêu đề cột và dòng số thứ tự cột.
+fn write_sheet(file: &Path, layout: &ColumnMapping, rows: Vec<Vec<Cell>>) -> Result<(), XlsxError> {
+    let mut workbook = Workbook::new();
+    let sheet = workbook.add_worksheet();
+    sheet.set_name("MauNhapLieu")?;
+
+    let header_rows = layout.header_rows.max(1) as u32;
+    let titles_row = header_rows.saturating_sub(3).min(header_rows - 1);
+    let fields = layout.fields();
+    let width = fields.iter().map(|(_, column)| column + 1).max().unwrap_or(0);
+    let stt = !fields.iter().any(|(_, column)| *column == 0);
+
+    if header_rows >= 4 {
+        sheet.write_string(header_rows - 4, 0, "MẪU NHẬP LIỆU")?;
+        if let Some(first) = layout.khuyet_tat.first() {
+            sheet.write_string(header_rows - 4, *first as u16, "Khuyết tật")?;
+        }
+        for (ma_kt, kt) in layout.khuyet_tat.iter().enumerate() {
+            sheet.write_number(header_rows - 2, *kt as u16, (ma_kt + 1) as f64)?;
+        }
+    }
+
+    if stt {
+        sheet.write_string(titles_row, 0, "STT")?;
+    }
+
+    let positions = serde_json::to_value(layout).unwrap();
+    for (field, title) in TITLES.iter() {
+        sheet.write_string(titles_row, positions[*field].as_u64().unwrap() as u16, *title)?;
+    }
+
+    if header_rows >= 2 {
+        for column in 0..width {
+            sheet.write_number(header_rows - 1, column as u16, (column + 1) as f64)?;
+        }
+    }
+
+    for (index, row) in rows.iter().enumerate() {
+        let sheet_row = header_rows + index as u32;
+
+        if stt {
+            sheet.write_number(sheet_row, 0, (index + 1) as f64)?;
+        }
+
+        for (column, cell) in row.iter().enumerate() {
+            match cell {
+                Cell::Text(text) if text.is_empty() => {},
+                Cell::Text(text) => { sheet.write_string(sheet_row, column as u16, text)?; },
+                Cell::Number(number) => { sheet.write_number(sheet_row, column as u16, *number)?; },
+            }
+        }
+    }
+
+    workbook.save(file)
+}
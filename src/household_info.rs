@@ -1,9 +1,12 @@
 use unidecode::unidecode;
 use base64::prelude::*;
 use calamine::Data;
+use crate::admin_code::AdminCode;
+use crate::cell_value;
+use crate::column_mapping::ColumnMapping;
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ValueFieldHouseOwner {
     pub so_phieu: String,
     pub chuho_hodem: String,
@@ -23,22 +26,22 @@ pub struct ValueFieldHouseOwner {
 }
 
 impl ValueFieldHouseOwner {
-    pub fn new(col: &[Data], ngay_dieutra: String, ma_tinh: String, ma_quanhuyen: String, ma_phuongxa: String, ma_thonxom: String, pcgd_csrf_token: String) -> Self {
+    pub fn new(col: &[Data], mapping: &ColumnMapping, ngay_dieutra: String, admin_code: &AdminCode, pcgd_csrf_token: String) -> Self {
         ValueFieldHouseOwner {
-            so_phieu: col[14].to_string(),
-            chuho_hodem: col[2].to_string(),
-            chuho_ten: col[3].to_string(),
-            dia_chi: col[13].to_string(),
-            tinh_trang_cu_tru: col[16].to_string(),
-            dien_thoai: col[49].to_string(),
+            so_phieu: cell_value::code(&col[mapping.so_phieu]),
+            chuho_hodem: cell_value::text(&col[mapping.ho_dem]),
+            chuho_ten: cell_value::text(&col[mapping.ten]),
+            dia_chi: cell_value::text(&col[mapping.dia_chi]),
+            tinh_trang_cu_tru: cell_value::text(&col[mapping.tinh_trang_cu_tru]),
+            dien_thoai: cell_value::phone(&col[mapping.dien_thoai]),
             ngay_dieutra,
-            ma_tinh,
-            ma_quanhuyen,
-            ma_phuongxa,
-            ma_thonxom,
-            dien_cu_tru: col[15].to_string(),
+            ma_tinh: admin_code.ma_tinh(),
+            ma_quanhuyen: admin_code.ma_quanhuyen(),
+            ma_phuongxa: admin_code.ma_phuongxa(),
+            ma_thonxom: admin_code.ma_thonxom().unwrap_or_default(),
+            dien_cu_tru: cell_value::text(&col[mapping.dien_cu_tru]),
             ma_phieu: "".to_owned(),
-            ghi_chu: col[50].to_string(),
+            ghi_chu: cell_value::text(&col[mapping.ghi_chu]),
             pcgd_csrf_token
         }
     }
@@ -64,7 +67,7 @@ impl ValueFieldHouseOwner {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
 pub struct ValueFieldHouseResident {
     pub ho_ten: String,
     pub ngay_sinh: String,
@@ -84,8 +87,8 @@ pub struct ValueFieldHouseResident {
 }
 
 impl ValueFieldHouseResident {
-    pub fn new(col: &[Data]) -> Self {
-        let raw_hoan_canh_db = col[45].to_string().to_lowercase();
+    pub fn new(col: &[Data], mapping: &ColumnMapping) -> Self {
+        let raw_hoan_canh_db = cell_value::text(&col[mapping.hoan_canh_db]).to_lowercase();
         let hoan_canh_db = if raw_hoan_canh_db == "chuyển đến" {
             "1".to_owned()
         } else if raw_hoan_canh_db == "chuyển đi" {
@@ -96,43 +99,32 @@ impl ValueFieldHouseResident {
             "".to_owned()
         };
 
-        let gioi_tinh = if col[7].to_string().to_lowercase() == "x" {
+        let gioi_tinh = if cell_value::text(&col[mapping.gioi_tinh_nu]).to_lowercase() == "x" {
             "2".to_owned()
         } else {
             "1".to_owned()
         };
 
         let mut khuyet_tat: Vec<String> = Vec::new();
-        for kt in 33..44 {
-            if col[kt].to_string().to_lowercase() == "x" {
-                khuyet_tat.push((kt - 32).to_string());
+        for (ma_kt, kt) in mapping.khuyet_tat.iter().enumerate() {
+            if cell_value::text(&col[*kt]).to_lowercase() == "x" {
+                khuyet_tat.push((ma_kt + 1).to_string());
             }
         }
 
-        let mut ngay = col[4].to_string();
-        let mut thang = col[5].to_string();
-        let nam = col[6].to_string();
-        if ngay.len() == 1 {
-            ngay.insert(0, '0');
-        }
-        if thang.len() == 1 {
-            thang.insert(0, '0');
-        }
-
-
         ValueFieldHouseResident {
-            ho_ten: format!("{} {}", col[2].to_string(), col[3].to_string()),
-            ngay_sinh: format!("{}/{}/{}", ngay, thang, nam),
+            ho_ten: format!("{} {}", cell_value::text(&col[mapping.ho_dem]), cell_value::text(&col[mapping.ten])).trim().to_owned(),
+            ngay_sinh: cell_value::birth_date(&col[mapping.ngay_sinh], &col[mapping.thang_sinh], &col[mapping.nam_sinh]),
             hoan_canh_db,
-            chi_tiet_hoan_canh_db: col[46].to_string(),
-            qh_chu_ho: col[47].to_string(),
-            ho_ten_cha: col[48].to_string(),
-            dien_uu_tien: col[10].to_string(),
-            dien_thoai: col[49].to_string(),
-            ghi_chu: col[50].to_string(),
+            chi_tiet_hoan_canh_db: cell_value::text(&col[mapping.chi_tiet_hoan_canh_db]),
+            qh_chu_ho: cell_value::text(&col[mapping.qh_chu_ho]),
+            ho_ten_cha: cell_value::text(&col[mapping.ho_ten_cha]),
+            dien_uu_tien: cell_value::code(&col[mapping.dien_uu_tien]),
+            dien_thoai: cell_value::phone(&col[mapping.dien_thoai]),
+            ghi_chu: cell_value::text(&col[mapping.ghi_chu]),
             gioi_tinh,
-            ma_dantoc: unidecode(&col[8].to_string().to_uppercase().replace(" ", "_").replace("-", "_")),
-            ton_giao: unidecode(&col[9].to_string().to_uppercase().replace(" ", "_").replace("-", "_")),
+            ma_dantoc: unidecode(&cell_value::text(&col[mapping.dan_toc]).to_uppercase().replace(" ", "_").replace("-", "_")),
+            ton_giao: unidecode(&cell_value::text(&col[mapping.ton_giao]).to_uppercase().replace(" ", "_").replace("-", "_")),
             ma_phieu: None,
             ma_dot: "".to_string(),
             khuyet_tat_benh: khuyet_tat.join(","),
@@ -164,7 +156,15 @@ impl ValueFieldHouseResident {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+fn or_zero(value: String) -> String {
+    if value.is_empty() {
+        "0".to_owned()
+    } else {
+        value
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
 pub struct ValueFieldHouseResidentGeneralEducation {
     pub tn_nam: String,
     pub so_bang_tn: String,
@@ -183,20 +183,20 @@ pub struct ValueFieldHouseResidentGeneralEducation {
 }
 
 impl ValueFieldHouseResidentGeneralEducation {
-    pub fn new(col: &[Data]) -> Self {
+    pub fn new(col: &[Data], mapping: &ColumnMapping) -> Self {
         ValueFieldHouseResidentGeneralEducation {
-            tn_nam: col[24].to_string(),
+            tn_nam: cell_value::year(&col[mapping.tn_nam]),
             so_bang_tn: "".to_owned(),
-            nam_tn_nghe: col[26].to_string(),
+            nam_tn_nghe: cell_value::year(&col[mapping.nam_tn_nghe]),
             nam_hx: "".to_owned(),
-            bohoc_nam: col[31].to_string(),
-            cap_tn: col[22].to_string(),
-            bac_tn_nghe: if col[25] != "" {col[25].to_string()} else {"0".to_owned()},
+            bohoc_nam: cell_value::year(&col[mapping.bohoc_nam]),
+            cap_tn: cell_value::code(&col[mapping.cap_tn]),
+            bac_tn_nghe: or_zero(cell_value::code(&col[mapping.bac_tn_nghe])),
             hoc_xong: " ".to_owned(),
-            bohoc_lop: if col[30] != "" {col[30].to_string()} else {"0".to_owned()},
-            tai_mu_chu: if col[34] != "" {col[34].to_string()} else {"0".to_owned()},
-            hoc_xmc_lop: if col[32] != "" {col[32].to_string()} else {"0".to_owned()},
-            congnhan_xmc: if col[33] != "" {col[33].to_string()} else {"0".to_owned()},
+            bohoc_lop: or_zero(cell_value::code(&col[mapping.bohoc_lop])),
+            tai_mu_chu: or_zero(cell_value::code(&col[mapping.tai_mu_chu])),
+            hoc_xmc_lop: or_zero(cell_value::code(&col[mapping.hoc_xmc_lop])),
+            congnhan_xmc: or_zero(cell_value::code(&col[mapping.congnhan_xmc])),
             bo_tuc: "".to_owned(),
             tnc2_loaitruong: "".to_owned(),
         }
@@ -222,7 +222,7 @@ impl ValueFieldHouseResidentGeneralEducation {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
 pub struct ValueFieldHouseResident2024Education {
     pub lophoc_2024: String,
     pub ma_tinh: String,
@@ -238,14 +238,14 @@ pub struct ValueFieldHouseResident2024Education {
 }
 
 impl ValueFieldHouseResident2024Education {
-    pub fn new(col: &[Data], ma_tinh: String, ma_quanhuyen: String) -> Self {
-        let hoc_bo_tuc = if col[23].to_string().to_lowercase() == "x" {
+    pub fn new(col: &[Data], mapping: &ColumnMapping, admin_code: &AdminCode) -> Self {
+        let hoc_bo_tuc = if cell_value::text(&col[mapping.hoc_bo_tuc]).to_lowercase() == "x" {
             "1".to_owned()
         } else {
             "".to_owned()
         };
 
-        let raw_khoi = col[17].to_string();
+        let raw_khoi = cell_value::code(&col[mapping.khoi]);
         let khoi = if raw_khoi.ends_with("tuổi") {
             format!("t{}", raw_khoi.chars().next().unwrap())
         } else if raw_khoi.len() == 1 || raw_khoi.len() == 2 {
@@ -255,11 +255,11 @@ impl ValueFieldHouseResident2024Education {
         };
 
         ValueFieldHouseResident2024Education {
-            lophoc_2024: col[18].to_string(),
-            ma_tinh,
-            ma_quanhuyen,
+            lophoc_2024: cell_value::code(&col[mapping.lophoc]),
+            ma_tinh: admin_code.ma_tinh(),
+            ma_quanhuyen: admin_code.ma_quanhuyen(),
             khoi,
-            ma_truong: col[21].to_string(),
+            ma_truong: cell_value::code(&col[mapping.ma_truong]),
             ma_hoctap_2024: "".to_string(),
             nam_hoc_re: "2024".to_string(),
             cb_view_mamnon_2024: "".to_string(),
@@ -285,3 +285,64 @@ impl ValueFieldHouseResident2024Education {
         }
     }
 }
+
+/// Dữ liệu của một thành viên: thông tin chung, học vấn phổ thông và học tập năm 2024.
+pub type ResidentFields = (ValueFieldHouseResident, ValueFieldHouseResidentGeneralEducation, ValueFieldHouseResident2024Education);
+
+/// Điền các trường còn trống bằng giá trị mà các hàm `new` đặt khi ô trong bảng tính để trống,
+/// dùng cho thành viên dựng lại từ các cột trên cổng (ví dụ khi khôi phục từ file sao lưu).
+pub fn fill_defaults(resident: &mut ResidentFields) {
+    let (info, education, education_2024) = resident;
+
+    if info.gioi_tinh.is_empty() {
+        info.gioi_tinh = "1".to_owned();
+    }
+
+    for field in [&mut education.bac_tn_nghe, &mut education.bohoc_lop, &mut education.tai_mu_chu, &mut education.hoc_xmc_lop, &mut education.congnhan_xmc] {
+        *field = or_zero(std::mem::take(field));
+    }
+
+    if education.hoc_xong.is_empty() {
+        education.hoc_xong = " ".to_owned();
+    }
+
+    if education_2024.nam_hoc_re.is_empty() {
+        education_2024.nam_hoc_re = "2024".to_owned();
+    }
+}
+
+/// Một hộ gia đình gồm chủ hộ và các thành viên (kể cả chủ hộ).
+#[derive(Debug)]
+pub struct Household {
+    pub so_phieu: String,
+    pub owner: ValueFieldHouseOwner,
+    pub residents: Vec<ResidentFields>,
+}
+
+/// Tham số form gửi tới phieudieutra/update.
+pub fn owner_form(owner: &mut ValueFieldHouseOwner, pcgd_csrf_token: &str) -> Vec<(String, String)> {
+    vec![
+        ("data".to_owned(), serde_json::to_string(&owner.build()).unwrap()),
+        ("pcgd-csrf-token".to_owned(), pcgd_csrf_token.to_owned()),
+    ]
+}
+
+/// Tham số form gửi tới doituong/add.
+pub fn resident_form(
+    resident: &ValueFieldHouseResident,
+    education: &ValueFieldHouseResidentGeneralEducation,
+    education_2024: &ValueFieldHouseResident2024Education,
+    pcgd_csrf_token: &str
+) -> Vec<(String, String)> {
+    let data1_json = serde_json::to_string(&resident.build()).unwrap();
+    let data2_json = serde_json::to_string(&education.build()).unwrap();
+    let data_dtht_json = serde_json::to_string(&education_2024.build()).unwrap();
+
+    vec![
+        ("data1".to_owned(), data1_json),
+        ("data2".to_owned(), data2_json),
+        ("data3".to_owned(), format!("{{\"pcgd-csrf-token\" : \"{}\"}}", pcgd_csrf_token)),
+        ("data_dtht".to_owned(), format!("{{\"2024\": \"{}\"}}", BASE64_STANDARD.encode(data_dtht_json))),
+        ("pcgd-csrf-token".to_owned(), pcgd_csrf_token.to_owned()),
+    ]
+}
@@ -1,12 +1,12 @@
 use reqwest::{header, blocking::Client};
 
-pub fn create_client_with_headers_preset(cookies: &String) -> Client {
+pub fn create_client_with_headers_preset(cookies: &str, origin: &str) -> Client {
     let mut headers = header::HeaderMap::new();
     headers.insert("Accept", header::HeaderValue::from_static("application/json, text/javascript, */*"));
     headers.insert("Connection", header::HeaderValue::from_static("keep-alive"));
     headers.insert("Accept-Language", header::HeaderValue::from_static("vi-VN,vi;q=0.9,fr-FR;q=0.8,fr;q=0.7,en-US;q=0.6,en;q=0.5"));
     headers.insert("Cookie", header::HeaderValue::from_str(cookies).unwrap());
-    headers.insert("Origin", header::HeaderValue::from_static("https://pcgd.moet.gov.vn"));
+    headers.insert("Origin", header::HeaderValue::from_str(origin).unwrap());
     headers.insert("Sec-Fetch-Dest", header::HeaderValue::from_static("empty"));
     headers.insert("Sec-Fetch-Mode", header::HeaderValue::from_static("cors"));
     headers.insert("Sec-Fetch-Site", header::HeaderValue::from_static("same-origin"));
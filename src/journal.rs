@@ -0,0 +1,157 @@
+use std::{fs::{self, File}, io::{BufWriter, Write}, path::Path};
+use serde::{Deserialize, Serialize};
+use crate::household_info::{
+    owner_form,
+    resident_form,
+    Household,
+    ValueFieldHouseOwner,
+    ValueFieldHouseResident,
+    ValueFieldHouseResident2024Education,
+    ValueFieldHouseResidentGeneralEducation
+};
+
+pub const JOURNAL_FILE: &str = "requests.jsonl";
+
+/// Giá trị giữ chỗ cho mã phiếu, chỉ biết được sau khi tạo hộ trên cổng PCGD.
+pub const MA_PHIEU_PLACEHOLDER: &str = "{{ma_phieu}}";
+
+/// Giá trị giữ chỗ cho CSRF token, được thay bằng token của phiên khi gửi đi.
+pub const CSRF_TOKEN_PLACEHOLDER: &str = "{{pcgd-csrf-token}}";
+
+/// Dữ liệu thành viên dạng dễ đọc, tương ứng data1, data2 và data_dtht.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ResidentPayload {
+    pub data1: ValueFieldHouseResident,
+    pub data2: ValueFieldHouseResidentGeneralEducation,
+    pub data_dtht: ValueFieldHouseResident2024Education,
+}
+
+/// Một request trong file nhật ký: `form` là dữ liệu gửi đi đúng như trên đường truyền,
+/// `decoded` là cùng dữ liệu đó trước khi mã hoá base64 để người duyệt đọc.
+/// Khi gửi lại, request được dựng lại từ `decoded`; muốn sửa thì sửa `decoded` và xoá `form`.
+/// Dòng nào còn `form` mà không khớp với `decoded` sẽ bị từ chối để không mất chỉnh sửa nào.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "endpoint")]
+pub enum JournalEntry {
+    #[serde(rename = "phieudieutra/update")]
+    HouseOwner {
+        so_phieu: String,
+        #[serde(default)]
+        form: Vec<(String, String)>,
+        decoded: Box<ValueFieldHouseOwner>,
+    },
+    #[serde(rename = "doituong/add")]
+    HouseResident {
+        so_phieu: String,
+        #[serde(default)]
+        form: Vec<(String, String)>,
+        decoded: Box<ResidentPayload>,
+    },
+}
+
+impl JournalEntry {
+    pub fn house_owner(mut owner: ValueFieldHouseOwner) -> Self {
+        JournalEntry::HouseOwner {
+            so_phieu: owner.so_phieu.clone(),
+            form: owner_form(&mut owner, CSRF_TOKEN_PLACEHOLDER),
+            decoded: Box::new(owner),
+        }
+    }
+
+    pub fn house_resident(
+        so_phieu: String,
+        mut resident: ValueFieldHouseResident,
+        education: ValueFieldHouseResidentGeneralEducation,
+        education_2024: ValueFieldHouseResident2024Education
+    ) -> Self {
+        resident.update_ma_phieu(MA_PHIEU_PLACEHOLDER.to_owned());
+
+        JournalEntry::HouseResident {
+            so_phieu,
+            form: resident_form(&resident, &education, &education_2024, CSRF_TOKEN_PLACEHOLDER),
+            decoded: Box::new(ResidentPayload {
+                data1: resident,
+                data2: education,
+                data_dtht: education_2024,
+            }),
+        }
+    }
+}
+
+/// Ghi các request vào file nhật ký, mỗi dòng một request.
+pub fn write_journal(path: &Path, entries: &[JournalEntry]) -> std::io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+
+    for entry in entries {
+        serde_json::to_writer(&mut writer, entry)?;
+        writer.write_all(b"\n")?;
+    }
+
+    writer.flush()
+}
+
+/// Đọc file nhật ký và gom các request lại theo từng hộ, giữ nguyên thứ tự trong file.
+/// CSRF token của phiên hiện tại được điền vào chỗ giữ chỗ, mã phiếu được lấy khi gửi đi.
+pub fn read_journal(path: &Path, pcgd_csrf_token: &str) -> Result<Vec<Household>, String> {
+    let content = fs::read_to_string(path)
+        .map_err(|error| format!("Không đọc được {}: {}", path.display(), error))?;
+
+    let mut households: Vec<Household> = vec![];
+
+    for (line_number, line) in content.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let entry: JournalEntry = serde_json::from_str(line)
+            .map_err(|error| format!("Dòng {} của {} không hợp lệ: {}", line_number + 1, path.display(), error))?;
+
+        match entry {
+            JournalEntry::HouseOwner { so_phieu, form, mut decoded } => {
+                check_form(&form, &owner_form(&mut decoded, CSRF_TOKEN_PLACEHOLDER), line_number + 1)?;
+
+                if households.iter().any(|household| household.so_phieu == so_phieu) {
+                    return Err(format!("Dòng {}: phiếu {} xuất hiện nhiều lần.", line_number + 1, so_phieu));
+                }
+
+                decoded.pcgd_csrf_token = pcgd_csrf_token.to_owned();
+                households.push(Household {
+                    so_phieu,
+                    owner: *decoded,
+                    residents: vec![],
+                });
+            },
+            JournalEntry::HouseResident { so_phieu, form, decoded } => {
+                check_form(&form, &resident_form(&decoded.data1, &decoded.data2, &decoded.data_dtht, CSRF_TOKEN_PLACEHOLDER), line_number + 1)?;
+
+                let household = households.iter_mut()
+                    .find(|household| household.so_phieu == so_phieu)
+                    .ok_or_else(|| format!("Dòng {}: thành viên thuộc phiếu {} nằm trước dòng chủ hộ.", line_number + 1, so_phieu))?;
+
+                household.residents.push((decoded.data1, decoded.data2, decoded.data_dtht));
+            },
+        }
+    }
+
+    Ok(households)
+}
+
+/// So `form` trong nhật ký với request dựng lại từ `decoded`; `form` bị xoá thì bỏ qua.
+fn check_form(form: &[(String, String)], rebuilt: &[(String, String)], line_number: usize) -> Result<(), String> {
+    if form.is_empty() || form == rebuilt {
+        return Ok(());
+    }
+
+    let mut keys: Vec<&str> = rebuilt.iter()
+        .filter(|pair| !form.contains(pair))
+        .chain(form.iter().filter(|pair| !rebuilt.contains(pair)))
+        .map(|(key, _)| key.as_str())
+        .collect();
+    keys.dedup();
+
+    Err(format!(
+        "Dòng {}: form không khớp với decoded ({}). Hãy sửa ở decoded và xoá form để request được dựng lại.",
+        line_number,
+        keys.join(", ")
+    ))
+}
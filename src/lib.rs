@@ -0,0 +1,19 @@
+pub mod admin_code;
+pub mod backup;
+pub mod cell_value;
+pub mod checkpoint;
+pub mod column_mapping;
+pub mod curl;
+pub mod export;
+pub mod household_info;
+pub mod http_client;
+pub mod journal;
+pub mod merge;
+pub mod pcgd_client;
+pub mod plan;
+pub mod profile;
+pub mod prompt;
+pub mod row_validation;
+pub mod session;
+pub mod upload;
+pub mod workbook;
@@ -1,349 +1,454 @@
-mod household_info;
-mod http_client;
-
-use base64::prelude::*;
-use household_info::{ValueFieldHouseOwner, ValueFieldHouseResident, ValueFieldHouseResident2024Education, ValueFieldHouseResidentGeneralEducation};
-use serde_json::Value;
-use std::{collections::HashMap, fs, path::PathBuf};
-use calamine::{open_workbook, Error, Reader, Xls};
+mod cli;
+
+use std::{env, io::{self, IsTerminal}, path::{Path, PathBuf}, process::ExitCode};
+use clap::Parser;
+use cli::Cli;
 use colored::Colorize;
-use inquire::{Confirm, Text};
-use regex::Regex;
+use inquire::{Select, Text};
+use pcgd_bulk::admin_code::{portal_villages, AdminCode};
+use pcgd_bulk::backup::{read_backup, restore_members, BACKUP_DIR};
+use pcgd_bulk::checkpoint::CHECKPOINT_FILE;
+use pcgd_bulk::column_mapping::{ColumnMapping, COLUMN_MAPPING_FILE};
+use pcgd_bulk::export::export_households;
+use pcgd_bulk::journal::{CSRF_TOKEN_PLACEHOLDER, JOURNAL_FILE};
+use pcgd_bulk::merge::MemberPolicy;
+use pcgd_bulk::pcgd_client::{validate_base_url, PcgdClient, BASE_URL_ENV, DEFAULT_BASE_URL};
+use pcgd_bulk::plan::PLAN_FILE;
+use pcgd_bulk::profile::{find_profile, load_profiles, Profile};
+use pcgd_bulk::session::{prompt_curl_session, read_session_file, Session};
+use pcgd_bulk::upload::{print_skipped, UploadOptions};
+use pcgd_bulk::workbook::{journal_replayer, plan_applier, workbook_reader, RunMode, RunOutcome, WorkbookJob, SUPPORTED_EXTENSIONS};
 use rfd::FileDialog;
 
-fn workbook_reader(file: &PathBuf, ngay_dieutra: &String, preflix_so_phieu: &String, pcgd_csrf_token: &String, cookies: &String) -> Result<(), Error> {
-    let mut workbook: Xls<_> = open_workbook(file)?;
-    let http_client = http_client::create_client_with_headers_preset(cookies);
-
-    let preflix_so_phieu = preflix_so_phieu.split("_").collect::<Vec<&str>>();
-    let ma_tinh = preflix_so_phieu[0];
-    let ma_quanhuyen = format!("{}_{}", ma_tinh, preflix_so_phieu[1]);
-    let ma_phuongxa = format!("{}_{}", ma_quanhuyen, preflix_so_phieu[2]);
-    let ma_thonxom = format!("{}_{}", ma_phuongxa, preflix_so_phieu[3]);
-
-    let mut so_chu_ho = 0;
-    let mut so_thanh_vien = 0;
-    let mut so_chu_ho_uploaded = 0;
-    let mut so_thanh_vien_uploaded = 0;
-
-    if let Ok(range) = workbook.worksheet_range("MauNhapLieu") {
-        let mut houses_owners: HashMap<String, Vec<ValueFieldHouseOwner>> = HashMap::new();
-        let mut houses_residents: HashMap<
-            String,
-            Vec<(ValueFieldHouseResident, ValueFieldHouseResidentGeneralEducation, ValueFieldHouseResident2024Education)>
-        > = HashMap::new();
-
-        let rows = range.rows();
-        let rows_data = rows.skip(4);
-
-        println!("{} Đang thiết lập mẫu dữ liệu...", ">".green().bold());
-
-        for col in rows_data {
-            if col[47].to_string().to_lowercase() == "chủ hộ" {
-                let household_owner = ValueFieldHouseOwner::new(
-                    col,
-                    ngay_dieutra.to_string(),
-                    ma_tinh.to_string(),
-                    ma_quanhuyen.to_string(),
-                    ma_phuongxa.to_string(),
-                    ma_thonxom.to_string(),
-                    pcgd_csrf_token.to_string()
-                );
-
-                houses_owners.entry(col[14].to_string())
-                    .or_insert_with(Vec::new)
-                    .push(household_owner);
-
-                so_chu_ho += 1;
-            }
-            
-            let household_resident = ValueFieldHouseResident::new(col);
-            let resident_education = ValueFieldHouseResidentGeneralEducation::new(col);
-            let resident_2024_education = ValueFieldHouseResident2024Education::new(
-                col,
-                ma_tinh.to_string(),
-                ma_quanhuyen.to_string()
-            );
-
-            houses_residents.entry(col[14].to_string())
-            .or_insert_with(Vec::new)
-            .push((
-                household_resident,
-                resident_education,
-                resident_2024_education
-            ));
-
-            so_thanh_vien += 1;
-        }
-
-        println!("{} Đã dựng được {} chủ hộ và {} thành viên.", ">".green().bold(), so_chu_ho, so_thanh_vien);
-
-        let mut mismatches: Vec<&String> = vec![];
-
-        for token in houses_residents.keys() {
-            if !houses_owners.contains_key(token) {
-                mismatches.push(token);
-            }
-        }
+/// Chọn file chứa lệnh cURL hoặc file HAR xuất từ DevTools và lấy ra CSRF token cùng cookie của phiên đăng nhập.
+fn read_session(base_url: &str) -> Option<Session> {
+    println!("{} Chọn file có chứa lệnh cURL hoặc file HAR", ">".green().bold());
 
-        if mismatches.len() > 0 {
-            println!("{} Đã phát hiện {} phiếu sau đây không có chủ hộ:", ">".green().bold(), mismatches.len());
-            for mismatch in mismatches.iter().enumerate() {
-                println!("{}. {}", mismatch.0 + 1, mismatch.1);
-            }
-        }
+    let session_file = match FileDialog::new()
+    .set_directory("/")
+    .pick_file() {
+        Some(file) => file,
+        None => {
+            println!("{}", "> Không nhận được file!".red().bold());
+            return None;
+        },
+    };
 
-        match Confirm::new("Tiếp tục công việc?").with_default(false).prompt() {
-            Ok(true) => println!("{} Đang thêm...", ">".green().bold()),
-            Ok(false) => {
-                println!("{}", "> Đã dừng công việc.".red().bold());
-                return Ok(());
-            },
-            Err(_) => {
-                println!("{}", "> Đã dừng công việc.".red().bold());
-                return Ok(());
+    match read_session_file(&session_file, base_url) {
+        Ok(session) => Some(session),
+        Err(error) => {
+            println!("{}", format!("> {}", error).red().bold());
+            None
+        },
+    }
+}
+
+/// Client của phiên đăng nhập. Khi có người ngồi ở terminal thì hỏi dán lệnh cURL mới nếu phiên hết hạn giữa chừng.
+fn connect(base_url: &str, session: &Session) -> PcgdClient {
+    let client = PcgdClient::new(base_url, &session.cookies, &session.pcgd_csrf_token);
+
+    if io::stdin().is_terminal() {
+        client.with_session_renewer(Box::new(prompt_curl_session))
+    } else {
+        client
+    }
+}
+
+/// Nhập tay phần đầu của mã số phiếu.
+fn prompt_admin_code() -> Option<AdminCode> {
+    match Text::new("Nhập phần đầu của mã số phiếu (VD: XX_YYYY_ZZZZZ_N_, hoặc XX_YYYY_ZZZZZ_ cho cả xã):").prompt() {
+        Ok(preflix_so_phieu) => match preflix_so_phieu.parse() {
+            Ok(admin_code) => Some(admin_code),
+            Err(error) => {
+                println!("{}", format!("> {}", error).red().bold());
+                None
             },
-        }
-
-        let houses_owners_vec: Vec<(String, Vec<ValueFieldHouseOwner>)> =  houses_owners.into_iter().collect();
-
-        for mut owner in houses_owners_vec {
-            let json_owner = serde_json::to_string(&owner.1[0].build()).unwrap();
-            let owner_params = [("data", &json_owner), ("pcgd-csrf-token", pcgd_csrf_token)];
-
-            let response = http_client.post("https://pcgd.moet.gov.vn/doing/phieudieutra/update")
-                .form(&owner_params)
-                .send()
-                .unwrap();
-
-            let text = response.text().unwrap();
-            let creation_response: Value = serde_json::from_str(&text).unwrap();
-
-            println!("{:#?}", owner_params);
-            println!("{}", creation_response);
-
-            let mut ma_phieu = String::new();
-
-            if creation_response["result"] == "success" {
-                println!("{}", format!("{} \"{} {}\" {}", "> Tải lên thành công hộ gia đình", owner.1[0].chuho_hodem, owner.1[0].chuho_ten, owner.1[0].so_phieu).green().bold());
-                ma_phieu = creation_response["ma_phieu"].to_string();
-                
-                ma_phieu.pop();
-                ma_phieu.remove(0);
-
-                so_chu_ho_uploaded += 1;
-                println!("{}", ma_phieu);
-            } else if creation_response["errors"]["so_phieu"].as_str().unwrap().ends_with(" đã tồn tại.") {
-                println!("{}", format!("{} \"{} {}\" {} {}", "> Hộ gia đình", owner.1[0].chuho_hodem, owner.1[0].chuho_ten, owner.1[0].so_phieu, "đã tồn tại, đang sửa lại dữ liệu...").yellow().bold());
-
-                let search_response = http_client.post("https://pcgd.moet.gov.vn/doing/phieudieutra/lay_phieu")
-                    .form(
-                        &[
-                            ("tinh", ma_tinh),
-                            ("quanhuyen", &ma_quanhuyen),
-                            ("phuongxa", &ma_phuongxa),
-                            ("tukhoa", &owner.1[0].so_phieu),
-                            ("_search", "false"),
-                            ("rows", "5"), 
-                            ("page", "1"),
-                            ("sidx", "so_phieu"),
-                            ("pcgd-csrf-token", pcgd_csrf_token),
-                        ])
-                    .send()
-                    .unwrap();
-
-                let re = Regex::new("id\":\"([^\"]+)").unwrap();
-                ma_phieu = re.captures(&search_response.text().unwrap()).unwrap().get(0).unwrap().as_str().split("id\":\"").last().unwrap().to_string();
-
-                let doituong_response = http_client.post(format!("https://pcgd.moet.gov.vn/doing/doituong/lay_doituong?phieu={}", ma_phieu))
-                    .form(
-                        &[
-                            ("_search", "false"),
-                            ("rows", "10"), 
-                            ("page", "1"),
-                            ("sord", "asc"),
-                            ("sidx", "ngay_sinh"),
-                            ("pcgd-csrf-token", pcgd_csrf_token),
-                        ])
-                    .send()
-                    .unwrap();
-
-                let mut json_doituong: Value = serde_json::from_str(&doituong_response.text().unwrap()).unwrap();
-
-                while json_doituong["records"] != "0" {
-                    let mut delete_params: Vec<(&str, String)> = vec![];
-
-                    let list_doituong = json_doituong["rows"].as_array_mut().unwrap();
-
-                    for doituong in 0..list_doituong.len() {
-                        let mut id = list_doituong[doituong]["id"].to_string();
-                        id.pop();
-                        id.remove(0);
-                        println!("{}", format!("> Thiết lập {}", id).green().bold());
-                        delete_params.push(("id[]", id));
-                    }
-
-                    delete_params.push(("pcgd-csrf-token", pcgd_csrf_token.to_string()));
-
-                    let delete_response = http_client.post(format!("https://pcgd.moet.gov.vn/doing/doituong/delete"))
-                        .form(&delete_params)
-                        .send()
-                        .unwrap();
-
-                    let json_delete: Value = serde_json::from_str(&delete_response.text().unwrap()).unwrap();
-
-                    if json_delete["result"] == "success" {
-                        println!("{}", format!("> Đã lọc {} thành viên", list_doituong.len()).green().bold());
-                    } else {
-                        println!("{}", json_delete);
-                    }
-
-                    let doituong_response = http_client.post(format!("https://pcgd.moet.gov.vn/doing/doituong/lay_doituong?phieu={}", ma_phieu))
-                        .form(
-                            &[
-                                ("_search", "false"),
-                                ("rows", "10"), 
-                                ("page", "1"),
-                                ("sord", "asc"),
-                                ("sidx", "ngay_sinh"),
-                                ("pcgd-csrf-token", pcgd_csrf_token),
-                            ])
-                        .send()
-                        .unwrap();
-
-                    json_doituong = serde_json::from_str(&doituong_response.text().unwrap()).unwrap();
-                }
-
-                so_chu_ho_uploaded += 1;
-                println!("{}", format!("> Hoàn thành lọc thành viên, đang thêm vào...").green().bold());
-            } else {
-                println!("{}", format!("{} \"{} {}\" {}", "> Có lỗi khi tải lên hộ gia đình\n\nThông tin debug:", owner.1[0].chuho_hodem, owner.1[0].chuho_ten, owner.1[0].so_phieu).red().bold());
-                
-                println!("{:#?}", owner_params);
-                println!("{}", creation_response);
-
-                println!("{}", "> Kết thúc thông tin debug.".red().bold());
-
-                match Confirm::new("Bạn có muốn tiếp tục?").with_default(false).prompt() {
-                    Ok(true) => println!("{} Đang tiếp tục...", ">".green().bold()),
-                    Ok(false) => {
-                        println!("{}", "> Đã dừng công việc.".red().bold());
-                        return Ok(());
-                    },
-                    Err(_) => {
-                        println!("{}", "> Đã dừng công việc.".red().bold());
-                        return Ok(());
-                    },
-                }
-            }
+        },
+        Err(_) => {
+            println!("{}", "> Đầu số phiếu không được để trống.".red().bold());
+            None
+        },
+    }
+}
 
-            for resident in houses_residents.get_mut(&owner.0).unwrap() {
-                resident.0.update_ma_phieu(ma_phieu.clone());
-
-                let data1_json = serde_json::to_string(&resident.0.build()).unwrap();
-                let data2_json = serde_json::to_string(&resident.1.build()).unwrap();
-                let data_dtht_json = serde_json::to_string(&resident.2.build()).unwrap();
-
-                let resident_params = [
-                    ("data1", &data1_json),
-                    ("data2", &data2_json),
-                    ("data3", &format!("{{\"pcgd-csrf-token\" : \"{}\"}}", pcgd_csrf_token)),
-                    ("data_dtht", &format!("{{\"2024\": \"{}\"}}", BASE64_STANDARD.encode(data_dtht_json))),
-                    ("pcgd-csrf-token", pcgd_csrf_token),
-                ];
-
-                let response = http_client.post("https://pcgd.moet.gov.vn/doing/doituong/add")
-                    .form(&resident_params)
-                    .send()
-                    .unwrap();
-
-                let resident_response: Value = serde_json::from_str(&response.text().unwrap()).unwrap();
-
-                if resident_response["result"] == "success" {
-                    so_thanh_vien_uploaded += 1;
-                    println!("{}", format!("> Đã thêm \"{}\" vào hộ {}", resident.0.ho_ten, owner.0).green().bold());
-                } else {
-                    println!("{}", format!("> Có lỗi khi thêm \"{}\" vào hộ {}\n\n Thông tin debug:\n", resident.0.ho_ten, owner.0).red().bold());
-                    
-                    println!("{:#?}", resident.0);
-                    println!("{:#?}", resident.1);
-                    println!("{:#?}", resident.2);
-                    println!("{:#?}", resident_params);
-                    println!("{}", resident_response);
-
-                    println!("{}", "> Kết thúc thông tin debug.".red().bold());
-
-                    match Confirm::new("Bạn có muốn tiếp tục?").with_default(false).prompt() {
-                        Ok(true) => println!("{} Đang tiếp tục...", ">".green().bold()),
-                        Ok(false) => {
-                            println!("{}", "> Đã dừng công việc.".red().bold());
-                            return Ok(());
-                        },
-                        Err(_) => {
-                            println!("{}", "> Đã dừng công việc.".red().bold());
-                            return Ok(());
-                        },
-                    }
-                }
-            }
-        }
+/// Nhập mã xã rồi chọn thôn trong số các thôn đã có phiếu trên cổng, hoặc cả xã.
+/// Cổng không cho tải danh mục đơn vị hành chính, nên thôn chưa có phiếu nào thì phải nhập tay.
+fn pick_admin_code(client: &PcgdClient) -> Option<AdminCode> {
+    let admin_code = prompt_admin_code()?;
+
+    if admin_code.ma_thonxom().is_some() {
+        return Some(admin_code);
     }
 
-    println!("{}", format!("> Đã thêm {}/{} hộ và {}/{} thành viên và các hộ.", so_chu_ho_uploaded, so_chu_ho, so_thanh_vien_uploaded, so_thanh_vien).green().bold());
+    let villages: Vec<String> = match portal_villages(client, &admin_code) {
+        Ok(villages) => villages.into_iter().collect(),
+        Err(error) => {
+            println!("{} Không tải được danh sách phiếu của xã {}: {}", ">".yellow().bold(), admin_code, error);
+            return Some(admin_code);
+        },
+    };
+
+    let mut choices = villages.clone();
+    choices.push("Cả xã (thôn lấy theo cột mã thôn hoặc số phiếu của từng hộ)".to_owned());
+    choices.push("Thôn khác (nhập tay)".to_owned());
 
-    Ok(())
+    match Select::new("Chọn thôn/xóm (các thôn đã có phiếu trên cổng):", choices).raw_prompt() {
+        Ok(choice) if choice.index < villages.len() => villages[choice.index].parse().ok(),
+        Ok(choice) if choice.index == villages.len() => Some(admin_code),
+        Ok(_) => prompt_admin_code(),
+        Err(_) => {
+            println!("{}", "> Đã dừng công việc.".red().bold());
+            None
+        },
+    }
 }
 
-fn main() {
-    println!("{} Chọn file XLS", ">".green().bold());
+/// Chọn file sao lưu và thêm lại các thành viên trong đó vào một phiếu trên cổng.
+fn restore_backup(base_url: &str, options: &UploadOptions) -> RunOutcome {
+    println!("{} Chọn file sao lưu (JSON)", ">".green().bold());
 
-    let excel_file = match FileDialog::new()
-    .add_filter("Excel spreadsheet", &["xls"])
-    .set_directory("/")
+    let backup_file = match FileDialog::new()
+    .add_filter("JSON", &["json"])
+    .set_directory(BACKUP_DIR)
     .pick_file() {
         Some(file) => file,
         None => {
             println!("{}", "> Không nhận được file!".red().bold());
-            return;
+            return RunOutcome::Stopped;
+        },
+    };
+
+    let backup = match read_backup(&backup_file) {
+        Ok(backup) => backup,
+        Err(error) => {
+            println!("{}", format!("> {}", error).red().bold());
+            return RunOutcome::Stopped;
+        },
+    };
+
+    println!("{} File sao lưu có {} thành viên của hộ {} (mã phiếu {}).", ">".green().bold(), backup.members.len(), backup.so_phieu, backup.ma_phieu);
+
+    let ma_phieu = match Text::new("Khôi phục vào mã phiếu:").with_default(&backup.ma_phieu).prompt() {
+        Ok(ma_phieu) if !ma_phieu.trim().is_empty() => ma_phieu.trim().to_owned(),
+        _ => {
+            println!("{}", "> Mã phiếu không được để trống.".red().bold());
+            return RunOutcome::Stopped;
+        },
+    };
+
+    let session = match read_session(base_url) {
+        Some(session) => session,
+        None => return RunOutcome::Stopped,
+    };
+
+    let client = connect(base_url, &session);
+    let summary = restore_members(&client, &backup, &ma_phieu, options);
+
+    println!("{}", format!("> Đã khôi phục {}/{} thành viên vào phiếu {}.", summary.restored, backup.members.len(), ma_phieu).green().bold());
+    if summary.present > 0 {
+        println!("{}", format!("> {} thành viên vẫn còn trên phiếu nên không thêm lại.", summary.present).yellow().bold());
+    }
+    print_skipped(&summary.skipped);
+
+    RunOutcome::from_counts(false, summary.skipped.len())
+}
+
+/// Tải các hộ của một xã hoặc thôn trên cổng về file XLSX theo mẫu MauNhapLieu.
+fn export_workbook(base_url: &str, mapping: &ColumnMapping, profile: Option<&Profile>) -> RunOutcome {
+    let default_prefix = profile.map(|profile| profile.admin_code.prefix()).unwrap_or_default();
+
+    let preflix_so_phieu = match Text::new("Nhập đầu số phiếu của xã hoặc thôn cần xuất (VD: XX_YYYY_ZZZZZ_ hoặc XX_YYYY_ZZZZZ_N_):").with_default(&default_prefix).prompt() {
+        Ok(preflix_so_phieu) if !preflix_so_phieu.trim().is_empty() => preflix_so_phieu.trim().to_owned(),
+        _ => {
+            println!("{}", "> Đầu số phiếu không được để trống.".red().bold());
+            return RunOutcome::Stopped;
+        },
+    };
+
+    let session = match read_session(base_url) {
+        Some(session) => session,
+        None => return RunOutcome::Stopped,
+    };
+
+    println!("{} Chọn nơi lưu file bảng tính", ">".green().bold());
+
+    let export_file = match FileDialog::new()
+    .add_filter("XLSX", &["xlsx"])
+    .set_file_name("MauNhapLieu.xlsx")
+    .save_file() {
+        Some(file) => file,
+        None => {
+            println!("{}", "> Không nhận được file!".red().bold());
+            return RunOutcome::Stopped;
         },
     };
 
-    println!("{} Chọn file có chứa lệnh cURL", ">".green().bold());
+    let client = connect(base_url, &session);
 
-    let curl_file = match FileDialog::new()
+    match export_households(&client, &preflix_so_phieu, mapping, &export_file) {
+        Ok(summary) => {
+            println!("{}", format!("> Đã xuất {} hộ với {} thành viên ra {}.", summary.households, summary.residents, export_file.display()).green().bold());
+            RunOutcome::Done
+        },
+        Err(error) => {
+            println!("{}", format!("> {}", error).red().bold());
+            RunOutcome::Stopped
+        },
+    }
+}
+
+/// Hỏi cách xử lý thành viên của các hộ đã có trên cổng cho lần chạy này.
+fn select_member_policy() -> Option<MemberPolicy> {
+    let policies = vec![
+        "Đối chiếu: thêm thành viên mới, báo thành viên khác dữ liệu, giữ thành viên chỉ có trên cổng",
+        "Đối chiếu và hỏi trước khi xoá thành viên chỉ có trên cổng hoặc thay thành viên khác dữ liệu",
+        "Xoá hết thành viên trên cổng rồi thêm lại",
+    ];
+
+    match Select::new("Với hộ đã có trên cổng:", policies).raw_prompt() {
+        Ok(choice) => Some(match choice.index {
+            0 => MemberPolicy::Merge,
+            1 => MemberPolicy::MergeAskDelete,
+            _ => MemberPolicy::Replace,
+        }),
+        Err(_) => {
+            println!("{}", "> Đã dừng công việc.".red().bold());
+            None
+        },
+    }
+}
+
+/// Chọn hồ sơ xã: theo tham số --profile, hoặc hỏi khi chạy giao diện hỏi đáp và file hồ sơ có hồ sơ nào đó.
+fn select_profile(profiles_file: &Path, name: Option<&str>, interactive: bool) -> Result<Option<Profile>, String> {
+    let profiles = load_profiles(profiles_file)?;
+
+    if let Some(name) = name {
+        return find_profile(profiles, name).map(Some);
+    }
+
+    if !interactive || profiles.is_empty() {
+        return Ok(None);
+    }
+
+    let mut choices: Vec<String> = profiles.iter()
+        .map(|profile| format!("{} ({})", profile.name, profile.admin_code))
+        .collect();
+    choices.push("Không dùng hồ sơ".to_owned());
+
+    match Select::new("Chọn hồ sơ xã:", choices).raw_prompt() {
+        Ok(choice) => Ok(profiles.into_iter().nth(choice.index)),
+        Err(_) => Err("Đã dừng công việc.".to_owned()),
+    }
+}
+
+/// Địa chỉ cổng PCGD, ưu tiên lần lượt: tham số --base-url, biến môi trường PCGD_BASE_URL, hồ sơ xã, mặc định.
+/// Biến môi trường đứng trước hồ sơ để có thể đổi cổng cho một lần chạy mà không sửa file cấu hình.
+fn resolve_base_url(from_args: Option<String>, from_profile: Option<String>) -> Result<String, String> {
+    let base_url = from_args
+        .or_else(|| env::var(BASE_URL_ENV).ok())
+        .or(from_profile)
+        .unwrap_or_else(|| DEFAULT_BASE_URL.to_owned());
+
+    validate_base_url(&base_url)
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    let profile = match select_profile(&cli.profiles, cli.profile.as_deref(), cli.command.is_none()) {
+        Ok(profile) => profile,
+        Err(error) => {
+            println!("{}", format!("> {}", error).red().bold());
+            return ExitCode::FAILURE;
+        },
+    };
+
+    if let Some(profile) = &profile {
+        println!("{} Đang dùng hồ sơ {} (đầu số phiếu {})", ">".green().bold(), profile.name, profile.admin_code.prefix());
+    }
+
+    let base_url = match resolve_base_url(cli.base_url, profile.as_ref().and_then(|profile| profile.base_url.clone())) {
+        Ok(base_url) => base_url,
+        Err(error) => {
+            println!("{}", format!("> {}", error).red().bold());
+            return ExitCode::FAILURE;
+        },
+    };
+
+    if base_url != DEFAULT_BASE_URL {
+        println!("{} Đang dùng cổng PCGD tại {}", ">".yellow().bold(), base_url);
+    }
+
+    let mapping_file = cli.mapping
+        .or_else(|| profile.as_ref().and_then(|profile| profile.column_mapping.clone()))
+        .unwrap_or_else(|| PathBuf::from(COLUMN_MAPPING_FILE));
+
+    let mapping = match ColumnMapping::load(&mapping_file) {
+        Ok(mapping) => {
+            if mapping_file.exists() {
+                println!("{} Đã nạp vị trí cột từ {}", ">".green().bold(), mapping_file.display());
+            }
+            mapping
+        },
+        Err(error) => {
+            println!("{}", format!("> {}", error).red().bold());
+            return ExitCode::FAILURE;
+        },
+    };
+
+    let result = match cli.command {
+        Some(command) => cli::run(command, &base_url, &mapping, profile.as_ref()),
+        None => interactive(&base_url, &mapping, profile.as_ref()).into_result(),
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            println!("{}", format!("> {}", error).red().bold());
+            ExitCode::FAILURE
+        },
+    }
+}
+
+/// Giao diện hỏi đáp khi chạy không có lệnh con.
+fn interactive(base_url: &str, mapping: &ColumnMapping, profile: Option<&Profile>) -> RunOutcome {
+    let on_error = profile.and_then(|profile| profile.on_error).unwrap_or_default();
+
+    let modes = vec![
+        "Tải lên cổng PCGD",
+        "Chạy thử (chỉ ghi request ra file)",
+        "Gửi file nhật ký đã duyệt",
+        "Khôi phục thành viên từ file sao lưu",
+        "Lập kế hoạch (so sánh bảng tính với cổng)",
+        "Thực hiện kế hoạch đã lập",
+        "Xuất dữ liệu từ cổng ra file Excel",
+    ];
+    let mode = match Select::new("Chọn chế độ:", modes).prompt() {
+        Ok("Tải lên cổng PCGD") => RunMode::Upload,
+        Ok("Chạy thử (chỉ ghi request ra file)") => RunMode::DryRun(PathBuf::from(JOURNAL_FILE)),
+        Ok("Gửi file nhật ký đã duyệt") => RunMode::Replay,
+        Ok("Khôi phục thành viên từ file sao lưu") => RunMode::Restore,
+        Ok("Lập kế hoạch (so sánh bảng tính với cổng)") => RunMode::Plan(PathBuf::from(PLAN_FILE)),
+        Ok("Thực hiện kế hoạch đã lập") => RunMode::Apply,
+        Ok(_) => RunMode::Export,
+        Err(_) => {
+            println!("{}", "> Đã dừng công việc.".red().bold());
+            return RunOutcome::Stopped;
+        },
+    };
+
+    if let RunMode::Restore = mode {
+        return restore_backup(base_url, &UploadOptions { on_error, ..UploadOptions::default() });
+    }
+
+    if let RunMode::Export = mode {
+        return export_workbook(base_url, mapping, profile);
+    }
+
+    if let RunMode::Apply = mode {
+        return match read_session(base_url) {
+            Some(session) => plan_applier(Path::new(PLAN_FILE), &connect(base_url, &session), &UploadOptions { on_error, ..UploadOptions::default() }),
+            None => RunOutcome::Stopped,
+        };
+    }
+
+    if let RunMode::Replay = mode {
+        println!("{} Chọn file nhật ký (JSONL)", ">".green().bold());
+
+        let journal_file = match FileDialog::new()
+        .add_filter("JSON Lines", &["jsonl"])
+        .set_directory("/")
+        .pick_file() {
+            Some(file) => file,
+            None => {
+                println!("{}", "> Không nhận được file!".red().bold());
+                return RunOutcome::Stopped;
+            },
+        };
+
+        let session = match read_session(base_url) {
+            Some(session) => session,
+            None => return RunOutcome::Stopped,
+        };
+        let member_policy = match select_member_policy() {
+            Some(member_policy) => member_policy,
+            None => return RunOutcome::Stopped,
+        };
+        let options = UploadOptions { member_policy, on_error, ..UploadOptions::default() };
+
+        let client = connect(base_url, &session);
+        return journal_replayer(&journal_file, &client, &session.pcgd_csrf_token, Path::new(CHECKPOINT_FILE), &options);
+    }
+
+    println!("{} Chọn file bảng tính (XLS, XLSX, XLSB, ODS)", ">".green().bold());
+
+    let excel_file = match FileDialog::new()
+    .add_filter("Spreadsheet", &SUPPORTED_EXTENSIONS)
     .set_directory("/")
     .pick_file() {
         Some(file) => file,
         None => {
             println!("{}", "> Không nhận được file!".red().bold());
-            return;
+            return RunOutcome::Stopped;
         },
     };
 
-    let curl_content = fs::read_to_string(curl_file).unwrap();
+    let (pcgd_csrf_token, client) = match mode {
+        RunMode::DryRun(_) => (CSRF_TOKEN_PLACEHOLDER.to_owned(), None),
+        _ => match read_session(base_url) {
+            Some(session) => (session.pcgd_csrf_token.clone(), Some(connect(base_url, &session))),
+            None => return RunOutcome::Stopped,
+        },
+    };
 
-    let token_extactor = curl_content.split("pcgd-csrf-token=");
-    let mut pcgd_csrf_token = token_extactor.last().unwrap().to_string();
-    let _ = pcgd_csrf_token.pop();
+    let member_policy = match mode {
+        RunMode::DryRun(_) => MemberPolicy::default(),
+        _ => match select_member_policy() {
+            Some(member_policy) => member_policy,
+            None => return RunOutcome::Stopped,
+        },
+    };
 
-    let re = Regex::new(r"'Cookie:\s([^']*)").unwrap();
-    let cookies = re.captures(&curl_content).unwrap().get(0).unwrap().as_str().split("'Cookie: ").last().unwrap().to_string();
+    let default_ngay_dieutra = profile.and_then(|profile| profile.ngay_dieutra.clone()).unwrap_or_default();
 
-    let ngay_dieutra = match Text::new("Nhập ngày điều tra:").prompt() {
+    let ngay_dieutra = match Text::new("Nhập ngày điều tra:").with_default(&default_ngay_dieutra).prompt() {
         Ok(ngay_dieutra) => ngay_dieutra,
         Err(_) => {
             println!("{}", "> Ngày điều tra không được để trống.".red().bold());
-            return;
+            return RunOutcome::Stopped;
         },
     };
 
-    let preflix_so_phieu = match Text::new("Nhập phần đầu của mã số phiếu (VD: XX_YYYY_ZZZZZ_N_):").prompt() {
-        Ok(ngay_dieutra) => ngay_dieutra,
-        Err(_) => {
-            println!("{}", "> Đầu số phiếu không được để trống.".red().bold());
-            return;
+    let admin_code: AdminCode = match (profile, &client) {
+        (Some(profile), _) => profile.admin_code.clone(),
+        (None, Some(client)) => match pick_admin_code(client) {
+            Some(admin_code) => admin_code,
+            None => return RunOutcome::Stopped,
+        },
+        (None, None) => match prompt_admin_code() {
+            Some(admin_code) => admin_code,
+            None => return RunOutcome::Stopped,
         },
     };
 
-    let _ = workbook_reader(&excel_file, &ngay_dieutra, &preflix_so_phieu, &pcgd_csrf_token, &cookies);
+    let job = WorkbookJob {
+        file: excel_file,
+        mapping,
+        mode,
+        ngay_dieutra,
+        admin_code,
+        pcgd_csrf_token,
+        checkpoint_file: PathBuf::from(CHECKPOINT_FILE),
+        options: UploadOptions { member_policy, on_error, ..UploadOptions::default() },
+        invalid_rows: profile.and_then(|profile| profile.invalid_rows).unwrap_or_default(),
+        allow_unverified_code: false,
+    };
+
+    match workbook_reader(&job, client.as_ref()) {
+        Ok(outcome) => outcome,
+        Err(error) => {
+            println!("{}", format!("> Không đọc được file bảng tính: {}", error).red().bold());
+            RunOutcome::Stopped
+        },
+    }
 }
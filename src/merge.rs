@@ -0,0 +1,231 @@
+use clap::ValueEnum;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+use unidecode::unidecode;
+use crate::household_info::{ResidentFields, ValueFieldHouseOwner, ValueFieldHouseResident};
+use crate::pcgd_client::GridRow;
+
+/// Ghi chú (đã bỏ dấu, viết thường) đánh dấu thành viên cần xoá khỏi hộ trên cổng.
+const DELETE_MARKERS: [&str; 2] = ["xoa", "xoa khoi ho"];
+
+/// Các trường không so sánh khi đối chiếu với dữ liệu trên cổng.
+const IGNORED_FIELDS: [&str; 4] = ["ma_phieu", "ma_dot", "pcgd-csrf-token", "pcgd_csrf_token"];
+
+/// Các trường ngày tháng, so sánh sau khi đưa về dd/mm/yyyy.
+const DATE_FIELDS: [&str; 2] = ["ngay_sinh", "ngay_dieutra"];
+
+/// Cách xử lý thành viên của hộ đã có trên cổng PCGD.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum MemberPolicy {
+    /// Thêm thành viên mới, giữ lại thành viên chỉ có trên cổng. Thành viên khớp nhưng khác dữ liệu
+    /// được báo là xung đột và giữ nguyên, vì cổng không có API sửa đối tượng.
+    #[default]
+    Merge,
+    /// Như `Merge`, nhưng hỏi để xoá các thành viên chỉ có trên cổng và để thay thành viên khác dữ liệu
+    /// (xoá bản cũ rồi thêm lại, mất mã đối tượng và lịch sử trên cổng).
+    MergeAskDelete,
+    /// Xoá hết thành viên trên cổng rồi thêm lại từ bảng tính.
+    Replace,
+}
+
+/// Kết quả đối chiếu thành viên trong bảng tính với thành viên trên cổng.
+#[derive(Debug, Default)]
+pub struct MemberMatch {
+    /// Thành viên trên cổng khớp với từng thành viên trong bảng tính, theo thứ tự trong hộ.
+    pub matched: Vec<Option<GridRow>>,
+    /// Thành viên chỉ có trên cổng.
+    pub unmatched: Vec<GridRow>,
+}
+
+/// Thành viên được đánh dấu xoá bằng ghi chú "Xoá".
+pub fn is_marked_for_deletion(resident: &ValueFieldHouseResident) -> bool {
+    DELETE_MARKERS.contains(&normalize_name(&resident.ghi_chu).as_str())
+}
+
+/// Khoá đối chiếu: họ tên bỏ dấu, viết thường cùng ngày sinh dạng dd/mm/yyyy.
+pub fn resident_key(ho_ten: &str, ngay_sinh: &str) -> String {
+    format!("{}|{}", normalize_name(ho_ten), normalize_birth_date(ngay_sinh))
+}
+
+/// Ghép từng thành viên trong bảng tính với một thành viên trên cổng có cùng họ tên và ngày sinh.
+pub fn match_members(residents: &[ResidentFields], portal_members: Vec<GridRow>) -> MemberMatch {
+    let mut unmatched = portal_members;
+
+    let matched = residents.iter()
+        .map(|resident| {
+            let key = resident_key(&resident.0.ho_ten, &resident.0.ngay_sinh);
+            unmatched.iter()
+                .position(|row| resident_key(&row.text("ho_ten"), &row.text("ngay_sinh")) == key)
+                .map(|position| unmatched.remove(position))
+        })
+        .collect();
+
+    MemberMatch { matched, unmatched }
+}
+
+/// Điền các trường còn trống trong bảng tính bằng giá trị đang có trên cổng, để cập nhật không làm mất dữ liệu.
+pub fn fill_from_portal(resident: &mut ResidentFields, row: &GridRow) {
+    resident.0 = fill_fields(&resident.0, row);
+    resident.1 = fill_fields(&resident.1, row);
+    resident.2 = fill_fields(&resident.2, row);
+}
+
+/// Các trường khác nhau giữa bảng tính và cổng: (trường, giá trị trên cổng, giá trị mới).
+/// Chỉ so các trường mà cổng có trả về.
+pub fn changed_fields(resident: &ResidentFields, row: &GridRow) -> Vec<(String, String, String)> {
+    let mut changes = field_changes(&resident.0, row);
+
+    for change in field_changes(&resident.1, row).into_iter().chain(field_changes(&resident.2, row)) {
+        if !changes.iter().any(|existing| existing.0 == change.0) {
+            changes.push(change);
+        }
+    }
+
+    changes
+}
+
+/// Các trường thành viên sẽ thay đổi khi cập nhật theo bảng tính, không sửa `resident`.
+pub fn member_changes(resident: &ResidentFields, row: &GridRow) -> Vec<(String, String, String)> {
+    let mut filled = resident.clone();
+    fill_from_portal(&mut filled, row);
+    changed_fields(&filled, row)
+}
+
+/// Điền các trường còn trống của chủ hộ bằng giá trị trên cổng rồi trả về các trường thay đổi.
+pub fn owner_changes(owner: &mut ValueFieldHouseOwner, row: &GridRow) -> Vec<(String, String, String)> {
+    *owner = fill_fields(owner, row);
+    field_changes(owner, row)
+}
+
+fn field_changes<T: Serialize>(value: &T, row: &GridRow) -> Vec<(String, String, String)> {
+    to_object(value).into_iter()
+        .filter(|(field, _)| !IGNORED_FIELDS.contains(&field.as_str()) && row.fields.contains_key(field))
+        .filter_map(|(field, value)| match value {
+            Value::String(value) => {
+                let portal = row.text(&field);
+                (comparable(&field, &portal) != comparable(&field, &value)).then_some((field, portal, value))
+            },
+            _ => None,
+        })
+        .collect()
+}
+
+/// Giá trị dùng để so sánh: ngày tháng đưa về dd/mm/yyyy, mã số bỏ số 0 ở đầu
+/// (cổng có thể trả về dạng số), chuỗi khác bỏ khoảng trắng thừa.
+fn comparable(field: &str, value: &str) -> String {
+    let value = value.split_whitespace().collect::<Vec<&str>>().join(" ");
+
+    if DATE_FIELDS.contains(&field) {
+        return normalize_birth_date(&value);
+    }
+
+    if !value.is_empty() && value.chars().all(|character| character.is_ascii_digit()) {
+        let trimmed = value.trim_start_matches('0');
+        return if trimmed.is_empty() { "0".to_owned() } else { trimmed.to_owned() };
+    }
+
+    value
+}
+
+fn fill_fields<T: Serialize + DeserializeOwned>(value: &T, row: &GridRow) -> T {
+    let mut object = to_object(value);
+
+    for (field, current) in object.iter_mut() {
+        if IGNORED_FIELDS.contains(&field.as_str()) {
+            continue;
+        }
+
+        if matches!(current, Value::String(text) if text.trim().is_empty()) {
+            let portal = row.text(field);
+            if !portal.is_empty() {
+                *current = Value::String(if DATE_FIELDS.contains(&field.as_str()) { normalize_birth_date(&portal) } else { portal });
+            }
+        }
+    }
+
+    serde_json::from_value(Value::Object(object)).unwrap()
+}
+
+fn to_object<T: Serialize>(value: &T) -> serde_json::Map<String, Value> {
+    match serde_json::to_value(value).unwrap() {
+        Value::Object(object) => object,
+        _ => serde_json::Map::new(),
+    }
+}
+
+fn normalize_name(name: &str) -> String {
+    unidecode(name)
+        .to_lowercase()
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+}
+
+/// Ngày sinh từ cổng có thể ở dạng yyyy-mm-dd, đưa về dd/mm/yyyy như trong bảng tính.
+pub fn normalize_birth_date(ngay_sinh: &str) -> String {
+    let ngay_sinh = ngay_sinh.trim();
+    let date = ngay_sinh.split(['T', ' ']).next().unwrap_or(ngay_sinh);
+    let parts: Vec<&str> = date.split(['/', '-', '.']).collect();
+
+    match parts.as_slice() {
+        [year, month, day] if year.len() == 4 => format!("{:0>2}/{:0>2}/{}", day, month, year),
+        [day, month, year] => format!("{:0>2}/{:0>2}/{}", day, month, year),
+        _ => date.to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn resident(ngay_sinh: &str, ma_dantoc: &str) -> ResidentFields {
+        let mut resident = ResidentFields::default();
+        resident.0.ho_ten = "Nguyễn Văn An".to_owned();
+        resident.0.ngay_sinh = ngay_sinh.to_owned();
+        resident.0.ma_dantoc = ma_dantoc.to_owned();
+        resident
+    }
+
+    fn portal_row(ngay_sinh: Value, ma_dantoc: Value) -> GridRow {
+        serde_json::from_value(json!({
+            "id": 7,
+            "ho_ten": "Nguyễn Văn An",
+            "ngay_sinh": ngay_sinh,
+            "ma_dantoc": ma_dantoc,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn differently_formatted_dates_and_codes_are_not_changes() {
+        let rows = [
+            portal_row(json!("2010-02-01"), json!(1)),
+            portal_row(json!("2010-02-01 00:00:00"), json!("01")),
+            portal_row(json!("1/2/2010"), json!("1")),
+        ];
+
+        for row in rows.iter() {
+            assert_eq!(changed_fields(&resident("01/02/2010", "01"), row), vec![], "{:?}", row);
+        }
+    }
+
+    #[test]
+    fn real_changes_are_still_reported() {
+        let row = portal_row(json!("2010-02-01"), json!(1));
+
+        let changes = changed_fields(&resident("02/01/2010", "02"), &row);
+
+        let fields: Vec<&str> = changes.iter().map(|(field, _, _)| field.as_str()).collect();
+        assert_eq!(fields, ["ma_dantoc", "ngay_sinh"]);
+    }
+
+    #[test]
+    fn matched_members_ignore_date_format() {
+        let members = match_members(&[resident("01/02/2010", "01")], vec![portal_row(json!("2010-02-01"), json!(1))]);
+
+        assert!(members.matched[0].is_some());
+        assert!(members.unmatched.is_empty());
+    }
+}
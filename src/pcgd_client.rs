@@ -0,0 +1,549 @@
+use std::{cell::RefCell, collections::HashMap, fmt};
+use colored::Colorize;
+use regex::Regex;
+use reqwest::{blocking::Client, header::CONTENT_TYPE, StatusCode, Url};
+use serde::{de::DeserializeOwned, Deserialize, Deserializer, Serialize};
+use serde_json::{Map, Value};
+use crate::http_client;
+use crate::household_info::{
+    owner_form,
+    resident_form,
+    ValueFieldHouseOwner,
+    ValueFieldHouseResident,
+    ValueFieldHouseResident2024Education,
+    ValueFieldHouseResidentGeneralEducation
+};
+use crate::session::Session;
+
+pub const DEFAULT_BASE_URL: &str = "https://pcgd.moet.gov.vn";
+
+/// Số dòng mỗi trang khi duyệt hết kết quả của lay_phieu và lay_doituong.
+const FIND_PAGE_SIZE: u32 = 50;
+
+/// Biến môi trường để trỏ tới cổng khác (bản tập huấn, máy chủ giả lập...).
+pub const BASE_URL_ENV: &str = "PCGD_BASE_URL";
+
+#[derive(Debug)]
+pub enum PcgdError {
+    /// Không kết nối được hoặc mất kết nối tới cổng PCGD.
+    Network(reqwest::Error),
+    /// Cookie hoặc CSRF token đã hết hạn, cổng trả về trang đăng nhập.
+    SessionExpired,
+    /// Cổng từ chối dữ liệu, kèm lỗi theo từng trường.
+    Validation(HashMap<String, String>),
+    /// Cổng trả về trang HTML thay vì JSON.
+    UnexpectedHtml(String),
+    /// Cổng trả về mã lỗi HTTP, kèm đoạn đầu nội dung.
+    HttpStatus(u16, String),
+    /// Nội dung trả về không phải JSON đúng định dạng.
+    MalformedJson(String),
+    /// Không có phiếu nào trùng khớp chính xác số phiếu.
+    PhieuNotFound(String),
+    /// Có nhiều phiếu trùng khớp chính xác số phiếu, kèm các mã phiếu tìm được.
+    AmbiguousPhieu(String, Vec<String>),
+    /// Phiếu cần tạo đã có trên cổng từ trước, kèm số phiếu và mã phiếu.
+    PhieuExists(String, String),
+}
+
+impl PcgdError {
+    /// Lỗi "số phiếu đã tồn tại" khi tạo phiếu điều tra, do cổng báo hoặc do đã tìm thấy phiếu trước khi gửi.
+    pub fn is_duplicate_so_phieu(&self) -> bool {
+        match self {
+            PcgdError::PhieuExists(..) => true,
+            PcgdError::Validation(fields) => fields.get("so_phieu")
+                .map(|message| message.ends_with(" đã tồn tại."))
+                .unwrap_or(false),
+            _ => false,
+        }
+    }
+
+    /// Lỗi có thể tự hết khi gửi lại: mất kết nối, lỗi phía máy chủ hoặc nội dung trả về bị cắt dở.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            PcgdError::Network(_) | PcgdError::UnexpectedHtml(_) | PcgdError::MalformedJson(_) => true,
+            PcgdError::HttpStatus(status, _) => *status >= 500,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for PcgdError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PcgdError::Network(error) => write!(f, "Lỗi kết nối: {}", error),
+            PcgdError::SessionExpired => write!(f, "Phiên đăng nhập đã hết hạn"),
+            PcgdError::Validation(fields) => {
+                let mut messages: Vec<String> = fields.iter()
+                    .map(|(field, message)| format!("{}: {}", field, message))
+                    .collect();
+                messages.sort();
+                write!(f, "Dữ liệu không hợp lệ ({})", messages.join("; "))
+            },
+            PcgdError::UnexpectedHtml(snippet) => write!(f, "Cổng PCGD trả về trang HTML: {}", snippet),
+            PcgdError::HttpStatus(status, snippet) => write!(f, "Cổng PCGD trả về lỗi HTTP {}: {}", status, snippet),
+            PcgdError::MalformedJson(detail) => write!(f, "Phản hồi không đúng định dạng JSON: {}", detail),
+            PcgdError::PhieuNotFound(so_phieu) => write!(f, "Không tìm thấy phiếu có số phiếu {}", so_phieu),
+            PcgdError::AmbiguousPhieu(so_phieu, ma_phieu) => write!(f, "Có {} phiếu cùng số phiếu {} (mã phiếu {})", ma_phieu.len(), so_phieu, ma_phieu.join(", ")),
+            PcgdError::PhieuExists(so_phieu, ma_phieu) => write!(f, "Số phiếu {} đã có trên cổng (mã phiếu {})", so_phieu, ma_phieu),
+        }
+    }
+}
+
+impl From<reqwest::Error> for PcgdError {
+    fn from(error: reqwest::Error) -> Self {
+        PcgdError::Network(error)
+    }
+}
+
+/// Phản hồi của các thao tác thêm, sửa, xoá.
+#[derive(Deserialize, Debug)]
+struct ActionResponse {
+    #[serde(default)]
+    result: String,
+    #[serde(default, deserialize_with = "optional_string_or_number")]
+    ma_phieu: Option<String>,
+    #[serde(default)]
+    errors: Value,
+}
+
+/// Phản hồi dạng bảng (jqGrid) của lay_phieu và lay_doituong.
+#[derive(Deserialize, Debug)]
+pub struct GridResponse {
+    #[serde(default, deserialize_with = "number_from_string_or_number")]
+    pub records: u64,
+    #[serde(default)]
+    pub rows: Vec<GridRow>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GridRow {
+    #[serde(deserialize_with = "string_or_number")]
+    pub id: String,
+    /// Các cột còn lại của dòng.
+    #[serde(flatten)]
+    pub fields: Map<String, Value>,
+}
+
+impl GridRow {
+    /// Giá trị của một cột dạng chuỗi, rỗng nếu không có.
+    pub fn text(&self, field: &str) -> String {
+        match self.fields.get(field) {
+            Some(Value::String(value)) => value.trim().to_owned(),
+            Some(Value::Null) | None => "".to_owned(),
+            Some(other) => other.to_string(),
+        }
+    }
+
+    /// Phiếu thuộc thôn `ma_thonxom`: cùng mã thôn, hoặc cổng không ghi mã thôn cho phiếu.
+    /// Tìm phiếu, lập kế hoạch và xuất đều dùng quy tắc này để chọn cùng một tập phiếu.
+    pub fn in_village(&self, ma_thonxom: &str) -> bool {
+        let village = self.text("ma_thonxom");
+        village.is_empty() || village == ma_thonxom
+    }
+}
+
+/// Điều kiện tìm phiếu điều tra trong lay_phieu.
+pub struct PhieuQuery<'a> {
+    pub tinh: &'a str,
+    pub quanhuyen: &'a str,
+    pub phuongxa: &'a str,
+    pub tukhoa: &'a str,
+    pub rows: u32,
+    pub page: u32,
+}
+
+/// Hỏi phiên đăng nhập mới khi phiên hiện tại hết hạn, trả về `None` để dừng.
+pub type SessionRenewer = Box<dyn Fn() -> Option<Session>>;
+
+/// Client gọi các API của cổng PCGD bằng cookie và CSRF token của một phiên đăng nhập.
+pub struct PcgdClient {
+    http_client: RefCell<Client>,
+    base_url: String,
+    pcgd_csrf_token: RefCell<String>,
+    renew_session: Option<SessionRenewer>,
+}
+
+impl PcgdClient {
+    pub fn new(base_url: &str, cookies: &str, pcgd_csrf_token: &str) -> Self {
+        let base_url = base_url.trim_end_matches('/');
+
+        PcgdClient {
+            http_client: RefCell::new(http_client::create_client_with_headers_preset(cookies, &origin(base_url))),
+            base_url: base_url.to_owned(),
+            pcgd_csrf_token: RefCell::new(pcgd_csrf_token.to_owned()),
+            renew_session: None,
+        }
+    }
+
+    /// Khi phiên đăng nhập hết hạn, hỏi phiên mới rồi gửi lại đúng request đang dở thay vì báo lỗi.
+    pub fn with_session_renewer(mut self, renew_session: SessionRenewer) -> Self {
+        self.renew_session = Some(renew_session);
+        self
+    }
+
+    /// Đổi sang cookie và CSRF token của phiên đăng nhập mới.
+    pub fn set_session(&self, session: &Session) {
+        *self.http_client.borrow_mut() = http_client::create_client_with_headers_preset(&session.cookies, &origin(&self.base_url));
+        *self.pcgd_csrf_token.borrow_mut() = session.pcgd_csrf_token.clone();
+    }
+
+    /// CSRF token của phiên đăng nhập hiện tại.
+    pub fn pcgd_csrf_token(&self) -> String {
+        self.pcgd_csrf_token.borrow().clone()
+    }
+
+    /// Tạo phiếu điều tra (phieudieutra/update), trả về mã phiếu.
+    pub fn update_phieu(&self, owner: &mut ValueFieldHouseOwner) -> Result<String, PcgdError> {
+        let response: ActionResponse = self.with_session(|| {
+            let pcgd_csrf_token = self.pcgd_csrf_token();
+            owner.pcgd_csrf_token = pcgd_csrf_token.clone();
+            let form = owner_form(owner, &pcgd_csrf_token);
+            self.post("/doing/phieudieutra/update", &form)
+        })?;
+
+        check_action(&response)?;
+
+        response.ma_phieu
+            .ok_or_else(|| PcgdError::MalformedJson("thiếu ma_phieu trong phản hồi".to_owned()))
+    }
+
+    /// Tìm phiếu điều tra (phieudieutra/lay_phieu).
+    pub fn lay_phieu(&self, query: &PhieuQuery) -> Result<GridResponse, PcgdError> {
+        let rows = query.rows.to_string();
+        let page = query.page.to_string();
+
+        self.with_session(|| {
+            let form = [
+                ("tinh", query.tinh),
+                ("quanhuyen", query.quanhuyen),
+                ("phuongxa", query.phuongxa),
+                ("tukhoa", query.tukhoa),
+                ("_search", "false"),
+                ("rows", &rows),
+                ("page", &page),
+                ("sidx", "so_phieu"),
+                ("pcgd-csrf-token", &self.pcgd_csrf_token()),
+            ];
+
+            self.post("/doing/phieudieutra/lay_phieu", &form)
+        })
+    }
+
+    /// Lấy toàn bộ phiếu điều tra của một xã/phường, duyệt qua mọi trang.
+    pub fn list_phieu(&self, tinh: &str, quanhuyen: &str, phuongxa: &str) -> Result<Vec<GridRow>, PcgdError> {
+        let mut phieu: Vec<GridRow> = vec![];
+        let mut page = 1;
+
+        loop {
+            let response = self.lay_phieu(&PhieuQuery { tinh, quanhuyen, phuongxa, tukhoa: "", rows: FIND_PAGE_SIZE, page })?;
+            let empty = response.rows.is_empty();
+            phieu.extend(response.rows);
+
+            if empty || phieu.len() as u64 >= response.records {
+                return Ok(phieu);
+            }
+
+            page += 1;
+        }
+    }
+
+    /// Tìm phiếu của hộ có đúng số phiếu (và thôn/xóm) của chủ hộ, duyệt qua mọi trang kết quả.
+    /// Tìm theo từ khoá có thể trả về cả phiếu khác chứa số phiếu (tìm "12" ra "112"), nên chỉ nhận dòng khớp chính xác.
+    pub fn find_phieu(&self, owner: &ValueFieldHouseOwner) -> Result<GridRow, PcgdError> {
+        let mut matches: Vec<GridRow> = vec![];
+        let mut seen = 0;
+        let mut page = 1;
+
+        loop {
+            let response = self.lay_phieu(&PhieuQuery {
+                tinh: &owner.ma_tinh,
+                quanhuyen: &owner.ma_quanhuyen,
+                phuongxa: &owner.ma_phuongxa,
+                tukhoa: &owner.so_phieu,
+                rows: FIND_PAGE_SIZE,
+                page,
+            })?;
+
+            seen += response.rows.len() as u64;
+
+            matches.extend(response.rows.iter()
+                .filter(|row| row.text("so_phieu") == owner.so_phieu)
+                .filter(|row| row.in_village(&owner.ma_thonxom))
+                .cloned());
+
+            if response.rows.is_empty() || seen >= response.records {
+                break;
+            }
+
+            page += 1;
+        }
+
+        match matches.len() {
+            0 => Err(PcgdError::PhieuNotFound(owner.so_phieu.clone())),
+            1 => Ok(matches.remove(0)),
+            _ => Err(PcgdError::AmbiguousPhieu(owner.so_phieu.clone(), matches.into_iter().map(|row| row.id).collect())),
+        }
+    }
+
+    /// Lấy danh sách đối tượng của một phiếu (doituong/lay_doituong).
+    pub fn lay_doituong(&self, ma_phieu: &str, rows: u32, page: u32) -> Result<GridResponse, PcgdError> {
+        let rows = rows.to_string();
+        let page = page.to_string();
+
+        self.with_session(|| {
+            let form = [
+                ("_search", "false"),
+                ("rows", &rows),
+                ("page", &page),
+                ("sord", "asc"),
+                ("sidx", "ngay_sinh"),
+                ("pcgd-csrf-token", &self.pcgd_csrf_token()),
+            ];
+
+            let query = form_urlencoded::Serializer::new(String::new())
+                .append_pair("phieu", ma_phieu)
+                .finish();
+
+            self.post(&format!("/doing/doituong/lay_doituong?{}", query), &form)
+        })
+    }
+
+    /// Lấy toàn bộ đối tượng của một phiếu, duyệt qua mọi trang.
+    pub fn list_doituong(&self, ma_phieu: &str) -> Result<Vec<GridRow>, PcgdError> {
+        let mut members: Vec<GridRow> = vec![];
+        let mut page = 1;
+
+        loop {
+            let response = self.lay_doituong(ma_phieu, FIND_PAGE_SIZE, page)?;
+            let empty = response.rows.is_empty();
+            members.extend(response.rows);
+
+            if empty || members.len() as u64 >= response.records {
+                return Ok(members);
+            }
+
+            page += 1;
+        }
+    }
+
+    /// Xoá các đối tượng theo id (doituong/delete).
+    pub fn delete_doituong(&self, ids: &[String]) -> Result<(), PcgdError> {
+        let response: ActionResponse = self.with_session(|| {
+            let pcgd_csrf_token = self.pcgd_csrf_token();
+            let mut form: Vec<(&str, &str)> = ids.iter().map(|id| ("id[]", id.as_str())).collect();
+            form.push(("pcgd-csrf-token", &pcgd_csrf_token));
+
+            self.post("/doing/doituong/delete", &form)
+        })?;
+        check_action(&response)
+    }
+
+    /// Thêm một đối tượng vào phiếu (doituong/add).
+    pub fn add_doituong(
+        &self,
+        resident: &ValueFieldHouseResident,
+        education: &ValueFieldHouseResidentGeneralEducation,
+        education_2024: &ValueFieldHouseResident2024Education
+    ) -> Result<(), PcgdError> {
+        let response: ActionResponse = self.with_session(|| {
+            let form = resident_form(resident, education, education_2024, &self.pcgd_csrf_token());
+            self.post("/doing/doituong/add", &form)
+        })?;
+        check_action(&response)
+    }
+
+    /// Gửi request, nếu phiên hết hạn và có cách hỏi phiên mới thì đổi phiên rồi gửi lại.
+    /// `request` phải dựng form từ CSRF token hiện tại của client ở mỗi lần gọi.
+    fn with_session<T>(&self, mut request: impl FnMut() -> Result<T, PcgdError>) -> Result<T, PcgdError> {
+        loop {
+            match request() {
+                Err(PcgdError::SessionExpired) => {
+                    let session = self.renew_session.as_ref().and_then(|renew_session| renew_session());
+
+                    match session {
+                        Some(session) => self.set_session(&session),
+                        None => {
+                            println!("{}", "> Phiên đăng nhập đã hết hạn, không có phiên mới.".red().bold());
+                            return Err(PcgdError::SessionExpired);
+                        },
+                    }
+                },
+                result => return result,
+            }
+        }
+    }
+
+    fn post<T: DeserializeOwned, F: serde::Serialize + ?Sized>(&self, path: &str, form: &F) -> Result<T, PcgdError> {
+        let response = self.http_client.borrow()
+            .post(format!("{}{}", self.base_url, path))
+            .form(form)
+            .send()?;
+
+        let status = response.status();
+        let content_type = response.headers()
+            .get(CONTENT_TYPE)
+            .and_then(|content_type| content_type.to_str().ok())
+            .unwrap_or("")
+            .to_lowercase();
+        let redirected_to_login = is_login_route(response.url().path());
+        let text = response.text()?;
+
+        if redirected_to_login || status == StatusCode::UNAUTHORIZED || status == StatusCode::FORBIDDEN {
+            return Err(PcgdError::SessionExpired);
+        }
+
+        parse_response(status, &content_type, &text)
+    }
+}
+
+/// Kiểm tra địa chỉ cổng PCGD, chỉ nhận http và https.
+pub fn validate_base_url(base_url: &str) -> Result<String, String> {
+    let url = Url::parse(base_url)
+        .map_err(|error| format!("Địa chỉ cổng PCGD \"{}\" không hợp lệ: {}", base_url, error))?;
+
+    if url.scheme() != "http" && url.scheme() != "https" {
+        return Err(format!("Địa chỉ cổng PCGD \"{}\" phải bắt đầu bằng http:// hoặc https://", base_url));
+    }
+
+    Ok(base_url.trim_end_matches('/').to_owned())
+}
+
+/// Phần scheme://host[:port] của địa chỉ, dùng cho header Origin.
+fn origin(base_url: &str) -> String {
+    Url::parse(base_url)
+        .map(|url| url.origin().ascii_serialization())
+        .unwrap_or_else(|_| base_url.to_owned())
+}
+
+/// Tên đoạn đường dẫn của trang đăng nhập mà cổng chuyển hướng tới khi hết phiên.
+const LOGIN_ROUTES: [&str; 3] = ["login", "dang-nhap", "dangnhap"];
+
+/// Đường dẫn bị chuyển hướng tới trang đăng nhập, so khớp nguyên đoạn chứ không tìm chuỗi con.
+fn is_login_route(path: &str) -> bool {
+    path.split('/').any(|segment| LOGIN_ROUTES.iter().any(|route| segment.eq_ignore_ascii_case(route)))
+}
+
+/// Trang HTML có form đăng nhập: form gửi tới trang đăng nhập hoặc có ô mật khẩu.
+/// Không dựa vào chữ "Đăng nhập" vì trang thường cũng có liên kết đó trên menu.
+fn has_login_form(html: &str) -> bool {
+    let login_action = Regex::new(r#"(?is)<form\b[^>]*\baction\s*=\s*["']([^"']*)["']"#).unwrap();
+    let password_input = Regex::new(r#"(?is)<input\b[^>]*\btype\s*=\s*["']?password\b"#).unwrap();
+
+    password_input.is_match(html) || login_action.captures_iter(html).any(|captures| {
+        let action = captures[1].split(['?', '#']).next().unwrap_or("");
+        is_login_route(action)
+    })
+}
+
+/// Kiểm tra mã trạng thái và kiểu nội dung trước khi đọc JSON.
+fn parse_response<T: DeserializeOwned>(status: StatusCode, content_type: &str, text: &str) -> Result<T, PcgdError> {
+    let trimmed = text.trim_start();
+    let snippet: String = trimmed.chars().take(200).collect();
+
+    if content_type.contains("html") || trimmed.starts_with('<') {
+        if has_login_form(trimmed) {
+            return Err(PcgdError::SessionExpired);
+        }
+        if !status.is_success() {
+            return Err(PcgdError::HttpStatus(status.as_u16(), snippet));
+        }
+        return Err(PcgdError::UnexpectedHtml(snippet));
+    }
+
+    if !status.is_success() {
+        return Err(PcgdError::HttpStatus(status.as_u16(), snippet));
+    }
+
+    serde_json::from_str(text)
+        .map_err(|error| PcgdError::MalformedJson(format!("{} ({})", error, snippet)))
+}
+
+fn check_action(response: &ActionResponse) -> Result<(), PcgdError> {
+    if response.result == "success" {
+        return Ok(());
+    }
+
+    let fields = match &response.errors {
+        Value::Object(errors) => errors.iter()
+            .map(|(field, message)| (field.clone(), value_to_message(message)))
+            .collect(),
+        Value::Null => HashMap::from([("result".to_owned(), response.result.clone())]),
+        other => HashMap::from([("errors".to_owned(), value_to_message(other))]),
+    };
+
+    Err(PcgdError::Validation(fields))
+}
+
+fn value_to_message(value: &Value) -> String {
+    match value {
+        Value::String(message) => message.clone(),
+        Value::Array(messages) => messages.iter().map(value_to_message).collect::<Vec<String>>().join(", "),
+        other => other.to_string(),
+    }
+}
+
+fn string_or_number<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    match Value::deserialize(deserializer)? {
+        Value::String(value) => Ok(value),
+        Value::Number(value) => Ok(value.to_string()),
+        other => Err(serde::de::Error::custom(format!("cần chuỗi hoặc số, nhận được {}", other))),
+    }
+}
+
+fn optional_string_or_number<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+    match Value::deserialize(deserializer)? {
+        Value::Null => Ok(None),
+        Value::String(value) => Ok(Some(value)),
+        Value::Number(value) => Ok(Some(value.to_string())),
+        other => Err(serde::de::Error::custom(format!("cần chuỗi hoặc số, nhận được {}", other))),
+    }
+}
+
+fn number_from_string_or_number<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+    match Value::deserialize(deserializer)? {
+        Value::Null => Ok(0),
+        Value::Number(value) => value.as_u64().ok_or_else(|| serde::de::Error::custom("số không hợp lệ")),
+        Value::String(value) => value.trim().parse().map_err(serde::de::Error::custom),
+        other => Err(serde::de::Error::custom(format!("cần số, nhận được {}", other))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MENU_PAGE: &str = r#"<html><head><title>Phổ cập giáo dục</title></head><body>
+        <nav><a href="/dang-xuat">Đăng xuất</a> | <a href="/doing/huongdan">Hướng dẫn đăng nhập</a></nav>
+        <form action="/doing/phieudieutra" method="get"><input type="text" name="q"></form>
+        </body></html>"#;
+
+    #[test]
+    fn login_route_matches_whole_segments_only() {
+        assert!(is_login_route("/login"));
+        assert!(is_login_route("/user/Login"));
+        assert!(is_login_route("/dang-nhap"));
+        assert!(!is_login_route("/doing/phieudieutra/lay_phieu"));
+        assert!(!is_login_route("/doing/login_history"));
+    }
+
+    #[test]
+    fn login_form_is_detected_by_action_or_password_input() {
+        assert!(has_login_form(r#"<form method="post" action="/login?next=%2Fdoing"></form>"#));
+        assert!(has_login_form(r#"<form action="https://pcgd.moet.gov.vn/dang-nhap"></form>"#));
+        assert!(has_login_form(r#"<form><input name="pass" type="password"></form>"#));
+        assert!(!has_login_form(MENU_PAGE));
+    }
+
+    #[test]
+    fn pages_mentioning_login_are_not_session_expiry() {
+        let result = parse_response::<Value>(StatusCode::OK, "text/html", MENU_PAGE);
+        assert!(matches!(result, Err(PcgdError::UnexpectedHtml(_))), "{:?}", result);
+
+        let result = parse_response::<Value>(StatusCode::INTERNAL_SERVER_ERROR, "text/html", MENU_PAGE);
+        assert!(matches!(result, Err(PcgdError::HttpStatus(500, _))), "{:?}", result);
+
+        let login = r#"<html><title>Đăng nhập</title><form action="/login"></form></html>"#;
+        let result = parse_response::<Value>(StatusCode::OK, "text/html", login);
+        assert!(matches!(result, Err(PcgdError::SessionExpired)), "{:?}", result);
+    }
+}
@@ -0,0 +1,404 @@
+use std::{collections::{HashMap, HashSet}, fs, path::Path};
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+use crate::backup::backup_members;
+use crate::household_info::{Household, ResidentFields, ValueFieldHouseOwner};
+use crate::journal::ResidentPayload;
+use crate::merge::{changed_fields, fill_from_portal, is_marked_for_deletion, match_members, owner_changes, MemberPolicy};
+use crate::pcgd_client::{GridRow, PcgdClient, PcgdError};
+use crate::upload::{add_member, create_phieu, decide, delete_members, with_retries, Decision, SkippedItem, UploadOptions};
+
+pub const PLAN_FILE: &str = "plan.json";
+
+/// Một trường khác nhau giữa cổng và bảng tính: (trường, giá trị trên cổng, giá trị mới).
+pub type FieldChange = (String, String, String);
+
+/// Một thao tác trong kế hoạch, mang đủ dữ liệu để thực hiện mà không cần đọc lại bảng tính.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum PlanAction {
+    /// Chỉ tạo phiếu, các thành viên là những `AddMember` ngay sau đó.
+    CreateHousehold {
+        so_phieu: String,
+        owner: Box<ValueFieldHouseOwner>,
+    },
+    UpdateOwner {
+        so_phieu: String,
+        ma_phieu: String,
+        changes: Vec<FieldChange>,
+        owner: Box<ValueFieldHouseOwner>,
+    },
+    AddMember {
+        so_phieu: String,
+        /// Để trống khi hộ được tạo bởi `CreateHousehold` trước đó trong kế hoạch.
+        ma_phieu: String,
+        resident: Box<ResidentPayload>,
+    },
+    /// Chỉ có với `MergeAskDelete`: xoá bản cũ rồi thêm lại, vì cổng không có API sửa đối tượng.
+    UpdateMember {
+        so_phieu: String,
+        ma_phieu: String,
+        id: String,
+        changes: Vec<FieldChange>,
+        resident: Box<ResidentPayload>,
+    },
+    RemoveMember {
+        so_phieu: String,
+        ma_phieu: String,
+        id: String,
+        ho_ten: String,
+        ngay_sinh: String,
+    },
+}
+
+impl PlanAction {
+    pub fn so_phieu(&self) -> &str {
+        match self {
+            PlanAction::CreateHousehold { so_phieu, .. }
+            | PlanAction::UpdateOwner { so_phieu, .. }
+            | PlanAction::AddMember { so_phieu, .. }
+            | PlanAction::UpdateMember { so_phieu, .. }
+            | PlanAction::RemoveMember { so_phieu, .. } => so_phieu,
+        }
+    }
+
+    /// Họ tên thành viên của thao tác, `None` với thao tác trên cả hộ.
+    pub fn ho_ten(&self) -> Option<&str> {
+        match self {
+            PlanAction::AddMember { resident, .. } | PlanAction::UpdateMember { resident, .. } => Some(&resident.data1.ho_ten),
+            PlanAction::RemoveMember { ho_ten, .. } => Some(ho_ten),
+            PlanAction::CreateHousehold { .. } | PlanAction::UpdateOwner { .. } => None,
+        }
+    }
+}
+
+/// Kế hoạch đưa cổng PCGD về đúng dữ liệu trong bảng tính.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Plan {
+    pub actions: Vec<PlanAction>,
+    /// Hộ không thể lập kế hoạch (ví dụ có nhiều phiếu trùng số phiếu), kèm lý do.
+    pub skipped: Vec<String>,
+    /// Hộ chỉ có trên cổng, không có trong bảng tính. Kế hoạch không động tới các hộ này.
+    pub portal_only: Vec<String>,
+    /// Thành viên khác dữ liệu trên cổng mà kế hoạch không thay (chỉ thay với `MergeAskDelete`), kèm các trường khác nhau.
+    #[serde(default)]
+    pub conflicts: Vec<String>,
+}
+
+/// Số thao tác đã thực hiện thành công và bị lỗi, cùng các hộ và thành viên bị bỏ qua.
+#[derive(Debug, Default)]
+pub struct ApplySummary {
+    pub done: usize,
+    pub failed: usize,
+    pub skipped: Vec<SkippedItem>,
+    /// Đã dừng trước khi thực hiện hết kế hoạch.
+    pub stopped: bool,
+}
+
+/// Tải danh sách hộ và thành viên trên cổng của các thôn/xóm có trong bảng tính rồi so sánh với bảng tính.
+pub fn build_plan(client: &PcgdClient, households: Vec<Household>, member_policy: MemberPolicy) -> Result<Plan, PcgdError> {
+    let mut plan = Plan::default();
+
+    let first_owner = match households.first() {
+        Some(household) => &household.owner,
+        None => return Ok(plan),
+    };
+
+    let villages: HashSet<String> = households.iter().map(|household| household.owner.ma_thonxom.clone()).collect();
+    let portal_phieu: Vec<GridRow> = client.list_phieu(&first_owner.ma_tinh, &first_owner.ma_quanhuyen, &first_owner.ma_phuongxa)?
+        .into_iter()
+        .filter(|row| villages.iter().any(|ma_thonxom| row.in_village(ma_thonxom)))
+        .collect();
+
+    let spreadsheet_phieu: HashSet<String> = households.iter().map(|household| household.so_phieu.clone()).collect();
+    plan.portal_only = portal_phieu.iter()
+        .map(|row| row.text("so_phieu"))
+        .filter(|so_phieu| !spreadsheet_phieu.contains(so_phieu))
+        .collect();
+
+    for mut household in households {
+        let matches: Vec<&GridRow> = portal_phieu.iter()
+            .filter(|row| row.text("so_phieu") == household.so_phieu)
+            .filter(|row| row.in_village(&household.owner.ma_thonxom))
+            .collect();
+
+        let row = match matches.as_slice() {
+            [] => {
+                plan.actions.push(PlanAction::CreateHousehold {
+                    so_phieu: household.so_phieu.clone(),
+                    owner: Box::new(household.owner),
+                });
+                for resident in household.residents.into_iter().filter(|resident| !is_marked_for_deletion(&resident.0)) {
+                    plan.actions.push(PlanAction::AddMember { so_phieu: household.so_phieu.clone(), ma_phieu: String::new(), resident: Box::new(payload(resident)) });
+                }
+                continue;
+            },
+            [row] => *row,
+            rows => {
+                let ids: Vec<String> = rows.iter().map(|row| row.id.clone()).collect();
+                plan.skipped.push(format!("{}: có {} phiếu cùng số phiếu (mã phiếu {})", household.so_phieu, ids.len(), ids.join(", ")));
+                continue;
+            },
+        };
+
+        let ma_phieu = row.id.clone();
+        let so_phieu = household.so_phieu.clone();
+
+        let changes = owner_changes(&mut household.owner, row);
+        if !changes.is_empty() {
+            household.owner.ma_phieu = ma_phieu.clone();
+            plan.actions.push(PlanAction::UpdateOwner {
+                so_phieu: so_phieu.clone(),
+                ma_phieu: ma_phieu.clone(),
+                changes,
+                owner: Box::new(household.owner),
+            });
+        }
+
+        let portal_members = client.list_doituong(&ma_phieu)?;
+
+        if member_policy == MemberPolicy::Replace {
+            for member in portal_members.iter() {
+                plan.actions.push(remove_action(&so_phieu, &ma_phieu, member));
+            }
+            for mut resident in household.residents.into_iter().filter(|resident| !is_marked_for_deletion(&resident.0)) {
+                resident.0.update_ma_phieu(ma_phieu.clone());
+                plan.actions.push(PlanAction::AddMember { so_phieu: so_phieu.clone(), ma_phieu: ma_phieu.clone(), resident: Box::new(payload(resident)) });
+            }
+            continue;
+        }
+
+        let members = match_members(&household.residents, portal_members);
+
+        for (mut resident, matched) in household.residents.into_iter().zip(members.matched) {
+            resident.0.update_ma_phieu(ma_phieu.clone());
+
+            match (is_marked_for_deletion(&resident.0), matched) {
+                (true, Some(member)) => plan.actions.push(remove_action(&so_phieu, &ma_phieu, &member)),
+                (true, None) => {},
+                (false, Some(member)) => {
+                    fill_from_portal(&mut resident, &member);
+                    let changes = changed_fields(&resident, &member);
+
+                    if !changes.is_empty() && member_policy != MemberPolicy::MergeAskDelete {
+                        let fields: Vec<String> = changes.iter().map(|(field, portal, ours)| format!("{}: \"{}\" -> \"{}\"", field, portal, ours)).collect();
+                        plan.conflicts.push(format!("\"{}\" trong hộ {} khác trên cổng ({})", resident.0.ho_ten, so_phieu, fields.join("; ")));
+                    } else if !changes.is_empty() {
+                        plan.actions.push(PlanAction::UpdateMember {
+                            so_phieu: so_phieu.clone(),
+                            ma_phieu: ma_phieu.clone(),
+                            id: member.id.clone(),
+                            changes,
+                            resident: Box::new(payload(resident)),
+                        });
+                    }
+                },
+                (false, None) => plan.actions.push(PlanAction::AddMember { so_phieu: so_phieu.clone(), ma_phieu: ma_phieu.clone(), resident: Box::new(payload(resident)) }),
+            }
+        }
+
+        if member_policy == MemberPolicy::MergeAskDelete {
+            for member in members.unmatched.iter() {
+                plan.actions.push(remove_action(&so_phieu, &ma_phieu, member));
+            }
+        }
+    }
+
+    Ok(plan)
+}
+
+/// In kế hoạch: + thêm mới, ~ sửa, - xoá.
+pub fn print_plan(plan: &Plan) {
+    let count = |predicate: fn(&PlanAction) -> bool| plan.actions.iter().filter(|action| predicate(action)).count();
+
+    println!(
+        "{} Kế hoạch: tạo {} hộ, cập nhật {} chủ hộ, thêm {} thành viên, thay {} thành viên, xoá {} thành viên.",
+        ">".green().bold(),
+        count(|action| matches!(action, PlanAction::CreateHousehold { .. })),
+        count(|action| matches!(action, PlanAction::UpdateOwner { .. })),
+        count(|action| matches!(action, PlanAction::AddMember { .. })),
+        count(|action| matches!(action, PlanAction::UpdateMember { .. })),
+        count(|action| matches!(action, PlanAction::RemoveMember { .. })),
+    );
+
+    for action in plan.actions.iter() {
+        match action {
+            PlanAction::CreateHousehold { so_phieu, owner } => {
+                println!("{}", format!("+ Tạo hộ {} \"{} {}\"", so_phieu, owner.chuho_hodem, owner.chuho_ten).green());
+            },
+            PlanAction::UpdateOwner { so_phieu, ma_phieu, changes, .. } => {
+                println!("{}", format!("~ Cập nhật chủ hộ {} (mã phiếu {})", so_phieu, ma_phieu).yellow());
+                print_changes(changes);
+            },
+            PlanAction::AddMember { so_phieu, resident, .. } => {
+                println!("{}", format!("+ Thêm \"{}\" ({}) vào hộ {}", resident.data1.ho_ten, resident.data1.ngay_sinh, so_phieu).green());
+            },
+            PlanAction::UpdateMember { so_phieu, resident, changes, .. } => {
+                println!("{}", format!("~ Thay \"{}\" ({}) trong hộ {} (xoá bản cũ rồi thêm lại)", resident.data1.ho_ten, resident.data1.ngay_sinh, so_phieu).yellow());
+                print_changes(changes);
+            },
+            PlanAction::RemoveMember { so_phieu, ho_ten, ngay_sinh, .. } => {
+                println!("{}", format!("- Xoá \"{}\" ({}) khỏi hộ {}", ho_ten, ngay_sinh, so_phieu).red());
+            },
+        }
+    }
+
+    for skipped in plan.skipped.iter() {
+        println!("{}", format!("! Bỏ qua hộ {}", skipped).red().bold());
+    }
+
+    for conflict in plan.conflicts.iter() {
+        println!("{}", format!("! Giữ nguyên {}", conflict).yellow().bold());
+    }
+
+    if !plan.portal_only.is_empty() {
+        println!("{} {} hộ chỉ có trên cổng, không thay đổi: {}", ">".yellow().bold(), plan.portal_only.len(), plan.portal_only.join(", "));
+    }
+}
+
+pub fn write_plan(path: &Path, plan: &Plan) -> Result<(), String> {
+    fs::write(path, serde_json::to_string_pretty(plan).unwrap())
+        .map_err(|error| format!("Không ghi được {}: {}", path.display(), error))
+}
+
+pub fn read_plan(path: &Path) -> Result<Plan, String> {
+    let content = fs::read_to_string(path)
+        .map_err(|error| format!("Không đọc được {}: {}", path.display(), error))?;
+
+    serde_json::from_str(&content)
+        .map_err(|error| format!("File kế hoạch {} không hợp lệ: {}", path.display(), error))
+}
+
+/// Thực hiện đúng các thao tác trong kế hoạch theo thứ tự. Thành viên của mỗi hộ được sao lưu trước lần xoá đầu tiên.
+/// Cổng không có API sửa đối tượng, nên sửa thành viên là xoá bản cũ rồi thêm lại.
+/// Lỗi được xử lý theo `options.on_error`; khi cả hộ bị bỏ qua thì các thao tác sau của hộ đó cũng bị bỏ qua.
+pub fn apply_plan(client: &PcgdClient, plan: Plan, options: &UploadOptions) -> ApplySummary {
+    let mut summary = ApplySummary::default();
+    let mut backed_up: HashSet<String> = HashSet::new();
+    let mut created: HashMap<String, String> = HashMap::new();
+    let mut skipped_households: HashSet<String> = HashSet::new();
+
+    'actions: for mut action in plan.actions {
+        if skipped_households.contains(action.so_phieu()) {
+            continue;
+        }
+
+        loop {
+            let error = match execute(client, &mut action, &mut backed_up, &mut created, options) {
+                Ok(message) => {
+                    summary.done += 1;
+                    println!("{}", format!("> {}", message).green().bold());
+                    break;
+                },
+                Err(error) => error,
+            };
+
+            println!("{}", format!("> {}", error).red().bold());
+
+            let decision = decide(options, action.ho_ten().is_some());
+            if decision == Decision::Retry {
+                continue;
+            }
+
+            summary.failed += 1;
+            let so_phieu = action.so_phieu().to_owned();
+            match decision {
+                Decision::Retry | Decision::Stop => {
+                    summary.stopped = true;
+                    break 'actions;
+                },
+                Decision::SkipResident => {
+                    summary.skipped.push(SkippedItem { so_phieu, ho_ten: action.ho_ten().map(str::to_owned), reason: error });
+                    break;
+                },
+                Decision::SkipHousehold => {
+                    skipped_households.insert(so_phieu.clone());
+                    summary.skipped.push(SkippedItem { so_phieu, ho_ten: None, reason: error });
+                    break;
+                },
+            }
+        }
+    }
+
+    summary
+}
+
+/// `created` giữ mã phiếu của các hộ vừa tạo theo số phiếu, cho các thao tác thêm thành viên phía sau.
+fn execute(client: &PcgdClient, action: &mut PlanAction, backed_up: &mut HashSet<String>, created: &mut HashMap<String, String>, options: &UploadOptions) -> Result<String, String> {
+    match action {
+        PlanAction::CreateHousehold { so_phieu, owner } => {
+            let ma_phieu = create_phieu(client, owner, options)
+                .map_err(|error| format!("Có lỗi khi tạo hộ {}: {}", so_phieu, error))?;
+            created.insert(so_phieu.clone(), ma_phieu.clone());
+
+            Ok(format!("Đã tạo hộ {} (mã phiếu {})", so_phieu, ma_phieu))
+        },
+        PlanAction::AddMember { so_phieu, ma_phieu, resident } if ma_phieu.is_empty() => {
+            let created_ma_phieu = created.get(so_phieu.as_str())
+                .ok_or_else(|| format!("Hộ {} chưa được tạo trên cổng, không thêm được \"{}\".", so_phieu, resident.data1.ho_ten))?;
+            *ma_phieu = created_ma_phieu.clone();
+            resident.data1.update_ma_phieu(created_ma_phieu.clone());
+
+            execute(client, action, backed_up, created, options)
+        },
+        PlanAction::UpdateOwner { so_phieu, owner, .. } => client.update_phieu(owner)
+            .map(|_| format!("Đã cập nhật chủ hộ {}", so_phieu))
+            .map_err(|error| error.to_string()),
+        PlanAction::AddMember { so_phieu, resident, .. } => add_member(client, &resident.data1, &resident.data2, &resident.data_dtht, options)
+            .map(|()| format!("Đã thêm \"{}\" vào hộ {}", resident.data1.ho_ten, so_phieu))
+            .map_err(|error| error.to_string()),
+        PlanAction::UpdateMember { so_phieu, ma_phieu, id, resident, .. } => {
+            backup_once(client, backed_up, so_phieu, ma_phieu, options)?;
+            delete_members(client, ma_phieu, std::slice::from_ref(id), options).map_err(|error| error.to_string())?;
+
+            // Đã xoá bản cũ, từ đây thao tác chỉ còn là thêm lại để thử lại không xoá thêm lần nữa.
+            let (so_phieu, ma_phieu, resident) = (so_phieu.clone(), ma_phieu.clone(), resident.clone());
+            *action = PlanAction::AddMember { so_phieu, ma_phieu, resident };
+
+            execute(client, action, backed_up, created, options)
+                .map(|message| format!("{} (thay cho bản cũ đã xoá)", message))
+        },
+        PlanAction::RemoveMember { so_phieu, ma_phieu, id, ho_ten, .. } => {
+            backup_once(client, backed_up, so_phieu, ma_phieu, options)
+                .and_then(|()| delete_members(client, ma_phieu, std::slice::from_ref(id), options).map_err(|error| error.to_string()))
+                .map(|()| format!("Đã xoá \"{}\" khỏi hộ {}", ho_ten, so_phieu))
+        },
+    }
+}
+
+fn backup_once(client: &PcgdClient, backed_up: &mut HashSet<String>, so_phieu: &str, ma_phieu: &str, options: &UploadOptions) -> Result<(), String> {
+    if backed_up.contains(ma_phieu) {
+        return Ok(());
+    }
+
+    let members = with_retries(options, || client.list_doituong(ma_phieu)).map_err(|error| error.to_string())?;
+    let path = backup_members(&options.backup_dir, so_phieu, ma_phieu, &members)?;
+    println!("{}", format!("> Đã sao lưu {} thành viên của hộ {} vào {}", members.len(), so_phieu, path.display()).green().bold());
+
+    backed_up.insert(ma_phieu.to_owned());
+    Ok(())
+}
+
+fn remove_action(so_phieu: &str, ma_phieu: &str, member: &GridRow) -> PlanAction {
+    PlanAction::RemoveMember {
+        so_phieu: so_phieu.to_owned(),
+        ma_phieu: ma_phieu.to_owned(),
+        id: member.id.clone(),
+        ho_ten: member.text("ho_ten"),
+        ngay_sinh: member.text("ngay_sinh"),
+    }
+}
+
+fn payload(resident: ResidentFields) -> ResidentPayload {
+    ResidentPayload {
+        data1: resident.0,
+        data2: resident.1,
+        data_dtht: resident.2,
+    }
+}
+
+fn print_changes(changes: &[FieldChange]) {
+    for (field, portal, ours) in changes.iter() {
+        println!("    {}: \"{}\" -> \"{}\"", field, portal, ours);
+    }
+}
@@ -0,0 +1,110 @@
+use std::{collections::BTreeMap, fs, path::{Path, PathBuf}};
+use regex::Regex;
+use serde::Deserialize;
+use crate::admin_code::AdminCode;
+use crate::pcgd_client::validate_base_url;
+use crate::row_validation::InvalidRowPolicy;
+use crate::upload::ErrorPolicy;
+
+pub const PROFILES_FILE: &str = "profiles.json";
+
+/// Hồ sơ của một xã đã được kiểm tra, dùng thay cho việc nhập lại ngày điều tra và đầu số phiếu mỗi lần chạy.
+#[derive(Debug, Clone)]
+pub struct Profile {
+    pub name: String,
+    pub admin_code: AdminCode,
+    /// Ngày điều tra mặc định (dd/mm/yyyy).
+    pub ngay_dieutra: Option<String>,
+    /// File cấu hình vị trí cột riêng của xã.
+    pub column_mapping: Option<PathBuf>,
+    /// Cổng PCGD của xã, dùng khi không có --base-url hay PCGD_BASE_URL.
+    pub base_url: Option<String>,
+    pub on_error: Option<ErrorPolicy>,
+    pub invalid_rows: Option<InvalidRowPolicy>,
+}
+
+/// Một hồ sơ như được ghi trong file cấu hình.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ProfileEntry {
+    ma_tinh: String,
+    ma_quanhuyen: String,
+    ma_phuongxa: String,
+    /// Bỏ trống khi bảng tính có hộ của nhiều thôn.
+    ma_thonxom: Option<String>,
+    ngay_dieutra: Option<String>,
+    column_mapping: Option<PathBuf>,
+    base_url: Option<String>,
+    on_error: Option<ErrorPolicy>,
+    invalid_rows: Option<InvalidRowPolicy>,
+}
+
+/// Đọc các hồ sơ trong file cấu hình (JSON, tên hồ sơ là khoá), sắp theo tên.
+/// Không có file thì trả về danh sách rỗng; hồ sơ nào sai thì báo lỗi kèm tên hồ sơ.
+pub fn load_profiles(path: &Path) -> Result<Vec<Profile>, String> {
+    if !path.exists() {
+        return Ok(vec![]);
+    }
+
+    let content = fs::read_to_string(path)
+        .map_err(|error| format!("Không đọc được {}: {}", path.display(), error))?;
+
+    let entries: BTreeMap<String, ProfileEntry> = serde_json::from_str(&content)
+        .map_err(|error| format!("File {} không hợp lệ: {}", path.display(), error))?;
+
+    entries.into_iter()
+        .map(|(name, entry)| validate_profile(&name, entry)
+            .map_err(|error| format!("Hồ sơ \"{}\" trong {}: {}", name, path.display(), error)))
+        .collect()
+}
+
+/// Tìm hồ sơ theo tên.
+pub fn find_profile(profiles: Vec<Profile>, name: &str) -> Result<Profile, String> {
+    let names = profiles.iter().map(|profile| profile.name.clone()).collect::<Vec<String>>().join(", ");
+
+    profiles.into_iter()
+        .find(|profile| profile.name == name)
+        .ok_or_else(|| format!("Không có hồ sơ \"{}\" (các hồ sơ hiện có: {}).", name, names))
+}
+
+fn validate_profile(name: &str, entry: ProfileEntry) -> Result<Profile, String> {
+    let admin_code: AdminCode = match &entry.ma_thonxom {
+        Some(ma_thonxom) => ma_thonxom.parse()?,
+        None => entry.ma_phuongxa.parse()?,
+    };
+
+    if let Some(ma_thonxom) = &entry.ma_thonxom {
+        if admin_code.ma_thonxom().is_none() {
+            return Err(format!("ma_thonxom \"{}\" thiếu mã thôn.", ma_thonxom.trim()));
+        }
+    }
+
+    let parents = [
+        ("ma_tinh", &entry.ma_tinh, admin_code.ma_tinh()),
+        ("ma_quanhuyen", &entry.ma_quanhuyen, admin_code.ma_quanhuyen()),
+        ("ma_phuongxa", &entry.ma_phuongxa, admin_code.ma_phuongxa()),
+    ];
+    for (field, value, expected) in parents {
+        if value.trim() != expected {
+            return Err(format!("{} là \"{}\" nhưng ma_thonxom {} thuộc {}.", field, value.trim(), admin_code, expected));
+        }
+    }
+
+    if let Some(ngay_dieutra) = &entry.ngay_dieutra {
+        if !Regex::new(r"^\d{1,2}/\d{1,2}/\d{4}$").unwrap().is_match(ngay_dieutra) {
+            return Err(format!("Ngày điều tra \"{}\" phải có dạng dd/mm/yyyy.", ngay_dieutra));
+        }
+    }
+
+    let base_url = entry.base_url.as_deref().map(validate_base_url).transpose()?;
+
+    Ok(Profile {
+        name: name.to_owned(),
+        admin_code,
+        ngay_dieutra: entry.ngay_dieutra,
+        column_mapping: entry.column_mapping,
+        base_url,
+        on_error: entry.on_error,
+        invalid_rows: entry.invalid_rows,
+    })
+}
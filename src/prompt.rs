@@ -0,0 +1,10 @@
+use inquire::Confirm;
+
+/// Hỏi xác nhận có/không, mặc định là không. Luôn trả về có khi `assume_yes` được bật.
+pub fn confirm(question: &str, assume_yes: bool) -> bool {
+    if assume_yes {
+        return true;
+    }
+
+    matches!(Confirm::new(question).with_default(false).prompt(), Ok(true))
+}
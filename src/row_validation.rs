@@ -0,0 +1,200 @@
+use calamine::Data;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use crate::cell_value;
+use crate::column_mapping::{column_letter, ColumnMapping};
+
+/// Các trường bắt buộc phải có giá trị ở mọi dòng dữ liệu.
+/// Năm sinh được kiểm tra riêng vì có thể nằm trong ô ngày sinh đầy đủ, xem `has_birth_year`.
+const REQUIRED_FIELDS: [&str; 3] = ["so_phieu", "ten", "qh_chu_ho"];
+
+/// Cách xử lý khi gặp dòng lỗi trong bảng tính.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum InvalidRowPolicy {
+    #[default]
+    Ask,
+    Skip,
+    Stop,
+}
+
+#[derive(Debug)]
+pub struct CellProblem {
+    pub column: String,
+    pub field: String,
+    pub message: String,
+}
+
+#[derive(Debug)]
+pub struct InvalidRow {
+    /// Số dòng theo Excel (tính từ 1).
+    pub row: usize,
+    pub problems: Vec<CellProblem>,
+}
+
+/// Dòng không có ô nào chứa dữ liệu.
+pub fn is_empty_row(col: &[Data]) -> bool {
+    col.iter().all(|cell| *cell == Data::Empty || cell.to_string().trim().is_empty())
+}
+
+/// Kiểm tra một dòng dữ liệu, trả về dòng đã được bù đủ số cột hoặc danh sách lỗi.
+/// `row` và `first_column` là vị trí tuyệt đối (tính từ 0) của dòng và cột đầu tiên trong sheet.
+pub fn check_row(col: &[Data], mapping: &ColumnMapping, row: usize, first_column: usize) -> Result<Vec<Data>, InvalidRow> {
+    let fields = mapping.fields();
+    let width = fields.iter().map(|(_, column)| column + 1).max().unwrap_or(0).max(col.len());
+
+    let mut padded = col.to_vec();
+    padded.resize(width, Data::Empty);
+
+    let mut problems: Vec<CellProblem> = vec![];
+
+    for (field, column) in fields {
+        let message = match &padded[column] {
+            Data::Error(error) => Some(format!("ô bị lỗi công thức ({})", error)),
+            cell if REQUIRED_FIELDS.contains(&field.as_str()) && cell.to_string().trim().is_empty() => {
+                if column >= col.len() {
+                    Some("dòng không đủ cột".to_owned())
+                } else {
+                    Some("ô bắt buộc bị trống".to_owned())
+                }
+            },
+            _ => None,
+        };
+
+        if let Some(message) = message {
+            problems.push(CellProblem {
+                column: column_letter(first_column + column),
+                field,
+                message,
+            });
+        }
+    }
+
+    if !has_birth_year(&padded, mapping) && !problems.iter().any(|problem| problem.field == "nam_sinh") {
+        problems.push(CellProblem {
+            column: column_letter(first_column + mapping.nam_sinh),
+            field: "nam_sinh".to_owned(),
+            message: "thiếu năm sinh (cột năm sinh hoặc ngày sinh đầy đủ)".to_owned(),
+        });
+    }
+
+    if problems.is_empty() {
+        Ok(padded)
+    } else {
+        Err(InvalidRow { row: row + 1, problems })
+    }
+}
+
+/// Dòng có năm sinh, ở cột năm sinh hoặc trong ô ngày sinh ghi đủ ngày tháng năm.
+fn has_birth_year(padded: &[Data], mapping: &ColumnMapping) -> bool {
+    let ngay_sinh = cell_value::birth_date(&padded[mapping.ngay_sinh], &padded[mapping.thang_sinh], &padded[mapping.nam_sinh]);
+    let year = ngay_sinh.rsplit('/').next().unwrap_or("");
+
+    year.len() == 4 && year.chars().all(|character| character.is_ascii_digit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Một dòng đủ các ô bắt buộc theo mẫu mặc định.
+    fn valid_row() -> Vec<Data> {
+        let mapping = ColumnMapping::default();
+        let mut row = vec![Data::Empty; mapping.ghi_chu + 1];
+        row[mapping.ten] = Data::String("An".to_owned());
+        row[mapping.nam_sinh] = Data::Int(1980);
+        row[mapping.so_phieu] = Data::String("0001".to_owned());
+        row[mapping.qh_chu_ho] = Data::String("Chủ hộ".to_owned());
+        row
+    }
+
+    #[test]
+    fn short_rows_are_padded() {
+        let mapping = ColumnMapping::default();
+        let mut row = valid_row();
+        row.truncate(mapping.qh_chu_ho + 1);
+
+        let padded = check_row(&row, &mapping, 5, 0).unwrap();
+
+        assert_eq!(padded.len(), mapping.ghi_chu + 1);
+        assert_eq!(padded[mapping.ghi_chu], Data::Empty);
+    }
+
+    #[test]
+    fn missing_required_columns_are_reported() {
+        let mapping = ColumnMapping::default();
+        let mut row = valid_row();
+        row.truncate(mapping.so_phieu + 1);
+
+        let invalid = check_row(&row, &mapping, 5, 0).unwrap_err();
+
+        assert_eq!(invalid.problems.len(), 1);
+        assert_eq!(invalid.problems[0].field, "qh_chu_ho");
+        assert_eq!(invalid.problems[0].message, "dòng không đủ cột");
+    }
+
+    #[test]
+    fn empty_required_cells_are_reported() {
+        let mapping = ColumnMapping::default();
+        let mut row = valid_row();
+        row[mapping.ten] = Data::String("  ".to_owned());
+
+        let invalid = check_row(&row, &mapping, 5, 0).unwrap_err();
+
+        assert_eq!(invalid.problems.len(), 1);
+        assert_eq!(invalid.problems[0].field, "ten");
+        assert_eq!(invalid.problems[0].message, "ô bắt buộc bị trống");
+    }
+
+    #[test]
+    fn error_cells_are_reported_in_any_column() {
+        let mapping = ColumnMapping::default();
+        let mut row = valid_row();
+        row[mapping.ghi_chu] = Data::Error(calamine::CellErrorType::Ref);
+
+        let invalid = check_row(&row, &mapping, 5, 0).unwrap_err();
+
+        assert_eq!(invalid.problems.len(), 1);
+        assert_eq!(invalid.problems[0].field, "ghi_chu");
+        assert!(invalid.problems[0].message.starts_with("ô bị lỗi công thức"), "{}", invalid.problems[0].message);
+    }
+
+    #[test]
+    fn problems_use_excel_row_and_column_letter() {
+        let mapping = ColumnMapping::default();
+        let mut row = valid_row();
+        row[mapping.so_phieu] = Data::Empty;
+
+        let invalid = check_row(&row, &mapping, 5, 0).unwrap_err();
+        assert_eq!(invalid.row, 6);
+        assert_eq!(invalid.problems[0].column, "O");
+
+        let invalid = check_row(&row, &mapping, 5, 2).unwrap_err();
+        assert_eq!(invalid.problems[0].column, "Q");
+    }
+
+    #[test]
+    fn rows_without_birth_year_are_reported() {
+        let mapping = ColumnMapping::default();
+        let mut row = valid_row();
+        row[mapping.ngay_sinh] = Data::Int(1);
+        row[mapping.thang_sinh] = Data::Int(2);
+        row[mapping.nam_sinh] = Data::Empty;
+
+        let invalid = check_row(&row, &mapping, 5, 0).unwrap_err();
+
+        assert_eq!(invalid.problems.len(), 1);
+        assert_eq!(invalid.problems[0].field, "nam_sinh");
+        assert_eq!(invalid.problems[0].column, "G");
+    }
+
+    #[test]
+    fn full_birth_date_is_enough_without_year_column() {
+        let mapping = ColumnMapping::default();
+        let mut row = valid_row();
+        row[mapping.ngay_sinh] = Data::String("1/2/2010".to_owned());
+        row[mapping.nam_sinh] = Data::Empty;
+
+        assert!(check_row(&row, &mapping, 5, 0).is_ok());
+    }
+}
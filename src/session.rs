@@ -0,0 +1,189 @@
+use std::{fs, io::{self, BufRead}, path::Path};
+use colored::Colorize;
+use reqwest::{header::HeaderValue, Url};
+use serde::Deserialize;
+use crate::curl::{parse_copied_request, CopiedRequest};
+
+/// Cookie và CSRF token của một phiên đăng nhập cổng PCGD.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Session {
+    pub pcgd_csrf_token: String,
+    pub cookies: String,
+}
+
+/// Phần của file HAR (DevTools > Network > Export HAR) cần để lấy phiên đăng nhập.
+#[derive(Deserialize)]
+struct Har {
+    log: HarLog,
+}
+
+#[derive(Deserialize)]
+struct HarLog {
+    entries: Vec<HarEntry>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct HarEntry {
+    #[serde(default)]
+    started_date_time: String,
+    request: HarRequest,
+    response: Option<HarResponse>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct HarRequest {
+    #[serde(default)]
+    method: String,
+    url: String,
+    #[serde(default)]
+    headers: Vec<HarPair>,
+    #[serde(default)]
+    cookies: Vec<HarPair>,
+    post_data: Option<HarPostData>,
+}
+
+#[derive(Deserialize)]
+struct HarPostData {
+    #[serde(default)]
+    text: String,
+    #[serde(default)]
+    params: Vec<HarPair>,
+}
+
+#[derive(Deserialize)]
+struct HarPair {
+    name: String,
+    value: String,
+}
+
+#[derive(Deserialize)]
+struct HarResponse {
+    #[serde(default)]
+    status: u16,
+}
+
+/// Lấy CSRF token và cookie từ lệnh copy trong trình duyệt (cURL bash/cmd, PowerShell hoặc fetch).
+pub fn parse_curl(curl_content: &str) -> Result<Session, String> {
+    session_from_request(&parse_copied_request(curl_content)?)
+}
+
+/// Lấy phiên đăng nhập từ request mới nhất tới cổng PCGD (`base_url`) trong file HAR
+/// mà có đủ cookie, pcgd-csrf-token và không bị cổng từ chối.
+pub fn parse_har(har_content: &str, base_url: &str) -> Result<Session, String> {
+    let har: Har = serde_json::from_str(har_content)
+        .map_err(|error| format!("File HAR không hợp lệ: {}", error))?;
+
+    let host = url_host(base_url).unwrap_or_default();
+
+    let mut entries: Vec<HarEntry> = har.log.entries.into_iter()
+        .filter(|entry| url_host(&entry.request.url).as_deref() == Some(host.as_str()))
+        .collect();
+
+    if entries.is_empty() {
+        return Err(format!("File HAR không có request nào tới {}.", host));
+    }
+
+    entries.sort_by(|a, b| a.started_date_time.cmp(&b.started_date_time));
+
+    entries.iter()
+        .rev()
+        .filter(|entry| entry.response.as_ref().map(|response| response.status == 0 || (200..300).contains(&response.status)).unwrap_or(true))
+        .find_map(|entry| session_from_request(&har_request(&entry.request)).ok())
+        .ok_or_else(|| format!(
+            "File HAR không có request nào tới {} kèm cookie và pcgd-csrf-token. Hãy đăng nhập, thao tác trên cổng rồi xuất HAR kèm dữ liệu nhạy cảm (\"Export HAR (with sensitive data)\").",
+            host
+        ))
+}
+
+/// Đổi request trong HAR sang dạng request copy từ trình duyệt.
+fn har_request(request: &HarRequest) -> CopiedRequest {
+    let mut cookies: Vec<String> = request.cookies.iter()
+        .map(|cookie| format!("{}={}", cookie.name, cookie.value))
+        .collect();
+    let mut headers: Vec<(String, String)> = vec![];
+
+    for header in request.headers.iter() {
+        if !header.name.eq_ignore_ascii_case("cookie") {
+            headers.push((header.name.clone(), header.value.clone()));
+        } else if request.cookies.is_empty() {
+            cookies.push(header.value.clone());
+        }
+    }
+
+    let body = match &request.post_data {
+        Some(post_data) if post_data.text.is_empty() => form_urlencoded::Serializer::new(String::new())
+            .extend_pairs(post_data.params.iter().map(|param| (&param.name, &param.value)))
+            .finish(),
+        Some(post_data) => post_data.text.clone(),
+        None => String::new(),
+    };
+
+    CopiedRequest { url: request.url.clone(), method: request.method.clone(), headers, cookies, body }
+}
+
+fn url_host(url: &str) -> Option<String> {
+    Url::parse(url).ok()?.host_str().map(str::to_owned)
+}
+
+fn session_from_request(request: &CopiedRequest) -> Result<Session, String> {
+    let cookies = request.cookie_header();
+    if cookies.is_empty() {
+        return Err("Không tìm thấy cookie trong lệnh cURL (header Cookie hoặc tham số -b).".to_owned());
+    }
+    if HeaderValue::from_str(&cookies).is_err() {
+        return Err("Cookie trong lệnh cURL có ký tự không hợp lệ (xuống dòng, tab...). Hãy copy lại request từ trình duyệt.".to_owned());
+    }
+
+    let pcgd_csrf_token = request.form_value("pcgd-csrf-token")
+        .or_else(|| request.header("pcgd-csrf-token").map(str::to_owned))
+        .filter(|pcgd_csrf_token| !pcgd_csrf_token.is_empty())
+        .ok_or_else(|| "Không tìm thấy pcgd-csrf-token trong dữ liệu gửi đi của lệnh cURL. Hãy copy một request POST tới cổng PCGD.".to_owned())?;
+
+    Ok(Session { pcgd_csrf_token, cookies })
+}
+
+/// Đọc phiên đăng nhập từ file HAR (đuôi .har) hoặc file chứa lệnh cURL.
+pub fn read_session_file(path: &Path, base_url: &str) -> Result<Session, String> {
+    let content = fs::read_to_string(path)
+        .map_err(|error| format!("Không đọc được {}: {}", path.display(), error))?;
+
+    let is_har = path.extension()
+        .map(|extension| extension.eq_ignore_ascii_case("har"))
+        .unwrap_or(false);
+
+    if is_har {
+        parse_har(&content, base_url)
+    } else {
+        parse_curl(&content)
+    }
+}
+
+/// Hỏi người dùng dán lệnh cURL mới (kết thúc bằng một dòng trống) khi phiên đăng nhập hết hạn.
+/// Trả về `None` nếu người dùng bỏ trống để dừng.
+pub fn prompt_curl_session() -> Option<Session> {
+    println!("{}", "> Phiên đăng nhập cổng PCGD đã hết hạn.".yellow().bold());
+
+    loop {
+        println!("{} Đăng nhập lại, copy lệnh cURL mới rồi dán vào đây, kết thúc bằng một dòng trống (bỏ trống để dừng):", ">".green().bold());
+
+        let lines: Vec<String> = io::stdin().lock()
+            .lines()
+            .map_while(Result::ok)
+            .take_while(|line| !line.trim().is_empty())
+            .collect();
+
+        if lines.is_empty() {
+            return None;
+        }
+
+        match parse_curl(&lines.join("\n")) {
+            Ok(session) => {
+                println!("{}", "> Đã nhận phiên đăng nhập mới, đang tiếp tục...".green().bold());
+                return Some(session);
+            },
+            Err(error) => println!("{}", format!("> {}", error).red().bold()),
+        }
+    }
+}
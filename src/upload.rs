@@ -0,0 +1,717 @@
+use std::{fmt, path::PathBuf, str::FromStr, thread, time::Duration};
+use colored::Colorize;
+use inquire::Select;
+use serde::{Deserialize, Serialize};
+use crate::backup::{backup_members, BACKUP_DIR};
+use crate::checkpoint::Checkpoint;
+use crate::household_info::{Household, ResidentFields, ValueFieldHouseOwner, ValueFieldHouseResident, ValueFieldHouseResident2024Education, ValueFieldHouseResidentGeneralEducation};
+use crate::merge::{changed_fields, fill_from_portal, is_marked_for_deletion, match_members, member_changes, owner_changes, resident_key, MemberMatch, MemberPolicy};
+use crate::pcgd_client::{GridRow, PcgdClient, PcgdError};
+use crate::prompt::confirm;
+
+/// Số lần thử lại khi cách xử lý lỗi là "retry" mà không ghi số lần.
+const DEFAULT_RETRIES: u32 = 3;
+
+/// Số hộ và thành viên đã tải lên thành công, cùng các mục bị bỏ qua vì lỗi.
+#[derive(Debug, Default)]
+pub struct UploadSummary {
+    /// Số hộ đã tải lên đủ chủ hộ và mọi thành viên.
+    pub households: usize,
+    /// Số hộ đã có phiếu trên cổng nhưng còn thành viên bị bỏ qua.
+    pub partial: usize,
+    pub residents: usize,
+    pub skipped: Vec<SkippedItem>,
+    /// Thành viên khác dữ liệu trên cổng nhưng không được thay, kèm các trường khác nhau.
+    pub conflicts: Vec<SkippedItem>,
+    /// Công việc đã dừng giữa chừng (chọn dừng khi lỗi hoặc phiên đăng nhập hết hạn).
+    pub stopped: bool,
+}
+
+/// Một hộ hoặc thành viên bị bỏ qua vì lỗi, để báo cáo khi kết thúc.
+#[derive(Debug, Clone)]
+pub struct SkippedItem {
+    pub so_phieu: String,
+    /// Họ tên thành viên, `None` khi bỏ qua cả hộ.
+    pub ho_ten: Option<String>,
+    pub reason: String,
+}
+
+/// Cách xử lý khi gửi lên cổng bị lỗi: "ask", "stop", "skip-household", "skip-resident" hoặc "retry:N".
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(try_from = "String", into = "String")]
+pub enum ErrorPolicy {
+    /// Hỏi thử lại, bỏ qua hay dừng (tự bỏ qua khi bật `assume_yes`).
+    #[default]
+    Ask,
+    /// Dừng ngay ở lỗi đầu tiên.
+    Stop,
+    /// Bỏ qua cả hộ đang xử lý rồi làm tiếp hộ sau.
+    SkipHousehold,
+    /// Chỉ bỏ qua thành viên bị lỗi; lỗi ở chủ hộ, tra cứu hay xoá vẫn bỏ qua cả hộ.
+    SkipResident,
+    /// Thử lại request tối đa N lần khi lỗi có thể tự hết (mất kết nối, lỗi máy chủ), sau đó bỏ qua như `SkipResident`.
+    Retry(u32),
+}
+
+impl FromStr for ErrorPolicy {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, String> {
+        let text = text.trim().to_lowercase().replace('_', "-");
+
+        match text.as_str() {
+            "ask" => Ok(ErrorPolicy::Ask),
+            "stop" => Ok(ErrorPolicy::Stop),
+            "skip-household" => Ok(ErrorPolicy::SkipHousehold),
+            "skip-resident" => Ok(ErrorPolicy::SkipResident),
+            "retry" => Ok(ErrorPolicy::Retry(DEFAULT_RETRIES)),
+            _ => text.strip_prefix("retry:")
+                .and_then(|times| times.parse().ok())
+                .filter(|times| *times > 0)
+                .map(ErrorPolicy::Retry)
+                .ok_or_else(|| format!("Cách xử lý lỗi \"{}\" không hợp lệ (chọn ask, stop, skip-household, skip-resident hoặc retry:N).", text)),
+        }
+    }
+}
+
+impl fmt::Display for ErrorPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ErrorPolicy::Ask => write!(f, "ask"),
+            ErrorPolicy::Stop => write!(f, "stop"),
+            ErrorPolicy::SkipHousehold => write!(f, "skip-household"),
+            ErrorPolicy::SkipResident => write!(f, "skip-resident"),
+            ErrorPolicy::Retry(times) => write!(f, "retry:{}", times),
+        }
+    }
+}
+
+impl TryFrom<String> for ErrorPolicy {
+    type Error = String;
+
+    fn try_from(text: String) -> Result<Self, String> {
+        text.parse()
+    }
+}
+
+impl From<ErrorPolicy> for String {
+    fn from(policy: ErrorPolicy) -> Self {
+        policy.to_string()
+    }
+}
+
+/// Việc cần làm sau khi một bước bị lỗi.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decision {
+    Retry,
+    SkipResident,
+    SkipHousehold,
+    Stop,
+}
+
+/// Tuỳ chọn cho một lần tải lên.
+#[derive(Debug, Clone)]
+pub struct UploadOptions {
+    /// Tự đồng ý mọi câu hỏi xác nhận, dùng khi chạy không có người theo dõi.
+    pub assume_yes: bool,
+    /// Cách xử lý thành viên của hộ đã có trên cổng.
+    pub member_policy: MemberPolicy,
+    /// Thư mục lưu bản sao thành viên trước khi xoá trên cổng.
+    pub backup_dir: PathBuf,
+    /// Cách xử lý khi gửi lên cổng bị lỗi.
+    pub on_error: ErrorPolicy,
+}
+
+impl Default for UploadOptions {
+    fn default() -> Self {
+        UploadOptions {
+            assume_yes: false,
+            member_policy: MemberPolicy::default(),
+            backup_dir: PathBuf::from(BACKUP_DIR),
+            on_error: ErrorPolicy::default(),
+        }
+    }
+}
+
+/// Tải lên từng hộ: tạo phiếu (hoặc đối chiếu với hộ đã có trên cổng) rồi thêm các thành viên.
+/// Các hộ và thành viên đã ghi trong điểm dừng được bỏ qua, để chạy lại không tạo trùng.
+/// Lỗi ở mỗi bước được xử lý theo `options.on_error`; hộ và thành viên bị bỏ qua được ghi vào `skipped`.
+pub fn upload_households(client: &PcgdClient, households: Vec<Household>, checkpoint: &mut Checkpoint, options: &UploadOptions) -> UploadSummary {
+    let mut summary = UploadSummary::default();
+    let merging = options.member_policy != MemberPolicy::Replace;
+
+    'households: for mut household in households {
+        let progress = checkpoint.household(&household.so_phieu).cloned().unwrap_or_default();
+
+        if progress.completed {
+            println!("{}", format!("> Hộ {} đã được tải lên ở lần chạy trước, bỏ qua.", household.so_phieu).green().bold());
+            summary.households += 1;
+            summary.residents += progress.residents_added.len();
+            continue;
+        }
+
+        let mut ma_phieu = progress.ma_phieu.clone();
+        let mut existing = !ma_phieu.is_empty();
+
+        if ma_phieu.is_empty() {
+            loop {
+                let error = match create_phieu(client, &mut household.owner, options) {
+                    Ok(created_ma_phieu) => {
+                        println!("{}", format!("{} \"{} {}\" {}", "> Tải lên thành công hộ gia đình", household.owner.chuho_hodem, household.owner.chuho_ten, household.owner.so_phieu).green().bold());
+                        println!("{}", created_ma_phieu);
+
+                        ma_phieu = created_ma_phieu;
+                        break;
+                    },
+                    Err(error) if error.is_duplicate_so_phieu() => {
+                        existing = true;
+                        println!("{}", format!("{} \"{} {}\" {} {}", "> Hộ gia đình", household.owner.chuho_hodem, household.owner.chuho_ten, household.owner.so_phieu, "đã tồn tại, đang sửa lại dữ liệu...").yellow().bold());
+
+                        match update_existing_household(client, &mut household, options) {
+                            Ok(existing_ma_phieu) => {
+                                ma_phieu = existing_ma_phieu;
+                                break;
+                            },
+                            Err(StepError::Portal(PcgdError::SessionExpired)) => {
+                                stop_on_expired_session(&household.so_phieu);
+                                summary.stopped = true;
+                                return summary;
+                            },
+                            Err(error) => {
+                                println!("{}", format!("> Có lỗi khi sửa lại hộ {}: {}", household.so_phieu, error).red().bold());
+                                error.to_string()
+                            },
+                        }
+                    },
+                    Err(PcgdError::SessionExpired) => {
+                        stop_on_expired_session(&household.so_phieu);
+                        summary.stopped = true;
+                        return summary;
+                    },
+                    Err(error) => {
+                        println!("{}", format!("{} \"{} {}\" {}", "> Có lỗi khi tải lên hộ gia đình\n\nThông tin debug:", household.owner.chuho_hodem, household.owner.chuho_ten, household.owner.so_phieu).red().bold());
+
+                        println!("{:#?}", household.owner);
+                        println!("{}", error);
+
+                        println!("{}", "> Kết thúc thông tin debug.".red().bold());
+                        error.to_string()
+                    },
+                };
+
+                match decide(options, false) {
+                    Decision::Retry => continue,
+                    Decision::Stop => {
+                        summary.stopped = true;
+                        return summary;
+                    },
+                    _ => {
+                        skip_household(&mut summary, &household.so_phieu, error);
+                        continue 'households;
+                    },
+                }
+            }
+
+            checkpoint.set_ma_phieu(&household.so_phieu, &ma_phieu);
+        } else {
+            println!("{}", format!("> Tiếp tục hộ {} (mã phiếu {}) từ lần chạy trước.", household.so_phieu, ma_phieu).green().bold());
+        }
+
+        let members = if merging && existing {
+            loop {
+                let matched = with_retries(options, || client.list_doituong(&ma_phieu))
+                    .map_err(StepError::from)
+                    .and_then(|portal_members| {
+                        let members = match_members(&household.residents, portal_members.clone());
+
+                        if will_delete(&household.residents, &members, options) {
+                            let path = backup_members(&options.backup_dir, &household.so_phieu, &ma_phieu, &portal_members).map_err(StepError::Backup)?;
+                            println!("{}", format!("> Đã sao lưu {} thành viên của hộ {} vào {}", portal_members.len(), household.so_phieu, path.display()).green().bold());
+                        }
+
+                        Ok(members)
+                    });
+
+                let error = match matched {
+                    Ok(members) => break members,
+                    Err(StepError::Portal(PcgdError::SessionExpired)) => {
+                        stop_on_expired_session(&household.so_phieu);
+                        summary.stopped = true;
+                        return summary;
+                    },
+                    Err(error) => error.to_string(),
+                };
+                println!("{}", format!("> Không lấy được thành viên của hộ {}: {}", household.so_phieu, error).red().bold());
+
+                match decide(options, false) {
+                    Decision::Retry => continue,
+                    Decision::Stop => {
+                        summary.stopped = true;
+                        return summary;
+                    },
+                    _ => {
+                        skip_household(&mut summary, &household.so_phieu, error);
+                        continue 'households;
+                    },
+                }
+            }
+        } else {
+            MemberMatch::default()
+        };
+
+        let mut all_residents_added = true;
+
+        for (index, resident) in household.residents.iter_mut().enumerate() {
+            if progress.residents_added.contains(&index) {
+                summary.residents += 1;
+                continue;
+            }
+
+            resident.0.update_ma_phieu(ma_phieu.clone());
+
+            let mut portal_member = members.matched.get(index).cloned().flatten();
+
+            loop {
+                let error = match upload_resident(client, &household.so_phieu, resident, &mut portal_member, options) {
+                    Ok(ResidentResult::Done(message)) => {
+                        summary.residents += 1;
+                        checkpoint.add_resident(&household.so_phieu, index);
+                        println!("{}", format!("> {}", message).green().bold());
+                        break;
+                    },
+                    Ok(ResidentResult::Conflict(reason)) => {
+                        all_residents_added = false;
+                        println!("{}", format!("> \"{}\" trong hộ {} khác dữ liệu trên cổng, giữ nguyên bản trên cổng.", resident.0.ho_ten, household.so_phieu).yellow().bold());
+                        summary.conflicts.push(SkippedItem { so_phieu: household.so_phieu.clone(), ho_ten: Some(resident.0.ho_ten.clone()), reason });
+                        break;
+                    },
+                    Err(PcgdError::SessionExpired) => {
+                        stop_on_expired_session(&household.so_phieu);
+                        summary.stopped = true;
+                        return summary;
+                    },
+                    Err(error) => error,
+                };
+
+                println!("{}", format!("> Có lỗi khi thêm \"{}\" vào hộ {}\n\n Thông tin debug:\n", resident.0.ho_ten, household.so_phieu).red().bold());
+
+                println!("{:#?}", resident.0);
+                println!("{:#?}", resident.1);
+                println!("{:#?}", resident.2);
+                println!("{}", error);
+
+                println!("{}", "> Kết thúc thông tin debug.".red().bold());
+
+                match decide(options, true) {
+                    Decision::Retry => continue,
+                    Decision::Stop => {
+                        summary.stopped = true;
+                        return summary;
+                    },
+                    Decision::SkipResident => {
+                        all_residents_added = false;
+                        summary.skipped.push(SkippedItem { so_phieu: household.so_phieu.clone(), ho_ten: Some(resident.0.ho_ten.clone()), reason: error.to_string() });
+                        break;
+                    },
+                    Decision::SkipHousehold => {
+                        skip_household(&mut summary, &household.so_phieu, error.to_string());
+                        continue 'households;
+                    },
+                }
+            }
+        }
+
+        if !members.unmatched.is_empty() {
+            loop {
+                let error = match handle_unmatched(client, &household.so_phieu, &ma_phieu, &members.unmatched, options) {
+                    Ok(()) => break,
+                    Err(PcgdError::SessionExpired) => {
+                        stop_on_expired_session(&household.so_phieu);
+                        summary.stopped = true;
+                        return summary;
+                    },
+                    Err(error) => error,
+                };
+                println!("{}", format!("> Có lỗi khi xoá thành viên của hộ {}: {}", household.so_phieu, error).red().bold());
+
+                match decide(options, false) {
+                    Decision::Retry => continue,
+                    Decision::Stop => {
+                        summary.stopped = true;
+                        return summary;
+                    },
+                    _ => {
+                        skip_household(&mut summary, &household.so_phieu, error.to_string());
+                        continue 'households;
+                    },
+                }
+            }
+        }
+
+        if all_residents_added {
+            summary.households += 1;
+            checkpoint.complete(&household.so_phieu);
+        } else {
+            summary.partial += 1;
+        }
+    }
+
+    summary
+}
+
+/// Kết quả xử lý một thành viên: đã xong, hoặc khác dữ liệu trên cổng mà không được thay (kèm các trường khác nhau).
+enum ResidentResult {
+    Done(String),
+    Conflict(String),
+}
+
+/// Thêm hoặc xoá một thành viên tuỳ theo thành viên khớp trên cổng và đánh dấu xoá trong bảng tính.
+/// Cổng không có API sửa đối tượng, nên thành viên khác dữ liệu chỉ được thay (xoá bản cũ đã sao lưu rồi thêm lại)
+/// khi dùng `MergeAskDelete` và được xác nhận, còn lại là xung đột. Sau khi xoá, `portal_member` được bỏ đi
+/// để lần thử lại chỉ thêm lại chứ không xoá lần nữa.
+fn upload_resident(client: &PcgdClient, so_phieu: &str, resident: &mut ResidentFields, portal_member: &mut Option<GridRow>, options: &UploadOptions) -> Result<ResidentResult, PcgdError> {
+    let ho_ten = resident.0.ho_ten.clone();
+    let ma_phieu = resident.0.ma_phieu.clone().unwrap_or_default();
+
+    match (is_marked_for_deletion(&resident.0), portal_member.as_ref()) {
+        (true, Some(row)) => {
+            delete_members(client, &ma_phieu, std::slice::from_ref(&row.id), options)?;
+            Ok(ResidentResult::Done(format!("Đã xoá \"{}\" khỏi hộ {}", ho_ten, so_phieu)))
+        },
+        (true, None) => Ok(ResidentResult::Done(format!("Bỏ qua \"{}\" (đánh dấu xoá nhưng không có trên cổng)", ho_ten))),
+        (false, Some(row)) => {
+            fill_from_portal(resident, row);
+            let changes = changed_fields(resident, row);
+
+            if changes.is_empty() {
+                return Ok(ResidentResult::Done(format!("\"{}\" trong hộ {} không thay đổi", ho_ten, so_phieu)));
+            }
+
+            for (field, portal, ours) in changes.iter() {
+                println!("  {}: \"{}\" -> \"{}\"", field, portal, ours);
+            }
+
+            let question = format!("Cổng không sửa được thành viên. Xoá \"{}\" (mất mã đối tượng và lịch sử trên cổng) rồi thêm lại theo bảng tính?", ho_ten);
+            if options.member_policy != MemberPolicy::MergeAskDelete || !confirm(&question, options.assume_yes) {
+                let fields: Vec<String> = changes.iter().map(|(field, portal, ours)| format!("{}: \"{}\" -> \"{}\"", field, portal, ours)).collect();
+                return Ok(ResidentResult::Conflict(format!("khác trên cổng ({})", fields.join("; "))));
+            }
+
+            delete_members(client, &ma_phieu, std::slice::from_ref(&row.id), options)?;
+            *portal_member = None;
+
+            add_member(client, &resident.0, &resident.1, &resident.2, options)?;
+            Ok(ResidentResult::Done(format!("Đã thay \"{}\" trong hộ {} (xoá bản cũ rồi thêm lại)", ho_ten, so_phieu)))
+        },
+        (false, None) => {
+            add_member(client, &resident.0, &resident.1, &resident.2, options)?;
+            Ok(ResidentResult::Done(format!("Đã thêm \"{}\" vào hộ {}", ho_ten, so_phieu)))
+        },
+    }
+}
+
+/// Thành viên chỉ có trên cổng: giữ lại, hoặc xoá sau khi được xác nhận.
+fn handle_unmatched(client: &PcgdClient, so_phieu: &str, ma_phieu: &str, unmatched: &[GridRow], options: &UploadOptions) -> Result<(), PcgdError> {
+    println!("{}", format!("> Hộ {} có {} thành viên trên cổng không có trong bảng tính:", so_phieu, unmatched.len()).yellow().bold());
+    for row in unmatched.iter() {
+        println!("- {} ({})", row.text("ho_ten"), row.text("ngay_sinh"));
+    }
+
+    if options.member_policy != MemberPolicy::MergeAskDelete || !confirm("Xoá các thành viên này khỏi cổng?", options.assume_yes) {
+        println!("{}", "> Giữ lại các thành viên này.".yellow().bold());
+        return Ok(());
+    }
+
+    let ids: Vec<String> = unmatched.iter().map(|row| row.id.clone()).collect();
+    delete_members(client, ma_phieu, &ids, options)?;
+
+    println!("{}", format!("> Đã xoá {} thành viên khỏi hộ {}", ids.len(), so_phieu).green().bold());
+    Ok(())
+}
+
+/// Hộ có thể bị xoá thành viên trên cổng: có thành viên khớp với cổng bị đánh dấu xoá, hoặc với `MergeAskDelete`
+/// có thể thay thành viên khác dữ liệu (xoá rồi thêm lại) hay xoá thành viên chỉ có trên cổng.
+fn will_delete(residents: &[ResidentFields], members: &MemberMatch, options: &UploadOptions) -> bool {
+    let ask_delete = options.member_policy == MemberPolicy::MergeAskDelete;
+    let marked = residents.iter()
+        .zip(members.matched.iter())
+        .any(|(resident, matched)| match matched {
+            Some(row) => is_marked_for_deletion(&resident.0) || (ask_delete && !member_changes(resident, row).is_empty()),
+            None => false,
+        });
+
+    marked || (ask_delete && !members.unmatched.is_empty())
+}
+
+/// Tìm đúng phiếu của hộ đã tồn tại, cập nhật thông tin chủ hộ rồi (khi thay thế) xoá thành viên cũ.
+fn update_existing_household(client: &PcgdClient, household: &mut Household, options: &UploadOptions) -> Result<String, StepError> {
+    let row = with_retries(options, || client.find_phieu(&household.owner))?;
+
+    update_owner(client, &mut household.owner, &row)?;
+
+    if options.member_policy == MemberPolicy::Replace {
+        clear_members(client, &household.so_phieu, &row.id, options)?;
+        println!("{}", "> Hoàn thành lọc thành viên, đang thêm vào...".green().bold());
+    }
+
+    Ok(row.id)
+}
+
+/// In các trường chủ hộ khác với trên cổng rồi gửi bản cập nhật kèm mã phiếu đã có.
+fn update_owner(client: &PcgdClient, owner: &mut ValueFieldHouseOwner, row: &GridRow) -> Result<(), PcgdError> {
+    let changes = owner_changes(owner, row);
+
+    if changes.is_empty() {
+        println!("{}", format!("> Thông tin chủ hộ của phiếu {} không thay đổi.", owner.so_phieu).green().bold());
+        return Ok(());
+    }
+
+    println!("{}", format!("> Thông tin chủ hộ của phiếu {} thay đổi:", owner.so_phieu).yellow().bold());
+    for (field, portal, ours) in changes.iter() {
+        println!("  {}: \"{}\" -> \"{}\"", field, portal, ours);
+    }
+
+    owner.ma_phieu = row.id.clone();
+    client.update_phieu(owner)?;
+
+    println!("{}", format!("> Đã cập nhật chủ hộ của phiếu {}", owner.so_phieu).green().bold());
+    Ok(())
+}
+
+/// Sao lưu rồi xoá hết thành viên hiện có của phiếu trên cổng.
+fn clear_members(client: &PcgdClient, so_phieu: &str, ma_phieu: &str, options: &UploadOptions) -> Result<(), StepError> {
+    let members = with_retries(options, || client.list_doituong(ma_phieu))?;
+    if members.is_empty() {
+        return Ok(());
+    }
+
+    let path = backup_members(&options.backup_dir, so_phieu, ma_phieu, &members).map_err(StepError::Backup)?;
+    println!("{}", format!("> Đã sao lưu {} thành viên của hộ {} vào {}", members.len(), so_phieu, path.display()).green().bold());
+
+    let mut doituong = with_retries(options, || client.lay_doituong(ma_phieu, 10, 1))?;
+
+    while doituong.records != 0 && !doituong.rows.is_empty() {
+        let ids: Vec<String> = doituong.rows.iter().map(|row| row.id.clone()).collect();
+
+        for id in ids.iter() {
+            println!("{}", format!("> Thiết lập {}", id).green().bold());
+        }
+
+        match delete_members(client, ma_phieu, &ids, options) {
+            Ok(()) => println!("{}", format!("> Đã lọc {} thành viên", ids.len()).green().bold()),
+            Err(error) => {
+                println!("{}", error);
+                return Err(error.into());
+            },
+        }
+
+        doituong = with_retries(options, || client.lay_doituong(ma_phieu, 10, 1))?;
+    }
+
+    Ok(())
+}
+
+/// Lỗi của một bước xử lý hộ: lỗi từ cổng được giữ nguyên để nhận ra phiên hết hạn, hoặc lỗi khi sao lưu.
+enum StepError {
+    Portal(PcgdError),
+    Backup(String),
+}
+
+impl From<PcgdError> for StepError {
+    fn from(error: PcgdError) -> Self {
+        StepError::Portal(error)
+    }
+}
+
+impl fmt::Display for StepError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StepError::Portal(error) => write!(f, "{}", error),
+            StepError::Backup(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+/// Phiên hết hạn mà không có phiên mới thì mọi request sau đều lỗi, nên dừng hẳn; điểm dừng đã được lưu.
+fn stop_on_expired_session(so_phieu: &str) {
+    println!("{}", format!("> Phiên đăng nhập đã hết hạn ở hộ {}. Đã dừng công việc, chạy lại với lệnh cURL mới để tiếp tục từ hộ này.", so_phieu).red().bold());
+}
+
+/// Gửi lại request khi lỗi có thể tự hết (mất kết nối, lỗi máy chủ), tối đa N lần với cách xử lý "retry:N".
+/// Lỗi do dữ liệu hay phiên hết hạn thì trả về ngay vì gửi lại cũng không khác.
+pub fn with_retries<T>(options: &UploadOptions, mut request: impl FnMut() -> Result<T, PcgdError>) -> Result<T, PcgdError> {
+    let times = match options.on_error {
+        ErrorPolicy::Retry(times) => times,
+        _ => 0,
+    };
+
+    let mut attempt = 0;
+    loop {
+        match request() {
+            Err(error) if error.is_transient() && attempt < times => {
+                attempt += 1;
+                println!("{}", format!("> {}. Thử lại lần {}/{}...", error, attempt, times).yellow().bold());
+                thread::sleep(Duration::from_millis(500 * attempt as u64));
+            },
+            result => return result,
+        }
+    }
+}
+
+fn retry_times(options: &UploadOptions) -> u32 {
+    match options.on_error {
+        ErrorPolicy::Retry(times) => times,
+        _ => 0,
+    }
+}
+
+/// Gửi một request ghi. Lỗi có thể tự hết không có nghĩa là cổng chưa ghi (máy chủ có thể lỗi sau khi đã lưu),
+/// nên trước khi gửi lại phải hỏi lại cổng bằng `landed`: đã ghi thì coi như thành công, chắc chắn chưa ghi
+/// mới gửi lại (tối đa N lần với "retry:N"), không hỏi được thì báo lỗi ban đầu.
+fn send_write<T>(
+    options: &UploadOptions,
+    mut send: impl FnMut() -> Result<T, PcgdError>,
+    mut landed: impl FnMut() -> Result<Option<T>, PcgdError>,
+) -> Result<T, PcgdError> {
+    let times = retry_times(options);
+    let mut attempt = 0;
+
+    loop {
+        let error = match send() {
+            Err(error) if error.is_transient() => error,
+            result => return result,
+        };
+
+        match with_retries(options, &mut landed) {
+            Ok(Some(value)) => {
+                println!("{}", format!("> {}. Cổng đã ghi nhận request, không gửi lại.", error).yellow().bold());
+                return Ok(value);
+            },
+            Ok(None) if attempt < times => {
+                attempt += 1;
+                println!("{}", format!("> {}. Cổng chưa ghi nhận, gửi lại lần {}/{}...", error, attempt, times).yellow().bold());
+                thread::sleep(Duration::from_millis(500 * attempt as u64));
+            },
+            _ => return Err(error),
+        }
+    }
+}
+
+/// Tạo phiếu mới cho chủ hộ, trả về mã phiếu. Phiếu đã có từ trước thì trả về `PhieuExists` mà không gửi.
+/// Sau lỗi thì tìm lại phiếu theo số phiếu trước khi gửi lại; vì trước đó chưa có phiếu nên phiếu tìm được là phiếu vừa tạo.
+pub fn create_phieu(client: &PcgdClient, owner: &mut ValueFieldHouseOwner, options: &UploadOptions) -> Result<String, PcgdError> {
+    let lookup = owner.clone();
+
+    match with_retries(options, || client.find_phieu(&lookup)) {
+        Ok(row) => return Err(PcgdError::PhieuExists(lookup.so_phieu.clone(), row.id)),
+        Err(PcgdError::PhieuNotFound(_)) => {},
+        Err(error) => return Err(error),
+    }
+
+    send_write(options, || client.update_phieu(owner), || match client.find_phieu(&lookup) {
+        Ok(row) => Ok(Some(row.id)),
+        Err(PcgdError::PhieuNotFound(_)) => Ok(None),
+        Err(error) => Err(error),
+    })
+}
+
+/// Thêm một thành viên vào phiếu đã ghi trong `resident`. Sau lỗi thì xem phiếu đã có thành viên
+/// cùng họ tên, ngày sinh chưa trước khi gửi lại.
+pub fn add_member(
+    client: &PcgdClient,
+    resident: &ValueFieldHouseResident,
+    education: &ValueFieldHouseResidentGeneralEducation,
+    education_2024: &ValueFieldHouseResident2024Education,
+    options: &UploadOptions,
+) -> Result<(), PcgdError> {
+    let ma_phieu = resident.ma_phieu.clone().unwrap_or_default();
+    let key = resident_key(&resident.ho_ten, &resident.ngay_sinh);
+
+    send_write(options, || client.add_doituong(resident, education, education_2024), || {
+        let members = client.list_doituong(&ma_phieu)?;
+        Ok(members.iter().any(|row| resident_key(&row.text("ho_ten"), &row.text("ngay_sinh")) == key).then_some(()))
+    })
+}
+
+/// Xoá các thành viên của phiếu `ma_phieu`. Sau lỗi thì xem các id còn trên cổng không trước khi gửi lại.
+pub fn delete_members(client: &PcgdClient, ma_phieu: &str, ids: &[String], options: &UploadOptions) -> Result<(), PcgdError> {
+    send_write(options, || client.delete_doituong(ids), || {
+        let members = client.list_doituong(ma_phieu)?;
+        Ok((!members.iter().any(|row| ids.contains(&row.id))).then_some(()))
+    })
+}
+
+/// Sau một lỗi (đã thử lại nếu có): thử lại, bỏ qua thành viên hoặc hộ, hay dừng, theo cách xử lý lỗi đã chọn.
+/// `resident` cho biết lỗi xảy ra ở một thành viên, khi đó mới có thể chỉ bỏ qua thành viên đó.
+pub fn decide(options: &UploadOptions, resident: bool) -> Decision {
+    let skip = if resident { Decision::SkipResident } else { Decision::SkipHousehold };
+
+    let decision = match options.on_error {
+        ErrorPolicy::Stop => Decision::Stop,
+        ErrorPolicy::SkipHousehold => Decision::SkipHousehold,
+        ErrorPolicy::SkipResident | ErrorPolicy::Retry(_) => skip,
+        ErrorPolicy::Ask if options.assume_yes => skip,
+        ErrorPolicy::Ask => {
+            let mut choices = vec![("Thử lại", Decision::Retry)];
+            if resident {
+                choices.push(("Bỏ qua thành viên này", Decision::SkipResident));
+            }
+            choices.push(("Bỏ qua hộ này", Decision::SkipHousehold));
+            choices.push(("Dừng công việc", Decision::Stop));
+
+            let labels: Vec<&str> = choices.iter().map(|(label, _)| *label).collect();
+            match Select::new("Bạn muốn làm gì tiếp?", labels).raw_prompt() {
+                Ok(choice) => choices[choice.index].1,
+                Err(_) => Decision::Stop,
+            }
+        },
+    };
+
+    match decision {
+        Decision::Retry => println!("{} Đang thử lại...", ">".yellow().bold()),
+        Decision::SkipResident => println!("{} Bỏ qua thành viên này, đang tiếp tục...", ">".yellow().bold()),
+        Decision::SkipHousehold => println!("{} Bỏ qua hộ này, đang tiếp tục...", ">".yellow().bold()),
+        Decision::Stop => println!("{}", "> Đã dừng công việc.".red().bold()),
+    }
+
+    decision
+}
+
+fn skip_household(summary: &mut UploadSummary, so_phieu: &str, reason: String) {
+    summary.skipped.push(SkippedItem { so_phieu: so_phieu.to_owned(), ho_ten: None, reason });
+}
+
+/// In số hộ chỉ tải lên được một phần, cần chạy lại để thêm nốt thành viên.
+pub fn print_partial(partial: usize) {
+    if partial > 0 {
+        println!("{}", format!("> Có {} hộ đã tạo phiếu nhưng còn thành viên chưa được thêm.", partial).yellow().bold());
+    }
+}
+
+/// In danh sách thành viên khác dữ liệu trên cổng nhưng chưa được thay.
+pub fn print_conflicts(conflicts: &[SkippedItem]) {
+    if conflicts.is_empty() {
+        return;
+    }
+
+    println!("{}", format!("> Có {} thành viên khác dữ liệu trên cổng, chưa được cập nhật:", conflicts.len()).yellow().bold());
+    for item in conflicts.iter() {
+        println!("- Thành viên \"{}\" của hộ {}: {}", item.ho_ten.as_deref().unwrap_or_default(), item.so_phieu, item.reason);
+    }
+    println!("{}", "> Sửa các thành viên này trực tiếp trên cổng, hoặc chạy lại với merge-ask-delete để xoá bản cũ rồi thêm lại.".yellow());
+}
+
+/// In danh sách hộ và thành viên bị bỏ qua vì lỗi, để sửa rồi chạy lại.
+pub fn print_skipped(skipped: &[SkippedItem]) {
+    if skipped.is_empty() {
+        return;
+    }
+
+    println!("{}", format!("> Có {} mục bị bỏ qua vì lỗi:", skipped.len()).red().bold());
+    for item in skipped.iter() {
+        match &item.ho_ten {
+            Some(ho_ten) => println!("- Thành viên \"{}\" của hộ {}: {}", ho_ten, item.so_phieu, item.reason),
+            None => println!("- Hộ {}: {}", item.so_phieu, item.reason),
+        }
+    }
+}
@@ -0,0 +1,499 @@
+use std::{collections::{BTreeMap, BTreeSet, HashMap}, path::{Path, PathBuf}};
+use calamine::{open_workbook_auto, Error, Reader};
+use colored::Colorize;
+use inquire::Select;
+use crate::admin_code::{portal_villages, AdminCode};
+use crate::cell_value;
+use crate::checkpoint::Checkpoint;
+use crate::column_mapping::{column_letter, ColumnMapping, COLUMN_MAPPING_FILE};
+use crate::household_info::{Household, ResidentFields, ValueFieldHouseOwner, ValueFieldHouseResident, ValueFieldHouseResident2024Education, ValueFieldHouseResidentGeneralEducation};
+use crate::journal::{read_journal, write_journal, JournalEntry};
+use crate::pcgd_client::PcgdClient;
+use crate::plan::{apply_plan, build_plan, print_plan, read_plan, write_plan};
+use crate::prompt::confirm;
+use crate::row_validation::{check_row, is_empty_row, CellProblem, InvalidRow, InvalidRowPolicy};
+use crate::upload::{print_conflicts, print_partial, print_skipped, upload_households, UploadOptions};
+
+pub enum RunMode {
+    Upload,
+    /// Chỉ ghi các request sẽ gửi vào file nhật ký, không kết nối tới cổng PCGD.
+    DryRun(PathBuf),
+    /// Gửi các request trong file nhật ký đã được duyệt.
+    Replay,
+    /// Thêm lại thành viên từ file sao lưu vào một phiếu.
+    Restore,
+    /// So sánh bảng tính với dữ liệu trên cổng và ghi kế hoạch thay đổi ra file, không gửi thay đổi nào.
+    Plan(PathBuf),
+    /// Thực hiện kế hoạch đã lập.
+    Apply,
+    /// Tải các hộ trên cổng về file bảng tính theo mẫu MauNhapLieu.
+    Export,
+}
+
+/// Kết quả một lần chạy, để dòng lệnh trả mã lỗi khi công việc chưa xong.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunOutcome {
+    Done,
+    /// Công việc đã dừng giữa chừng: người dùng không đồng ý, chọn dừng khi lỗi, phiên hết hạn...
+    Stopped,
+    /// Chạy hết nhưng còn số dòng, hộ, thành viên hoặc thao tác bị bỏ qua vì lỗi.
+    Incomplete(usize),
+}
+
+impl RunOutcome {
+    /// Dừng giữa chừng là `Stopped`, còn mục bị bỏ qua là `Incomplete`.
+    pub fn from_counts(stopped: bool, skipped: usize) -> RunOutcome {
+        match (stopped, skipped) {
+            (true, _) => RunOutcome::Stopped,
+            (false, 0) => RunOutcome::Done,
+            (false, skipped) => RunOutcome::Incomplete(skipped),
+        }
+    }
+
+    /// Chuyển thành lỗi cho dòng lệnh khi công việc chưa xong.
+    pub fn into_result(self) -> Result<(), String> {
+        match self {
+            RunOutcome::Done => Ok(()),
+            RunOutcome::Stopped => Err("Công việc đã dừng trước khi hoàn tất.".to_owned()),
+            RunOutcome::Incomplete(skipped) => Err(format!("Có {} mục bị bỏ qua vì lỗi.", skipped)),
+        }
+    }
+}
+
+pub const SUPPORTED_EXTENSIONS: [&str; 5] = ["xls", "xlsx", "xlsm", "xlsb", "ods"];
+
+/// Thông tin cho một lần đọc bảng tính và tải lên.
+pub struct WorkbookJob<'a> {
+    pub file: PathBuf,
+    pub mapping: &'a ColumnMapping,
+    pub mode: RunMode,
+    pub ngay_dieutra: String,
+    /// Mã xã của các hộ (kèm thôn mặc định nếu có), lấy từ hồ sơ hoặc đầu số phiếu.
+    /// Thôn của từng hộ lấy theo cột mã thôn hoặc số phiếu trước, xem `AdminCode::village_for`.
+    pub admin_code: AdminCode,
+    pub pcgd_csrf_token: String,
+    pub checkpoint_file: PathBuf,
+    pub options: UploadOptions,
+    /// Cách xử lý các dòng lỗi trong bảng tính.
+    pub invalid_rows: InvalidRowPolicy,
+    /// Vẫn chạy khi cổng chưa có phiếu nào của thôn của một số hộ để đối chiếu mã, không hỏi lại.
+    pub allow_unverified_code: bool,
+}
+
+pub fn workbook_reader(job: &WorkbookJob, client: Option<&PcgdClient>) -> Result<RunOutcome, Error> {
+    let file = &job.file;
+    let mapping = job.mapping;
+    let mode = &job.mode;
+    let ngay_dieutra = job.ngay_dieutra.as_str();
+    let pcgd_csrf_token = job.pcgd_csrf_token.as_str();
+
+    let extension = file.extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| extension.to_lowercase())
+        .unwrap_or_default();
+
+    if !SUPPORTED_EXTENSIONS.contains(&extension.as_str()) {
+        return Err(Error::Msg("Định dạng file không được hỗ trợ (chỉ nhận xls, xlsx, xlsm, xlsb, ods)."));
+    }
+
+    let mut workbook = open_workbook_auto(file)?;
+
+    let admin_code = &job.admin_code;
+
+    let mut so_chu_ho = 0;
+    let mut so_thanh_vien = 0;
+    let mut skipped_rows = 0;
+
+    if !workbook.sheet_names().iter().any(|name| name == "MauNhapLieu") {
+        return Err(Error::Msg("File bảng tính không có sheet MauNhapLieu."));
+    }
+
+    let range = workbook.worksheet_range("MauNhapLieu")?;
+    let (mapping, data_start) = if mapping.detect_headers {
+        match mapping.detect(&range) {
+            Ok(detected) => detected,
+            Err(error) => {
+                println!("{}", format!("> Không nhận diện được tiêu đề cột trong sheet MauNhapLieu:\n{}", error).red().bold());
+                println!("{}", format!("> Sửa lại tiêu đề, hoặc đặt \"detect_headers\": false trong {} để đọc theo vị trí cột cố định.", COLUMN_MAPPING_FILE).yellow());
+                println!("{}", "> Đã dừng công việc.".red().bold());
+                return Ok(RunOutcome::Stopped);
+            },
+        }
+    } else {
+        println!("{}", format!("> Đọc theo vị trí cột cố định trong {} (detect_headers: false).", COLUMN_MAPPING_FILE).yellow());
+        (mapping.clone(), mapping.header_rows)
+    };
+    let mapping = &mapping;
+
+    let mut houses_owners: HashMap<String, Vec<ValueFieldHouseOwner>> = HashMap::new();
+    let mut houses_residents: HashMap<String, Vec<ResidentFields>> = HashMap::new();
+
+    let (first_row, first_column) = range.start().unwrap_or((0, 0));
+    let rows = range.rows();
+    let rows_data = rows.enumerate().skip(data_start);
+    let mut invalid_rows: Vec<InvalidRow> = vec![];
+
+    println!("{} Đang thiết lập mẫu dữ liệu...", ">".green().bold());
+
+    for (row, col) in rows_data {
+        if is_empty_row(col) {
+            continue;
+        }
+
+        let col = match check_row(col, mapping, first_row as usize + row, first_column as usize) {
+            Ok(col) => col,
+            Err(invalid_row) => {
+                invalid_rows.push(invalid_row);
+                continue;
+            },
+        };
+        let col = col.as_slice();
+
+        if cell_value::text(&col[mapping.qh_chu_ho]).to_lowercase() == "chủ hộ" {
+            let thon_xom = mapping.thon_xom.map(|column| cell_value::code(&col[column])).unwrap_or_default();
+
+            let village = match admin_code.village_for(&thon_xom, &cell_value::code(&col[mapping.so_phieu])) {
+                Ok(village) => village,
+                Err(message) => {
+                    let (field, column) = match mapping.thon_xom {
+                        Some(column) => ("thon_xom", column),
+                        None => ("so_phieu", mapping.so_phieu),
+                    };
+                    invalid_rows.push(InvalidRow {
+                        row: first_row as usize + row + 1,
+                        problems: vec![CellProblem { column: column_letter(first_column as usize + column), field: field.to_owned(), message }],
+                    });
+                    continue;
+                },
+            };
+
+            let household_owner = ValueFieldHouseOwner::new(
+                col,
+                mapping,
+                ngay_dieutra.to_string(),
+                &village,
+                pcgd_csrf_token.to_string()
+            );
+
+            houses_owners.entry(cell_value::code(&col[mapping.so_phieu]))
+                .or_default()
+                .push(household_owner);
+
+            so_chu_ho += 1;
+        }
+        
+        let household_resident = ValueFieldHouseResident::new(col, mapping);
+        let resident_education = ValueFieldHouseResidentGeneralEducation::new(col, mapping);
+        let resident_2024_education = ValueFieldHouseResident2024Education::new(
+            col,
+            mapping,
+            admin_code
+        );
+
+        houses_residents.entry(cell_value::code(&col[mapping.so_phieu]))
+        .or_default()
+        .push((
+            household_resident,
+            resident_education,
+            resident_2024_education
+        ));
+
+        so_thanh_vien += 1;
+    }
+
+    println!("{} Đã dựng được {} chủ hộ và {} thành viên.", ">".green().bold(), so_chu_ho, so_thanh_vien);
+
+    if !invalid_rows.is_empty() {
+        println!("{}", format!("> Đã phát hiện {} dòng bị lỗi:", invalid_rows.len()).red().bold());
+        for invalid_row in invalid_rows.iter() {
+            for problem in invalid_row.problems.iter() {
+                println!("Dòng {}, cột {} ({}): {}", invalid_row.row, problem.column, problem.field, problem.message);
+            }
+        }
+
+        let skip_invalid_rows = match job.invalid_rows {
+            InvalidRowPolicy::Skip => true,
+            InvalidRowPolicy::Stop => false,
+            InvalidRowPolicy::Ask if job.options.assume_yes => true,
+            InvalidRowPolicy::Ask => {
+                let options = vec!["Bỏ qua các dòng lỗi", "Dừng công việc"];
+                matches!(Select::new("Xử lý các dòng lỗi như thế nào?", options).prompt(), Ok("Bỏ qua các dòng lỗi"))
+            },
+        };
+
+        if !skip_invalid_rows {
+            println!("{}", "> Đã dừng công việc.".red().bold());
+            return Ok(RunOutcome::Stopped);
+        }
+
+        println!("{} Đã bỏ qua {} dòng lỗi.", ">".yellow().bold(), invalid_rows.len());
+        skipped_rows = invalid_rows.len();
+    }
+
+    let mut mismatches: Vec<&String> = vec![];
+
+    for token in houses_residents.keys() {
+        if !houses_owners.contains_key(token) {
+            mismatches.push(token);
+        }
+    }
+
+    if !mismatches.is_empty() {
+        println!("{} Đã phát hiện {} phiếu sau đây không có chủ hộ:", ">".green().bold(), mismatches.len());
+        for mismatch in mismatches.iter().enumerate() {
+            println!("{}. {}", mismatch.0 + 1, mismatch.1);
+        }
+    }
+
+    let mut households: Vec<Household> = houses_owners.into_iter()
+        .map(|(so_phieu, mut owners)| Household {
+            residents: houses_residents.remove(&so_phieu).unwrap_or_default(),
+            owner: owners.remove(0),
+            so_phieu,
+        })
+        .collect();
+    households.sort_by(|a, b| (&a.owner.ma_thonxom, &a.so_phieu).cmp(&(&b.owner.ma_thonxom, &b.so_phieu)));
+
+    print_villages(&households);
+    print_village_overrides(&households, admin_code);
+
+    if let Some(client) = client {
+        if !check_villages(client, &households, job) {
+            println!("{}", "> Đã dừng công việc.".red().bold());
+            return Ok(RunOutcome::Stopped);
+        }
+    }
+
+    if !confirm("Tiếp tục công việc?", job.options.assume_yes) {
+        println!("{}", "> Đã dừng công việc.".red().bold());
+        return Ok(RunOutcome::Stopped);
+    }
+
+    println!("{} Đang thêm...", ">".green().bold());
+
+    if let RunMode::DryRun(journal_file) = mode {
+        let mut entries: Vec<JournalEntry> = vec![];
+
+        for household in households {
+            entries.push(JournalEntry::house_owner(household.owner));
+
+            for resident in household.residents {
+                entries.push(JournalEntry::house_resident(household.so_phieu.clone(), resident.0, resident.1, resident.2));
+            }
+        }
+
+        if let Err(error) = write_journal(journal_file, &entries) {
+            println!("{}", format!("> Không ghi được file {}: {}", journal_file.display(), error).red().bold());
+            return Ok(RunOutcome::Stopped);
+        }
+
+        println!("{}", format!("> Đã ghi {} request vào {}, chưa có dữ liệu nào được gửi đi.", entries.len(), journal_file.display()).green().bold());
+        return Ok(RunOutcome::from_counts(false, skipped_rows));
+    }
+
+    if let RunMode::Plan(plan_file) = mode {
+        let client = match client {
+            Some(client) => client,
+            None => return Ok(RunOutcome::Stopped),
+        };
+
+        println!("{} Đang tải dữ liệu trên cổng để so sánh...", ">".green().bold());
+
+        let plan = match build_plan(client, households, job.options.member_policy) {
+            Ok(plan) => plan,
+            Err(error) => {
+                println!("{}", format!("> Không lập được kế hoạch: {}", error).red().bold());
+                return Ok(RunOutcome::Stopped);
+            },
+        };
+
+        print_plan(&plan);
+
+        if let Err(error) = write_plan(plan_file, &plan) {
+            println!("{}", format!("> {}", error).red().bold());
+            return Ok(RunOutcome::Stopped);
+        }
+
+        println!("{}", format!("> Đã ghi kế hoạch vào {}, chưa có thay đổi nào được gửi đi.", plan_file.display()).green().bold());
+        return Ok(RunOutcome::from_counts(false, skipped_rows + plan.skipped.len() + plan.conflicts.len()));
+    }
+
+    let mut checkpoint = match load_checkpoint(&job.checkpoint_file, file) {
+        Some(checkpoint) => checkpoint,
+        None => return Ok(RunOutcome::Stopped),
+    };
+
+    let client = match client {
+        Some(client) => client,
+        None => return Ok(RunOutcome::Stopped),
+    };
+
+    let summary = upload_households(client, households, &mut checkpoint, &job.options);
+
+    println!("{}", format!("> Đã thêm {}/{} hộ và {}/{} thành viên và các hộ.", summary.households, so_chu_ho, summary.residents, so_thanh_vien).green().bold());
+    print_partial(summary.partial);
+    print_skipped(&summary.skipped);
+    print_conflicts(&summary.conflicts);
+
+    Ok(RunOutcome::from_counts(summary.stopped, skipped_rows + summary.skipped.len() + summary.conflicts.len()))
+}
+
+/// Đối chiếu thôn của từng hộ với các thôn đã có phiếu trên cổng. Thôn chưa có phiếu nào thì chưa kiểm tra được mã:
+/// chỉ chạy tiếp khi có `allow_unverified_code` hoặc người ngồi ở terminal đồng ý, lần chạy với `assume_yes` thì dừng.
+fn check_villages(client: &PcgdClient, households: &[Household], job: &WorkbookJob) -> bool {
+    let villages: BTreeSet<&str> = households.iter().map(|household| household.owner.ma_thonxom.as_str()).collect();
+
+    if villages.is_empty() {
+        return true;
+    }
+
+    let portal = match portal_villages(client, &job.admin_code) {
+        Ok(portal) => portal,
+        Err(error) => {
+            println!("{} Không tải được danh sách phiếu của xã {} để kiểm tra mã thôn: {}", ">".yellow().bold(), job.admin_code.commune(), error);
+            return accept_unverified_code(job);
+        },
+    };
+
+    let unverified: Vec<&str> = villages.into_iter().filter(|village| !portal.contains(*village)).collect();
+
+    if unverified.is_empty() {
+        println!("{} Các thôn trong bảng tính đều đã có phiếu trên cổng PCGD.", ">".green().bold());
+        return true;
+    }
+
+    println!("{} Cổng PCGD chưa có phiếu nào của các thôn sau, chưa kiểm tra được mã có đúng không: {}", ">".yellow().bold(), unverified.join(", "));
+    accept_unverified_code(job)
+}
+
+fn accept_unverified_code(job: &WorkbookJob) -> bool {
+    if job.allow_unverified_code {
+        return true;
+    }
+
+    if job.options.assume_yes {
+        println!("{}", "> Kiểm tra lại mã rồi chạy lại với --allow-unverified-code nếu mã đúng.".red().bold());
+        return false;
+    }
+
+    confirm("Vẫn dùng mã chưa kiểm tra được?", false)
+}
+
+/// In số hộ và thành viên của từng thôn, theo thứ tự mã thôn.
+fn print_villages(households: &[Household]) {
+    let mut villages: BTreeMap<&str, (usize, usize)> = BTreeMap::new();
+
+    for household in households.iter() {
+        let counts = villages.entry(household.owner.ma_thonxom.as_str()).or_default();
+        counts.0 += 1;
+        counts.1 += household.residents.len();
+    }
+
+    println!("{} Số hộ theo thôn:", ">".green().bold());
+    for (ma_thonxom, (so_ho, so_thanh_vien)) in villages {
+        println!("{}: {} hộ, {} thành viên", ma_thonxom, so_ho, so_thanh_vien);
+    }
+}
+
+/// Cảnh báo các hộ có thôn (lấy theo cột mã thôn hoặc số phiếu) khác thôn đã chọn trong hồ sơ hoặc đầu số phiếu.
+fn print_village_overrides(households: &[Household], admin_code: &AdminCode) {
+    let ma_thonxom = match admin_code.ma_thonxom() {
+        Some(ma_thonxom) => ma_thonxom,
+        None => return,
+    };
+
+    let overrides: Vec<&Household> = households.iter().filter(|household| household.owner.ma_thonxom != ma_thonxom).collect();
+
+    if overrides.is_empty() {
+        return;
+    }
+
+    println!("{} {} hộ có thôn khác thôn đã chọn {} (theo cột mã thôn hoặc số phiếu):", ">".yellow().bold(), overrides.len(), ma_thonxom);
+    for household in overrides {
+        println!("{}: {}", household.so_phieu, household.owner.ma_thonxom);
+    }
+}
+
+/// Đọc điểm dừng của file nguồn để tiếp tục lần tải lên bị gián đoạn.
+fn load_checkpoint(checkpoint_file: &Path, source_file: &Path) -> Option<Checkpoint> {
+    match Checkpoint::load(checkpoint_file, source_file) {
+        Ok(checkpoint) => {
+            if checkpoint.recorded_households() > 0 {
+                println!("{} Tìm thấy điểm dừng của {} hộ trong {}, sẽ tiếp tục từ chỗ đã dừng.", ">".yellow().bold(), checkpoint.recorded_households(), checkpoint_file.display());
+            }
+            Some(checkpoint)
+        },
+        Err(error) => {
+            println!("{}", format!("> {}", error).red().bold());
+            None
+        },
+    }
+}
+
+/// Gửi lên cổng PCGD đúng các request trong file nhật ký, không cần tới file bảng tính.
+pub fn journal_replayer(file: &Path, client: &PcgdClient, pcgd_csrf_token: &str, checkpoint_file: &Path, options: &UploadOptions) -> RunOutcome {
+    let households = match read_journal(file, pcgd_csrf_token) {
+        Ok(households) => households,
+        Err(error) => {
+            println!("{}", format!("> {}", error).red().bold());
+            return RunOutcome::Stopped;
+        },
+    };
+
+    let so_chu_ho = households.len();
+    let so_thanh_vien: usize = households.iter().map(|household| household.residents.len()).sum();
+
+    println!("{} File nhật ký có {} hộ và {} thành viên.", ">".green().bold(), so_chu_ho, so_thanh_vien);
+
+    if !confirm("Tiếp tục công việc?", options.assume_yes) {
+        println!("{}", "> Đã dừng công việc.".red().bold());
+        return RunOutcome::Stopped;
+    }
+
+    println!("{} Đang thêm...", ">".green().bold());
+
+    let mut checkpoint = match load_checkpoint(checkpoint_file, file) {
+        Some(checkpoint) => checkpoint,
+        None => return RunOutcome::Stopped,
+    };
+
+    let summary = upload_households(client, households, &mut checkpoint, options);
+
+    println!("{}", format!("> Đã thêm {}/{} hộ và {}/{} thành viên và các hộ.", summary.households, so_chu_ho, summary.residents, so_thanh_vien).green().bold());
+    print_partial(summary.partial);
+    print_skipped(&summary.skipped);
+    print_conflicts(&summary.conflicts);
+
+    RunOutcome::from_counts(summary.stopped, summary.skipped.len() + summary.conflicts.len())
+}
+
+/// Thực hiện kế hoạch trong file đã lập bằng chế độ so sánh.
+pub fn plan_applier(file: &Path, client: &PcgdClient, options: &UploadOptions) -> RunOutcome {
+    let plan = match read_plan(file) {
+        Ok(plan) => plan,
+        Err(error) => {
+            println!("{}", format!("> {}", error).red().bold());
+            return RunOutcome::Stopped;
+        },
+    };
+
+    print_plan(&plan);
+
+    if plan.actions.is_empty() {
+        println!("{}", "> Kế hoạch không có thay đổi nào.".green().bold());
+        return RunOutcome::Done;
+    }
+
+    if !confirm("Thực hiện kế hoạch này?", options.assume_yes) {
+        println!("{}", "> Đã dừng công việc.".red().bold());
+        return RunOutcome::Stopped;
+    }
+
+    let total = plan.actions.len();
+    let summary = apply_plan(client, plan, options);
+
+    println!("{}", format!("> Đã thực hiện {}/{} thao tác, {} thao tác bị lỗi.", summary.done, total, summary.failed).green().bold());
+    print_skipped(&summary.skipped);
+
+    RunOutcome::from_counts(summary.stopped, summary.failed)
+}
@@ -0,0 +1,127 @@
+mod common;
+
+use std::collections::BTreeMap;
+use common::{job, mapping, person, sample_rows, temp_dir, write_workbook, TOKEN};
+use common::mock_server::{MockPhieu, MockServer};
+use pcgd_bulk::admin_code::{portal_villages, AdminCode};
+use pcgd_bulk::pcgd_client::PcgdClient;
+use pcgd_bulk::workbook::{workbook_reader, RunMode, RunOutcome};
+
+fn start() -> (MockServer, PcgdClient) {
+    let server = MockServer::start("127.0.0.1:0", TOKEN).unwrap();
+    let client = PcgdClient::new(&server.base_url, "PHPSESSID=test", TOKEN);
+    (server, client)
+}
+
+#[test]
+fn admin_code_parses_and_formats() {
+    let code: AdminCode = "01_001_00001_1_".parse().unwrap();
+    assert_eq!(code.ma_tinh(), "01");
+    assert_eq!(code.ma_quanhuyen(), "01_001");
+    assert_eq!(code.ma_phuongxa(), "01_001_00001");
+    assert_eq!(code.ma_thonxom().as_deref(), Some("01_001_00001_1"));
+    assert_eq!(code.to_string(), "01_001_00001_1");
+    assert_eq!(code.prefix(), "01_001_00001_1_");
+
+    let commune: AdminCode = "01_001_00001".parse().unwrap();
+    assert_eq!(commune, code.commune());
+    assert_eq!(commune.ma_thonxom(), None);
+    assert_eq!(commune.prefix(), "01_001_00001_");
+
+    for nonsense in ["", "01", "01_001", "1_001_00001_1", "01_001_0001_1", "01_0a1_00001_1", "01__00001_1", "01_001_00001_1_2"] {
+        assert!(nonsense.parse::<AdminCode>().is_err(), "{}", nonsense);
+    }
+}
+
+#[test]
+fn village_comes_from_column_then_so_phieu_then_prefix() {
+    let commune: AdminCode = "01_001_00001".parse().unwrap();
+    let village: AdminCode = "01_001_00001_1".parse().unwrap();
+
+    assert_eq!(commune.village_for("2", "0001").unwrap().to_string(), "01_001_00001_2");
+    assert_eq!(village.village_for("01_001_00001_3", "01_001_00001_2_0001").unwrap().to_string(), "01_001_00001_3");
+    assert_eq!(village.village_for("", "01_001_00001_2_0001").unwrap().to_string(), "01_001_00001_2");
+    assert_eq!(village.village_for("", "0001").unwrap(), village);
+
+    assert!(commune.village_for("", "0001").is_err());
+    assert!(commune.village_for("01_001_00002_1", "0001").is_err());
+    assert!(commune.village_for("01_001_00001", "0001").is_err());
+}
+
+fn add_phieu(server: &MockServer, so_phieu: &str, ma_thonxom: &str) {
+    let mut parts = ma_thonxom.split('_');
+    let (tinh, huyen, xa) = (parts.next().unwrap(), parts.next().unwrap(), parts.next().unwrap());
+    let fields: BTreeMap<String, String> = [
+        ("so_phieu", so_phieu.to_owned()),
+        ("ma_tinh", tinh.to_owned()),
+        ("ma_quanhuyen", format!("{}_{}", tinh, huyen)),
+        ("ma_phuongxa", format!("{}_{}_{}", tinh, huyen, xa)),
+        ("ma_thonxom", ma_thonxom.to_owned()),
+    ]
+    .into_iter()
+    .map(|(field, value)| (field.to_owned(), value))
+    .collect();
+
+    let mut state = server.state.lock().unwrap();
+    let ma_phieu = (state.phieu.len() + 1).to_string();
+    state.phieu.push(MockPhieu { ma_phieu, fields });
+}
+
+#[test]
+fn villages_are_taken_from_phieu_on_the_portal() {
+    let (server, client) = start();
+    add_phieu(&server, "01_001_00001_1_0001", "01_001_00001_1");
+    add_phieu(&server, "01_001_00001_1_0002", "01_001_00001_1");
+    add_phieu(&server, "01_001_00001_2_0001", "01_001_00001_2");
+    add_phieu(&server, "01_001_00002_3_0001", "01_001_00002_3");
+
+    let villages = portal_villages(&client, &"01_001_00001_1".parse().unwrap()).unwrap();
+    assert_eq!(villages.into_iter().collect::<Vec<_>>(), ["01_001_00001_1", "01_001_00001_2"]);
+
+    assert!(portal_villages(&client, &"01_001_00009".parse().unwrap()).unwrap().is_empty());
+
+    server.state.lock().unwrap().failures.insert("/doing/phieudieutra/lay_phieu".to_owned(), 1);
+    assert!(portal_villages(&client, &"01_001_00001".parse().unwrap()).is_err());
+}
+
+#[test]
+fn unverified_code_stops_unattended_runs_unless_allowed() {
+    let dir = temp_dir("admin-unverified");
+    let workbook = dir.join("input.xlsx");
+    write_workbook(&workbook, &sample_rows());
+    let (server, client) = start();
+    let mapping = mapping();
+
+    let mut job = job(workbook, &mapping, RunMode::Upload, &dir, TOKEN);
+    job.allow_unverified_code = false;
+
+    assert_eq!(workbook_reader(&job, Some(&client)).unwrap(), RunOutcome::Stopped);
+    assert!(server.state.lock().unwrap().phieu.is_empty());
+
+    add_phieu(&server, "01_001_00001_1_9999", "01_001_00001_1");
+    assert_eq!(workbook_reader(&job, Some(&client)).unwrap(), RunOutcome::Done);
+    assert!(server.state.lock().unwrap().phieu.len() > 1);
+}
+
+#[test]
+fn villages_taken_from_rows_are_checked_too() {
+    let dir = temp_dir("admin-row-villages");
+    let workbook = dir.join("input.xlsx");
+    write_workbook(&workbook, &[
+        person("0001", "Nguyễn Văn", "An", (1, 2, 1980), "Chủ hộ"),
+        person("01_001_00001_2_0001", "Lê Thị", "Dung", (7, 8, 1975), "Chủ hộ"),
+    ]);
+    let (server, client) = start();
+    add_phieu(&server, "01_001_00001_1_9999", "01_001_00001_1");
+    let mapping = mapping();
+
+    let mut job = job(workbook, &mapping, RunMode::Upload, &dir, TOKEN);
+    job.allow_unverified_code = false;
+
+    assert_eq!(workbook_reader(&job, Some(&client)).unwrap(), RunOutcome::Stopped);
+    assert_eq!(server.state.lock().unwrap().phieu.len(), 1);
+
+    add_phieu(&server, "01_001_00001_2_9999", "01_001_00001_2");
+    assert_eq!(workbook_reader(&job, Some(&client)).unwrap(), RunOutcome::Done);
+    assert!(server.state.lock().unwrap().phieu_by_so_phieu("01_001_00001_2_0001").is_some());
+}
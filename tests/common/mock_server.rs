@@ -0,0 +1,369 @@
+use std::{collections::BTreeMap, sync::{Arc, Mutex}, thread::{self, JoinHandle}};
+use base64::prelude::*;
+use serde_json::{json, Map, Value};
+use tiny_http::{Header, Request, Response, Server};
+
+type Fields = BTreeMap<String, String>;
+
+/// Các trường ngày tháng, được cổng lưu và trả về dạng yyyy-mm-dd.
+const DATE_FIELDS: [&str; 2] = ["ngay_sinh", "ngay_dieutra"];
+
+/// Các cột của danh sách thành viên: lay_doituong chỉ trả về các cột hiển thị trong danh sách,
+/// không phải mọi trường đã gửi khi thêm đối tượng.
+pub const DOITUONG_LIST_COLUMNS: [&str; 5] = ["ho_ten", "ngay_sinh", "gioi_tinh", "ma_dantoc", "qh_chu_ho"];
+
+/// Các cột mã số, được cổng trả về dạng số.
+const NUMERIC_FIELDS: [&str; 2] = ["id", "ma_phieu"];
+
+/// Một phiếu điều tra trên cổng giả lập, các trường đã được giải mã base64.
+#[derive(Debug, Clone)]
+pub struct MockPhieu {
+    pub ma_phieu: String,
+    pub fields: BTreeMap<String, String>,
+}
+
+/// Một đối tượng (thành viên) trên cổng giả lập, tương ứng data1, data2 và data_dtht đã giải mã.
+#[derive(Debug, Clone)]
+pub struct MockDoiTuong {
+    pub id: String,
+    pub ma_phieu: String,
+    pub fields: BTreeMap<String, String>,
+    pub education: BTreeMap<String, String>,
+    pub education_2024: BTreeMap<String, String>,
+}
+
+/// Dữ liệu trong bộ nhớ của cổng giả lập.
+#[derive(Debug, Default)]
+pub struct MockState {
+    pub phieu: Vec<MockPhieu>,
+    pub doituong: Vec<MockDoiTuong>,
+    /// Số request tiếp theo bị trả lỗi HTTP 500, theo đường dẫn, để thử các cách xử lý lỗi.
+    pub failures: BTreeMap<String, usize>,
+    /// Số request tiếp theo được ghi nhận nhưng vẫn trả lỗi HTTP 500, theo đường dẫn, như khi máy chủ lỗi sau khi đã lưu.
+    pub lost_replies: BTreeMap<String, usize>,
+    /// Số request tiếp theo nhận trang đăng nhập như khi phiên hết hạn, theo đường dẫn.
+    pub expired: BTreeMap<String, usize>,
+    next_id: u64,
+}
+
+impl MockState {
+    pub fn phieu_by_so_phieu(&self, so_phieu: &str) -> Option<&MockPhieu> {
+        self.phieu.iter().find(|phieu| phieu.fields.get("so_phieu").map(String::as_str) == Some(so_phieu))
+    }
+
+    pub fn doituong_of(&self, ma_phieu: &str) -> Vec<&MockDoiTuong> {
+        self.doituong.iter().filter(|doituong| doituong.ma_phieu == ma_phieu).collect()
+    }
+
+    fn next_id(&mut self) -> String {
+        self.next_id += 1;
+        self.next_id.to_string()
+    }
+}
+
+/// Cổng PCGD giả lập các endpoint mà công cụ sử dụng, để chạy thử từ đầu đến cuối không cần cổng thật.
+/// Dữ liệu trả về có dạng như cổng thật (ngày yyyy-mm-dd, mã số dạng số) chứ không lặp lại nguyên văn dữ liệu gửi lên.
+pub struct MockServer {
+    pub base_url: String,
+    pub state: Arc<Mutex<MockState>>,
+    handle: JoinHandle<()>,
+}
+
+impl MockServer {
+    /// Mở cổng giả lập tại địa chỉ cho trước (dùng cổng 0 để hệ điều hành tự chọn).
+    /// Mọi request phải mang đúng `pcgd_csrf_token`, nếu không sẽ nhận trang đăng nhập.
+    pub fn start(address: &str, pcgd_csrf_token: &str) -> Result<MockServer, String> {
+        let server = Server::http(address)
+            .map_err(|error| format!("Không mở được cổng giả lập tại {}: {}", address, error))?;
+
+        let base_url = match server.server_addr().to_ip() {
+            Some(socket) => format!("http://{}", socket),
+            None => return Err(format!("Địa chỉ {} không phải địa chỉ IP", address)),
+        };
+
+        let state = Arc::new(Mutex::new(MockState::default()));
+        let thread_state = Arc::clone(&state);
+        let pcgd_csrf_token = pcgd_csrf_token.to_owned();
+
+        let handle = thread::spawn(move || {
+            for request in server.incoming_requests() {
+                handle_request(request, &thread_state, &pcgd_csrf_token);
+            }
+        });
+
+        Ok(MockServer { base_url, state, handle })
+    }
+
+    /// Chờ cho tới khi cổng giả lập dừng.
+    pub fn wait(self) {
+        let _ = self.handle.join();
+    }
+}
+
+fn handle_request(mut request: Request, state: &Mutex<MockState>, pcgd_csrf_token: &str) {
+    let mut body = String::new();
+    let _ = request.as_reader().read_to_string(&mut body);
+
+    let url = request.url().to_owned();
+    let (path, query) = url.split_once('?').unwrap_or((url.as_str(), ""));
+    let form: Vec<(String, String)> = form_urlencoded::parse(body.as_bytes()).into_owned().collect();
+    let query: Vec<(String, String)> = form_urlencoded::parse(query.as_bytes()).into_owned().collect();
+
+    let mut state = state.lock().unwrap();
+
+    let expired = state.expired.get_mut(path).filter(|remaining| **remaining > 0).map(|remaining| *remaining -= 1).is_some();
+
+    if expired || form_value(&form, "pcgd-csrf-token") != Some(pcgd_csrf_token) {
+        let page = "<!DOCTYPE html><html><head><title>Đăng nhập</title></head><body><form action=\"/login\"></form></body></html>";
+        let response = Response::from_string(page)
+            .with_status_code(403)
+            .with_header(content_type("text/html; charset=utf-8"));
+        let _ = request.respond(response);
+        return;
+    }
+
+    if let Some(remaining) = state.failures.get_mut(path).filter(|remaining| **remaining > 0) {
+        *remaining -= 1;
+        let response = Response::from_string("<html><body>500 Internal Server Error</body></html>")
+            .with_status_code(500)
+            .with_header(content_type("text/html; charset=utf-8"));
+        let _ = request.respond(response);
+        return;
+    }
+
+    let reply = match path {
+        "/doing/phieudieutra/update" => update_phieu(&mut state, &form),
+        "/doing/phieudieutra/lay_phieu" => lay_phieu(&state, &form),
+        "/doing/doituong/lay_doituong" => lay_doituong(&state, &form, form_value(&query, "phieu").unwrap_or("")),
+        "/doing/doituong/delete" => delete_doituong(&mut state, &form),
+        "/doing/doituong/add" => add_doituong(&mut state, &form),
+        _ => {
+            let response = Response::from_string("<html><body>404 Not Found</body></html>")
+                .with_status_code(404)
+                .with_header(content_type("text/html; charset=utf-8"));
+            let _ = request.respond(response);
+            return;
+        },
+    };
+
+    if let Some(remaining) = state.lost_replies.get_mut(path).filter(|remaining| **remaining > 0) {
+        *remaining -= 1;
+        let response = Response::from_string("<html><body>500 Internal Server Error</body></html>")
+            .with_status_code(500)
+            .with_header(content_type("text/html; charset=utf-8"));
+        let _ = request.respond(response);
+        return;
+    }
+
+    let response = Response::from_string(reply.to_string())
+        .with_header(content_type("application/json; charset=utf-8"));
+    let _ = request.respond(response);
+}
+
+fn update_phieu(state: &mut MockState, form: &[(String, String)]) -> Value {
+    let fields = match form_value(form, "data").and_then(decode_object) {
+        Some(fields) => portal_fields(fields),
+        None => return error_reply("data", "Dữ liệu không hợp lệ."),
+    };
+
+    let so_phieu = fields.get("so_phieu").cloned().unwrap_or_default();
+    let ma_phieu = fields.get("ma_phieu").cloned().unwrap_or_default();
+
+    if so_phieu.is_empty() {
+        return error_reply("so_phieu", "Số phiếu không được để trống.");
+    }
+
+    let duplicate = state.phieu.iter().any(|phieu| {
+        phieu.ma_phieu != ma_phieu
+            && phieu.fields.get("so_phieu") == Some(&so_phieu)
+            && phieu.fields.get("ma_phuongxa") == fields.get("ma_phuongxa")
+    });
+
+    if duplicate {
+        return error_reply("so_phieu", &format!("Số phiếu {} đã tồn tại.", so_phieu));
+    }
+
+    if ma_phieu.is_empty() {
+        let ma_phieu = state.next_id();
+        let mut fields = fields;
+        fields.insert("ma_phieu".to_owned(), ma_phieu.clone());
+        state.phieu.push(MockPhieu { ma_phieu: ma_phieu.clone(), fields });
+
+        return json!({ "result": "success", "ma_phieu": portal_value("ma_phieu", &ma_phieu) });
+    }
+
+    match state.phieu.iter_mut().find(|phieu| phieu.ma_phieu == ma_phieu) {
+        Some(phieu) => {
+            phieu.fields = fields;
+            json!({ "result": "success", "ma_phieu": portal_value("ma_phieu", &ma_phieu) })
+        },
+        None => error_reply("ma_phieu", &format!("Phiếu {} không tồn tại.", ma_phieu)),
+    }
+}
+
+fn lay_phieu(state: &MockState, form: &[(String, String)]) -> Value {
+    let filters = [("ma_tinh", "tinh"), ("ma_quanhuyen", "quanhuyen"), ("ma_phuongxa", "phuongxa")];
+    let tukhoa = form_value(form, "tukhoa").unwrap_or("").to_lowercase();
+
+    let mut matches: Vec<&MockPhieu> = state.phieu.iter()
+        .filter(|phieu| filters.iter().all(|(field, parameter)| match form_value(form, parameter) {
+            Some(value) if !value.is_empty() => phieu.fields.get(*field).map(String::as_str) == Some(value),
+            _ => true,
+        }))
+        .filter(|phieu| {
+            tukhoa.is_empty() || ["so_phieu", "chuho_hodem", "chuho_ten"].iter()
+                .any(|field| phieu.fields.get(*field).map(|value| value.to_lowercase().contains(&tukhoa)).unwrap_or(false))
+        })
+        .collect();
+
+    matches.sort_by(|a, b| a.fields.get("so_phieu").cmp(&b.fields.get("so_phieu")));
+
+    let rows: Vec<Value> = matches.iter()
+        .map(|phieu| grid_row(&phieu.ma_phieu, [&phieu.fields]))
+        .collect();
+
+    grid_reply(rows, form)
+}
+
+fn lay_doituong(state: &MockState, form: &[(String, String)], ma_phieu: &str) -> Value {
+    let rows: Vec<Value> = state.doituong_of(ma_phieu).iter()
+        .map(|doituong| {
+            let columns: Fields = doituong.fields.iter()
+                .filter(|(field, _)| DOITUONG_LIST_COLUMNS.contains(&field.as_str()))
+                .map(|(field, value)| (field.clone(), value.clone()))
+                .collect();
+            grid_row(&doituong.id, [&columns])
+        })
+        .collect();
+
+    grid_reply(rows, form)
+}
+
+fn delete_doituong(state: &mut MockState, form: &[(String, String)]) -> Value {
+    let ids: Vec<&str> = form.iter()
+        .filter(|(key, _)| key == "id[]")
+        .map(|(_, value)| value.as_str())
+        .collect();
+
+    if ids.is_empty() {
+        return error_reply("id", "Chưa chọn đối tượng cần xoá.");
+    }
+
+    state.doituong.retain(|doituong| !ids.contains(&doituong.id.as_str()));
+
+    json!({ "result": "success" })
+}
+
+fn add_doituong(state: &mut MockState, form: &[(String, String)]) -> Value {
+    let (fields, education, education_2024) = match decode_resident(form) {
+        Some(decoded) => decoded,
+        None => return error_reply("data", "Dữ liệu không hợp lệ."),
+    };
+
+    let ma_phieu = fields.get("ma_phieu").cloned().unwrap_or_default();
+
+    if !state.phieu.iter().any(|phieu| phieu.ma_phieu == ma_phieu) {
+        return error_reply("ma_phieu", &format!("Phiếu {} không tồn tại.", ma_phieu));
+    }
+
+    if fields.get("ho_ten").map(|ho_ten| ho_ten.trim().is_empty()).unwrap_or(true) {
+        return error_reply("ho_ten", "Họ tên không được để trống.");
+    }
+
+    let id = state.next_id();
+    state.doituong.push(MockDoiTuong { id: id.clone(), ma_phieu, fields, education, education_2024 });
+
+    json!({ "result": "success", "id": portal_value("id", &id) })
+}
+
+/// Giải mã data1, data2 và data_dtht của form thêm đối tượng.
+fn decode_resident(form: &[(String, String)]) -> Option<(Fields, Fields, Fields)> {
+    let fields = form_value(form, "data1").and_then(decode_object).map(portal_fields)?;
+    let education = form_value(form, "data2").and_then(decode_object)?;
+    let education_2024 = form_value(form, "data_dtht")
+        .and_then(|data_dtht| serde_json::from_str::<Value>(data_dtht).ok())
+        .and_then(|data_dtht| data_dtht["2024"].as_str().and_then(decode_base64))
+        .and_then(|json| decode_object(&json))?;
+
+    Some((fields, education, education_2024))
+}
+
+/// Một dòng jqGrid: id cùng các trường, trường ở bảng sau ghi đè bảng trước.
+fn grid_row<const N: usize>(id: &str, field_maps: [&BTreeMap<String, String>; N]) -> Value {
+    let mut row = Map::new();
+
+    for fields in field_maps {
+        for (field, value) in fields {
+            row.insert(field.clone(), portal_value(field, value));
+        }
+    }
+
+    row.insert("id".to_owned(), portal_value("id", id));
+    Value::Object(row)
+}
+
+/// Phân trang giống jqGrid, `records` là chuỗi như cổng thật.
+fn grid_reply(rows: Vec<Value>, form: &[(String, String)]) -> Value {
+    let per_page: usize = form_value(form, "rows").and_then(|rows| rows.parse().ok()).filter(|rows| *rows > 0).unwrap_or(10);
+    let page: usize = form_value(form, "page").and_then(|page| page.parse().ok()).filter(|page| *page > 0).unwrap_or(1);
+    let records = rows.len();
+    let total = records.div_ceil(per_page);
+
+    let rows: Vec<Value> = rows.into_iter().skip((page - 1) * per_page).take(per_page).collect();
+
+    json!({ "page": page, "total": total, "records": records.to_string(), "rows": rows })
+}
+
+/// Đưa các trường ngày tháng về dạng cổng lưu (yyyy-mm-dd).
+fn portal_fields(mut fields: Fields) -> Fields {
+    for field in DATE_FIELDS {
+        if let Some(value) = fields.get_mut(field) {
+            let parts: Vec<&str> = value.trim().split('/').collect();
+            if let [day, month, year] = parts.as_slice() {
+                *value = format!("{}-{:0>2}-{:0>2}", year, month, day);
+            }
+        }
+    }
+
+    fields
+}
+
+/// Giá trị một cột như cổng trả về: mã số dạng số, còn lại dạng chuỗi.
+fn portal_value(field: &str, value: &str) -> Value {
+    match value.parse::<u64>() {
+        Ok(number) if NUMERIC_FIELDS.contains(&field) => Value::from(number),
+        _ => Value::from(value),
+    }
+}
+
+fn error_reply(field: &str, message: &str) -> Value {
+    json!({ "result": "error", "errors": { field: message } })
+}
+
+fn form_value<'a>(form: &'a [(String, String)], key: &str) -> Option<&'a str> {
+    form.iter().find(|(name, _)| name == key).map(|(_, value)| value.as_str())
+}
+
+/// Giải mã đối tượng JSON có các giá trị được mã hoá base64; giá trị không phải base64 giữ nguyên.
+fn decode_object(json: &str) -> Option<BTreeMap<String, String>> {
+    let object: Map<String, Value> = serde_json::from_str(json).ok()?;
+
+    Some(object.into_iter()
+        .map(|(field, value)| {
+            let value = match value {
+                Value::String(value) => decode_base64(&value).unwrap_or(value),
+                Value::Null => "".to_owned(),
+                other => other.to_string(),
+            };
+            (field, value)
+        })
+        .collect())
+}
+
+fn decode_base64(value: &str) -> Option<String> {
+    BASE64_STANDARD.decode(value).ok().and_then(|bytes| String::from_utf8(bytes).ok())
+}
+
+fn content_type(value: &str) -> Header {
+    Header::from_bytes("Content-Type", value).unwrap()
+}
@@ -0,0 +1,173 @@
+#![allow(dead_code)]
+
+pub mod mock_server;
+
+use std::{fs::{self, File}, io::Write, path::{Path, PathBuf}};
+use pcgd_bulk::column_mapping::ColumnMapping;
+use pcgd_bulk::merge::MemberPolicy;
+use pcgd_bulk::row_validation::InvalidRowPolicy;
+use pcgd_bulk::upload::UploadOptions;
+use pcgd_bulk::workbook::{RunMode, WorkbookJob};
+use zip::{write::SimpleFileOptions, ZipWriter};
+
+pub const TOKEN: &str = "test-csrf-token";
+pub const PREFIX: &str = "01_001_00001_1_";
+
+pub const GIOI_TINH_NU: usize = 6;
+pub const DIA_CHI: usize = 10;
+pub const KHOI: usize = 14;
+pub const DIEN_THOAI: usize = 31;
+pub const GHI_CHU: usize = 32;
+pub const KHUYET_TAT: usize = 33;
+
+/// Tiêu đề sheet MauNhapLieu, cột cuối là nhóm khuyết tật.
+const HEADERS: [&str; 34] = [
+    "STT", "Họ đệm", "Tên", "Ngày sinh", "Tháng sinh", "Năm sinh", "Giới tính nữ", "Dân tộc", "Tôn giáo",
+    "Diện ưu tiên", "Địa chỉ", "Số phiếu", "Diện cư trú", "Tình trạng cư trú", "Khối", "Lớp học",
+    "Mã trường", "Cấp tốt nghiệp", "Học bổ túc", "Năm tốt nghiệp", "Bậc tốt nghiệp nghề",
+    "Năm tốt nghiệp nghề", "Bỏ học lớp", "Bỏ học năm", "Học XMC lớp", "Công nhận XMC", "Tái mù chữ",
+    "Hoàn cảnh đặc biệt", "Chi tiết hoàn cảnh đặc biệt", "Quan hệ với chủ hộ", "Họ tên cha mẹ",
+    "Điện thoại", "Ghi chú", "Khuyết tật",
+];
+
+/// Số cột khuyết tật theo cấu hình mặc định.
+const KHUYET_TAT_COLUMNS: usize = 11;
+
+pub enum Cell {
+    Text(String),
+    Number(f64),
+}
+
+/// Một người trong bảng tính: (số phiếu, họ đệm, tên, ngày, tháng, năm sinh, quan hệ với chủ hộ).
+pub fn person(so_phieu: &str, ho_dem: &str, ten: &str, birth: (u32, u32, u32), qh_chu_ho: &str) -> Vec<Cell> {
+    let mut row: Vec<Cell> = (0..HEADERS.len() - 1 + KHUYET_TAT_COLUMNS).map(|_| Cell::Text(String::new())).collect();
+
+    row[1] = Cell::Text(ho_dem.to_owned());
+    row[2] = Cell::Text(ten.to_owned());
+    row[3] = Cell::Number(birth.0 as f64);
+    row[4] = Cell::Number(birth.1 as f64);
+    row[5] = Cell::Number(birth.2 as f64);
+    row[7] = Cell::Text("Kinh".to_owned());
+    row[DIA_CHI] = Cell::Text("Thôn 1".to_owned());
+    row[11] = Cell::Text(so_phieu.to_owned());
+    row[29] = Cell::Text(qh_chu_ho.to_owned());
+    row[DIEN_THOAI] = Cell::Text("912345678".to_owned());
+
+    row
+}
+
+/// Hai hộ mẫu: hộ 0001 có ba người, hộ 0002 có hai người.
+pub fn sample_rows() -> Vec<Vec<Cell>> {
+    vec![
+        person("0001", "Nguyễn Văn", "An", (1, 2, 1980), "Chủ hộ"),
+        person("0001", "Trần Thị", "Bình", (3, 4, 1982), "Vợ"),
+        person("0001", "Nguyễn Văn", "Cường", (5, 6, 2010), "Con"),
+        person("0002", "Lê Thị", "Dung", (7, 8, 1975), "Chủ hộ"),
+        person("0002", "Lê Văn", "Em", (9, 10, 2012), "Con"),
+    ]
+}
+
+/// Thư mục tạm riêng cho từng test.
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("pcgd-bulk-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Ghi file XLSX tối thiểu có sheet MauNhapLieu gồm dòng tiêu đề và các dòng dữ liệu.
+pub fn write_workbook(path: &Path, rows: &[Vec<Cell>]) {
+    let mut header: Vec<Cell> = HEADERS.iter().map(|title| Cell::Text(title.to_string())).collect();
+    header.extend((1..KHUYET_TAT_COLUMNS).map(|_| Cell::Text(String::new())));
+
+    let mut sheet_rows = String::new();
+    for (index, row) in std::iter::once(&header).chain(rows.iter()).enumerate() {
+        sheet_rows.push_str(&format!("<row r=\"{}\">", index + 1));
+        for (column, cell) in row.iter().enumerate() {
+            let reference = format!("{}{}", pcgd_bulk::column_mapping::column_letter(column), index + 1);
+            match cell {
+                Cell::Text(text) if text.is_empty() => {},
+                Cell::Text(text) => sheet_rows.push_str(&format!("<c r=\"{}\" t=\"inlineStr\"><is><t>{}</t></is></c>", reference, escape(text))),
+                Cell::Number(number) => sheet_rows.push_str(&format!("<c r=\"{}\"><v>{}</v></c>", reference, number)),
+            }
+        }
+        sheet_rows.push_str("</row>");
+    }
+
+    let files = [
+        ("[Content_Types].xml", concat!(
+            r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>"#,
+            r#"<Types xmlns="http://schemas.openxmlformats.org/package/2006/content-types">"#,
+            r#"<Default Extension="rels" ContentType="application/vnd.openxmlformats-package.relationships+xml"/>"#,
+            r#"<Default Extension="xml" ContentType="application/xml"/>"#,
+            r#"<Override PartName="/xl/workbook.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.sheet.main+xml"/>"#,
+            r#"<Override PartName="/xl/worksheets/sheet1.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.worksheet+xml"/>"#,
+            r#"</Types>"#,
+        ).to_owned()),
+        ("_rels/.rels", concat!(
+            r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>"#,
+            r#"<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships">"#,
+            r#"<Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/officeDocument" Target="xl/workbook.xml"/>"#,
+            r#"</Relationships>"#,
+        ).to_owned()),
+        ("xl/workbook.xml", concat!(
+            r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>"#,
+            r#"<workbook xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main" xmlns:r="http://schemas.openxmlformats.org/officeDocument/2006/relationships">"#,
+            r#"<sheets><sheet name="MauNhapLieu" sheetId="1" r:id="rId1"/></sheets>"#,
+            r#"</workbook>"#,
+        ).to_owned()),
+        ("xl/_rels/workbook.xml.rels", concat!(
+            r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>"#,
+            r#"<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships">"#,
+            r#"<Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/worksheet" Target="worksheets/sheet1.xml"/>"#,
+            r#"</Relationships>"#,
+        ).to_owned()),
+        ("xl/worksheets/sheet1.xml", format!(
+            r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?><worksheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main"><sheetData>{}</sheetData></worksheet>"#,
+            sheet_rows
+        )),
+    ];
+
+    let mut zip = ZipWriter::new(File::create(path).unwrap());
+    for (name, content) in files.iter() {
+        zip.start_file(*name, SimpleFileOptions::default()).unwrap();
+        zip.write_all(content.as_bytes()).unwrap();
+    }
+    zip.finish().unwrap();
+}
+
+/// Bảng cột dò theo tiêu đề của sheet mẫu trong test.
+pub fn mapping() -> ColumnMapping {
+    ColumnMapping { detect_headers: true, ..ColumnMapping::default() }
+}
+
+/// Lần chạy không hỏi lại, dùng điểm dừng riêng trong thư mục tạm.
+/// Cổng giả lập ban đầu chưa có phiếu nào để đối chiếu mã nên cho phép mã chưa kiểm tra được.
+pub fn job<'a>(workbook: PathBuf, mapping: &'a ColumnMapping, mode: RunMode, dir: &Path, pcgd_csrf_token: &str) -> WorkbookJob<'a> {
+    WorkbookJob {
+        file: workbook,
+        mapping,
+        mode,
+        ngay_dieutra: "15/09/2024".to_owned(),
+        admin_code: PREFIX.parse().unwrap(),
+        pcgd_csrf_token: pcgd_csrf_token.to_owned(),
+        checkpoint_file: dir.join("checkpoint.json"),
+        options: options(dir),
+        invalid_rows: InvalidRowPolicy::Skip,
+        allow_unverified_code: true,
+    }
+}
+
+/// Tuỳ chọn tải lên không hỏi lại, sao lưu vào thư mục tạm.
+pub fn options(dir: &Path) -> UploadOptions {
+    UploadOptions {
+        assume_yes: true,
+        member_policy: MemberPolicy::Merge,
+        backup_dir: dir.join("backups"),
+        ..UploadOptions::default()
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
@@ -0,0 +1,99 @@
+use pcgd_bulk::curl::{detect_format, parse_copied_request, CopyFormat};
+use pcgd_bulk::session::{parse_curl, parse_har, Session};
+
+const COOKIES: &str = "PHPSESSID=abc123; pcgd_csrf_cookie=c00k1e";
+const TOKEN: &str = "5f2b9c+d0e";
+const URL: &str = "https://pcgd.moet.gov.vn/doing/phieudieutra/lay_phieu";
+
+fn expected_session() -> Session {
+    Session { pcgd_csrf_token: TOKEN.to_owned(), cookies: COOKIES.to_owned() }
+}
+
+#[test]
+fn bash_with_cookie_flag_and_fields_after_token() {
+    let fixture = include_str!("fixtures/curl/bash.txt");
+    let request = parse_copied_request(fixture).unwrap();
+
+    assert_eq!(detect_format(fixture), CopyFormat::Bash);
+    assert_eq!(request.url, URL);
+    assert_eq!(request.method, "POST");
+    assert_eq!(request.header("x-requested-with"), Some("XMLHttpRequest"));
+    assert_eq!(request.form_value("sidx").as_deref(), Some("so_phieu"));
+    assert_eq!(parse_curl(fixture).unwrap(), expected_session());
+}
+
+#[test]
+fn bash_with_cookie_header_and_ansi_c_quoting() {
+    let fixture = include_str!("fixtures/curl/bash_ansi.txt");
+    let request = parse_copied_request(fixture).unwrap();
+
+    assert_eq!(request.form_value("tukhoa").as_deref(), Some("Nguyễn 'An'"));
+    assert_eq!(parse_curl(fixture).unwrap(), expected_session());
+}
+
+#[test]
+fn cmd_with_caret_escapes() {
+    let fixture = include_str!("fixtures/curl/cmd.txt");
+    let request = parse_copied_request(fixture).unwrap();
+
+    assert_eq!(detect_format(fixture), CopyFormat::Cmd);
+    assert_eq!(request.url, URL);
+    assert_eq!(request.header("Accept"), Some("application/json, text/javascript, */*; q=0.01"));
+    assert_eq!(parse_curl(fixture).unwrap(), expected_session());
+}
+
+#[test]
+fn powershell_web_session_cookies() {
+    let fixture = include_str!("fixtures/curl/powershell.txt");
+    let request = parse_copied_request(fixture).unwrap();
+
+    assert_eq!(detect_format(fixture), CopyFormat::PowerShell);
+    assert_eq!(request.url, URL);
+    assert_eq!(request.method, "POST");
+    assert_eq!(request.header("Origin"), Some("https://pcgd.moet.gov.vn"));
+    assert_eq!(request.header("Content-Type"), Some("application/x-www-form-urlencoded; charset=UTF-8"));
+    assert_eq!(parse_curl(fixture).unwrap(), expected_session());
+}
+
+#[test]
+fn fetch_for_node_includes_cookies() {
+    let fixture = include_str!("fixtures/curl/fetch_node.txt");
+    let request = parse_copied_request(fixture).unwrap();
+
+    assert_eq!(detect_format(fixture), CopyFormat::Fetch);
+    assert_eq!(request.url, URL);
+    assert_eq!(request.header("referer"), Some("https://pcgd.moet.gov.vn/doing/phieudieutra"));
+    assert_eq!(parse_curl(fixture).unwrap(), expected_session());
+}
+
+#[test]
+fn browser_fetch_without_cookies_is_rejected() {
+    let error = parse_curl(include_str!("fixtures/curl/fetch_browser.txt")).unwrap_err();
+    assert!(error.contains("Node.js"), "{}", error);
+}
+
+#[test]
+fn request_without_token_is_rejected() {
+    let error = parse_curl(include_str!("fixtures/curl/get_without_token.txt")).unwrap_err();
+    assert!(error.contains("pcgd-csrf-token"), "{}", error);
+}
+
+#[test]
+fn cookies_with_control_characters_are_rejected() {
+    let bash = format!("curl '{}' -H $'Cookie: PHPSESSID=abc\\n123' --data-raw 'pcgd-csrf-token={}'", URL, TOKEN);
+    assert!(parse_curl(&bash).unwrap_err().contains("Cookie"));
+
+    let har = format!(
+        r#"{{"log":{{"entries":[{{"startedDateTime":"2024-10-01T08:00:00Z","request":{{"method":"POST","url":"{}","headers":[],"cookies":[{{"name":"PHPSESSID","value":"abc\u0001123"}}],"postData":{{"text":"pcgd-csrf-token={}"}}}}}}]}}}}"#,
+        URL, TOKEN
+    );
+    assert!(parse_har(&har, "https://pcgd.moet.gov.vn").unwrap_err().contains("kèm cookie"));
+}
+
+#[test]
+fn malformed_commands_are_rejected() {
+    assert!(parse_curl("").unwrap_err().contains("trống"));
+    assert!(parse_curl("wget 'https://pcgd.moet.gov.vn'").unwrap_err().contains("curl"));
+    assert!(parse_curl("curl 'https://pcgd.moet.gov.vn -b 'a=b'").is_err());
+    assert!(parse_curl("curl 'https://pcgd.moet.gov.vn' -b cookies.txt").unwrap_err().contains("file cookie"));
+}
@@ -0,0 +1,143 @@
+mod common;
+
+use std::collections::BTreeMap;
+use common::{job, mapping, sample_rows, temp_dir, write_workbook, Cell, GIOI_TINH_NU, KHOI, KHUYET_TAT, PREFIX, TOKEN};
+use common::mock_server::{MockServer, DOITUONG_LIST_COLUMNS};
+use calamine::{open_workbook, Reader, Xlsx};
+use pcgd_bulk::column_mapping::ColumnMapping;
+use pcgd_bulk::export::export_households;
+use pcgd_bulk::pcgd_client::PcgdClient;
+use pcgd_bulk::workbook::{workbook_reader, RunMode};
+
+type Snapshot = Vec<(BTreeMap<String, String>, Vec<BTreeMap<String, String>>)>;
+
+/// Dữ liệu các hộ trên cổng giả lập, bỏ mã phiếu vì mỗi cổng cấp mã khác nhau. Với thành viên chỉ lấy
+/// các cột có trong danh sách thành viên, vì file xuất chỉ có được các cột đó.
+fn snapshot(server: &MockServer) -> Snapshot {
+    let state = server.state.lock().unwrap();
+
+    state.phieu.iter()
+        .map(|phieu| {
+            let mut fields = phieu.fields.clone();
+            fields.remove("ma_phieu");
+
+            let members = state.doituong_of(&phieu.ma_phieu).iter()
+                .map(|doituong| {
+                    doituong.fields.iter()
+                        .filter(|(field, _)| DOITUONG_LIST_COLUMNS.contains(&field.as_str()))
+                        .map(|(field, value)| (field.clone(), value.clone()))
+                        .collect()
+                })
+                .collect();
+
+            (fields, members)
+        })
+        .collect()
+}
+
+#[test]
+fn exported_workbook_uploads_the_same_listed_columns() {
+    let dir = temp_dir("export");
+    let workbook = dir.join("MauNhapLieu.xlsx");
+    let exported = dir.join("export.xlsx");
+    let mapping = mapping();
+    let template = ColumnMapping::default();
+
+    let mut rows = sample_rows();
+    rows[1][GIOI_TINH_NU] = Cell::Text("X".to_owned());
+    rows[1][KHUYET_TAT + 2] = Cell::Text("X".to_owned());
+    rows[2][KHOI] = Cell::Text("5 tuổi".to_owned());
+    rows[4][KHOI] = Cell::Text("6".to_owned());
+    write_workbook(&workbook, &rows);
+
+    let server = MockServer::start("127.0.0.1:0", TOKEN).unwrap();
+    let client = PcgdClient::new(&server.base_url, "PHPSESSID=test", TOKEN);
+    workbook_reader(&job(workbook, &mapping, RunMode::Upload, &dir, TOKEN), Some(&client)).unwrap();
+
+    let summary = export_households(&client, PREFIX, &template, &exported).unwrap();
+    assert_eq!((summary.households, summary.residents), (2, 5));
+
+    let copy = MockServer::start("127.0.0.1:0", TOKEN).unwrap();
+    let copy_client = PcgdClient::new(&copy.base_url, "PHPSESSID=test", TOKEN);
+    workbook_reader(&job(exported, &template, RunMode::Upload, &temp_dir("export-copy"), TOKEN), Some(&copy_client)).unwrap();
+
+    let original = snapshot(&server);
+    assert_eq!(original[0].1[1]["gioi_tinh"], "2");
+    assert_eq!(snapshot(&copy), original);
+
+    // Khuyết tật và khối không có trong danh sách thành viên nên không xuất ra được.
+    let state = copy.state.lock().unwrap();
+    let binh = &state.doituong[1];
+    assert_eq!((binh.fields["khuyet_tat_benh"].as_str(), state.doituong[2].education_2024["khoi"].as_str()), ("", ""));
+}
+
+#[test]
+fn export_uses_template_columns_and_header() {
+    let dir = temp_dir("export-template");
+    let workbook = dir.join("MauNhapLieu.xlsx");
+    let exported = dir.join("export.xlsx");
+    let mapping = mapping();
+    let template = ColumnMapping::default();
+
+    write_workbook(&workbook, &sample_rows());
+
+    let server = MockServer::start("127.0.0.1:0", TOKEN).unwrap();
+    let client = PcgdClient::new(&server.base_url, "PHPSESSID=test", TOKEN);
+    workbook_reader(&job(workbook, &mapping, RunMode::Upload, &dir, TOKEN), Some(&client)).unwrap();
+    export_households(&client, PREFIX, &template, &exported).unwrap();
+
+    let mut sheet: Xlsx<_> = open_workbook(&exported).unwrap();
+    let range = sheet.worksheet_range("MauNhapLieu").unwrap();
+    let cell = |row: u32, column: usize| range.get_value((row, column as u32)).map(|cell| cell.to_string()).unwrap_or_default();
+
+    assert_eq!(cell(0, 0), "MẪU NHẬP LIỆU");
+    assert_eq!(cell(0, template.khuyet_tat[0]), "Khuyết tật");
+    assert_eq!(cell(1, template.ho_dem), "Họ đệm");
+    assert_eq!(cell(1, template.so_phieu), "Số phiếu");
+    assert_eq!(cell(1, template.qh_chu_ho), "Quan hệ với chủ hộ");
+    assert_eq!(cell(2, template.khuyet_tat[2]), "3");
+    assert_eq!(cell(3, template.ghi_chu), (template.ghi_chu + 1).to_string());
+
+    assert_eq!(cell(4, 0), "1");
+    assert_eq!(cell(4, template.ho_dem), "Nguyễn Văn");
+    assert_eq!(cell(4, template.ten), "An");
+    assert_eq!(cell(4, template.nam_sinh), "1980");
+    assert_eq!(cell(4, template.so_phieu), "0001");
+    assert_eq!(cell(4, template.qh_chu_ho), "Chủ hộ");
+}
+
+#[test]
+fn export_of_another_village_is_empty() {
+    let dir = temp_dir("export-village");
+    let workbook = dir.join("MauNhapLieu.xlsx");
+    let mapping = mapping();
+
+    write_workbook(&workbook, &sample_rows());
+
+    let server = MockServer::start("127.0.0.1:0", TOKEN).unwrap();
+    let client = PcgdClient::new(&server.base_url, "PHPSESSID=test", TOKEN);
+    workbook_reader(&job(workbook, &mapping, RunMode::Upload, &dir, TOKEN), Some(&client)).unwrap();
+
+    let summary = export_households(&client, "01_001_00001_2_", &mapping, &dir.join("export.xlsx")).unwrap();
+    assert_eq!((summary.households, summary.residents), (0, 0));
+}
+
+#[test]
+fn phieu_without_village_are_exported_like_upload_finds_them() {
+    let dir = temp_dir("export-no-village");
+    let workbook = dir.join("MauNhapLieu.xlsx");
+    let mapping = mapping();
+
+    write_workbook(&workbook, &sample_rows());
+
+    let server = MockServer::start("127.0.0.1:0", TOKEN).unwrap();
+    let client = PcgdClient::new(&server.base_url, "PHPSESSID=test", TOKEN);
+    workbook_reader(&job(workbook, &mapping, RunMode::Upload, &dir, TOKEN), Some(&client)).unwrap();
+    server.state.lock().unwrap().phieu[0].fields.remove("ma_thonxom");
+
+    let summary = export_households(&client, PREFIX, &mapping, &dir.join("export.xlsx")).unwrap();
+    assert_eq!(summary.households, 2);
+
+    let summary = export_households(&client, "01_001_00001_2_", &mapping, &dir.join("export-2.xlsx")).unwrap();
+    assert_eq!(summary.households, 1);
+}
@@ -0,0 +1,29 @@
+use pcgd_bulk::pcgd_client::DEFAULT_BASE_URL;
+use pcgd_bulk::session::{parse_har, Session};
+
+#[test]
+fn latest_authenticated_portal_request_is_used() {
+    let session = parse_har(include_str!("fixtures/har/pcgd.har"), DEFAULT_BASE_URL).unwrap();
+
+    assert_eq!(session, Session {
+        pcgd_csrf_token: "5f2b9c+d0e".to_owned(),
+        cookies: "PHPSESSID=abc123; pcgd_csrf_cookie=c00k1e".to_owned(),
+    });
+}
+
+#[test]
+fn requests_to_other_portals_are_ignored() {
+    let error = parse_har(include_str!("fixtures/har/pcgd.har"), "http://127.0.0.1:8088").unwrap_err();
+    assert!(error.contains("127.0.0.1"), "{}", error);
+}
+
+#[test]
+fn sanitized_har_without_cookies_is_rejected() {
+    let error = parse_har(include_str!("fixtures/har/sanitized.har"), DEFAULT_BASE_URL).unwrap_err();
+    assert!(error.contains("with sensitive data"), "{}", error);
+}
+
+#[test]
+fn invalid_har_is_rejected() {
+    assert!(parse_har("curl 'https://pcgd.moet.gov.vn'", DEFAULT_BASE_URL).unwrap_err().contains("HAR"));
+}
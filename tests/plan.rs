@@ -0,0 +1,123 @@
+mod common;
+
+use common::{job, mapping, options, person, sample_rows, temp_dir, write_workbook, Cell, DIA_CHI, GHI_CHU, TOKEN};
+use common::mock_server::MockServer;
+use pcgd_bulk::merge::MemberPolicy;
+use pcgd_bulk::pcgd_client::PcgdClient;
+use pcgd_bulk::upload::{ErrorPolicy, UploadOptions};
+use pcgd_bulk::plan::{read_plan, PlanAction};
+use pcgd_bulk::workbook::{plan_applier, workbook_reader, RunMode, RunOutcome};
+
+#[test]
+fn plan_lists_changes_and_apply_executes_them() {
+    let dir = temp_dir("plan");
+    let workbook = dir.join("MauNhapLieu.xlsx");
+    let plan_file = dir.join("plan.json");
+    let server = MockServer::start("127.0.0.1:0", TOKEN).unwrap();
+    let client = PcgdClient::new(&server.base_url, "PHPSESSID=test", TOKEN);
+    let mapping = mapping();
+
+    write_workbook(&workbook, &sample_rows());
+    workbook_reader(&job(workbook.clone(), &mapping, RunMode::Upload, &dir, TOKEN), Some(&client)).unwrap();
+    let members_before = server.state.lock().unwrap().doituong.clone();
+
+    let mut owner = person("0001", "Nguyễn Văn", "An", (1, 2, 1980), "Chủ hộ");
+    owner[DIA_CHI] = Cell::Text("Thôn 2".to_owned());
+    let binh = person("0001", "Trần Thị", "Bình", (3, 4, 1982), "Con dâu");
+    let mut cuong = person("0001", "Nguyễn Văn", "Cường", (5, 6, 2010), "Con");
+    cuong[GHI_CHU] = Cell::Text("Xoá".to_owned());
+    write_workbook(&workbook, &[
+        owner,
+        binh,
+        cuong,
+        person("0001", "Nguyễn Thị", "Giang", (2, 2, 2015), "Con"),
+        person("0002", "Lê Thị", "Dung", (7, 8, 1975), "Chủ hộ"),
+        person("0002", "Lê Văn", "Em", (9, 10, 2012), "Con"),
+        person("0003", "Hoàng Văn", "Hải", (4, 5, 1990), "Chủ hộ"),
+    ]);
+
+    workbook_reader(&job(workbook, &mapping, RunMode::Plan(plan_file.clone()), &dir, TOKEN), Some(&client)).unwrap();
+
+    let plan = read_plan(&plan_file).unwrap();
+    let kinds: Vec<&str> = plan.actions.iter()
+        .map(|action| match action {
+            PlanAction::CreateHousehold { .. } => "create",
+            PlanAction::UpdateOwner { .. } => "owner",
+            PlanAction::AddMember { .. } => "add",
+            PlanAction::UpdateMember { .. } => "update",
+            PlanAction::RemoveMember { .. } => "remove",
+        })
+        .collect();
+    assert_eq!(kinds, vec!["owner", "remove", "add", "create", "add"]);
+    assert_eq!(plan.conflicts.len(), 1);
+    assert!(plan.conflicts[0].contains("qh_chu_ho"), "{}", plan.conflicts[0]);
+    assert_eq!(server.state.lock().unwrap().doituong.len(), members_before.len());
+    assert_eq!(server.state.lock().unwrap().phieu.len(), 2);
+
+    assert_eq!(plan_applier(&plan_file, &client, &options(&dir)), RunOutcome::Done);
+
+    let state = server.state.lock().unwrap();
+    let phieu = state.phieu_by_so_phieu("0001").unwrap();
+    let members: Vec<String> = state.doituong_of(&phieu.ma_phieu).iter().map(|doituong| doituong.fields["ho_ten"].clone()).collect();
+    assert_eq!(phieu.fields["dia_chi"], "Thôn 2");
+    assert_eq!(members, vec!["Nguyễn Văn An", "Trần Thị Bình", "Nguyễn Thị Giang"]);
+    assert_eq!(state.doituong_of(&phieu.ma_phieu)[1].fields["qh_chu_ho"], "Vợ");
+    assert_eq!(state.doituong_of(&state.phieu_by_so_phieu("0003").unwrap().ma_phieu).len(), 1);
+    assert!(dir.join("backups").exists());
+}
+
+#[test]
+fn failed_member_of_a_new_household_does_not_recreate_or_skip_the_household() {
+    let dir = temp_dir("plan-create");
+    let workbook = dir.join("MauNhapLieu.xlsx");
+    let plan_file = dir.join("plan.json");
+    let server = MockServer::start("127.0.0.1:0", TOKEN).unwrap();
+    let client = PcgdClient::new(&server.base_url, "PHPSESSID=test", TOKEN);
+    let mapping = mapping();
+
+    write_workbook(&workbook, &sample_rows());
+    workbook_reader(&job(workbook, &mapping, RunMode::Plan(plan_file.clone()), &dir, TOKEN), Some(&client)).unwrap();
+
+    server.state.lock().unwrap().failures.insert("/doing/doituong/add".to_owned(), 1);
+    let options = UploadOptions { on_error: ErrorPolicy::SkipResident, ..options(&dir) };
+    assert_eq!(plan_applier(&plan_file, &client, &options), RunOutcome::Incomplete(1));
+
+    let state = server.state.lock().unwrap();
+    let phieu = state.phieu_by_so_phieu("0001").unwrap();
+    let members: Vec<String> = state.doituong_of(&phieu.ma_phieu).iter().map(|doituong| doituong.fields["ho_ten"].clone()).collect();
+    assert_eq!(state.phieu.len(), 2);
+    assert_eq!(members, vec!["Trần Thị Bình", "Nguyễn Văn Cường"]);
+    assert_eq!(state.doituong_of(&state.phieu_by_so_phieu("0002").unwrap().ma_phieu).len(), 2);
+}
+
+#[test]
+fn changed_members_are_replaced_only_when_asked() {
+    let dir = temp_dir("plan-replace");
+    let workbook = dir.join("MauNhapLieu.xlsx");
+    let plan_file = dir.join("plan.json");
+    let server = MockServer::start("127.0.0.1:0", TOKEN).unwrap();
+    let client = PcgdClient::new(&server.base_url, "PHPSESSID=test", TOKEN);
+    let mapping = mapping();
+
+    write_workbook(&workbook, &sample_rows());
+    workbook_reader(&job(workbook.clone(), &mapping, RunMode::Upload, &dir, TOKEN), Some(&client)).unwrap();
+
+    let mut rows = sample_rows();
+    rows[1] = person("0001", "Trần Thị", "Bình", (3, 4, 1982), "Con dâu");
+    write_workbook(&workbook, &rows);
+
+    let mut plan_job = job(workbook, &mapping, RunMode::Plan(plan_file.clone()), &dir, TOKEN);
+    plan_job.options.member_policy = MemberPolicy::MergeAskDelete;
+    workbook_reader(&plan_job, Some(&client)).unwrap();
+
+    let plan = read_plan(&plan_file).unwrap();
+    assert!(matches!(plan.actions.as_slice(), [PlanAction::UpdateMember { .. }]));
+    assert!(plan.conflicts.is_empty());
+
+    assert_eq!(plan_applier(&plan_file, &client, &options(&dir)), RunOutcome::Done);
+
+    let state = server.state.lock().unwrap();
+    let phieu = state.phieu_by_so_phieu("0001").unwrap();
+    let binh = state.doituong_of(&phieu.ma_phieu).into_iter().find(|doituong| doituong.fields["ho_ten"] == "Trần Thị Bình").unwrap();
+    assert_eq!(binh.fields["qh_chu_ho"], "Con dâu");
+}
@@ -0,0 +1,80 @@
+mod common;
+
+use std::fs;
+use common::{temp_dir, PREFIX};
+use pcgd_bulk::admin_code::AdminCode;
+use pcgd_bulk::profile::{find_profile, load_profiles};
+use pcgd_bulk::row_validation::InvalidRowPolicy;
+use pcgd_bulk::upload::ErrorPolicy;
+
+const PROFILES: &str = r#"{
+    "xa-b": {
+        "ma_tinh": "01",
+        "ma_quanhuyen": "01_001",
+        "ma_phuongxa": "01_001_00002",
+        "ma_thonxom": "01_001_00002_3",
+        "on_error": "retry:5"
+    },
+    "xa-a": {
+        "ma_tinh": "01",
+        "ma_quanhuyen": "01_001",
+        "ma_phuongxa": "01_001_00001",
+        "ma_thonxom": "01_001_00001_1",
+        "ngay_dieutra": "15/09/2024",
+        "column_mapping": "xa-a.json",
+        "base_url": "http://127.0.0.1:8080/",
+        "on_error": "stop",
+        "invalid_rows": "skip"
+    }
+}"#;
+
+#[test]
+fn profiles_are_loaded_by_name() {
+    let dir = temp_dir("profiles");
+    let file = dir.join("profiles.json");
+    fs::write(&file, PROFILES).unwrap();
+
+    let profiles = load_profiles(&file).unwrap();
+    assert_eq!(profiles.iter().map(|profile| profile.name.as_str()).collect::<Vec<&str>>(), ["xa-a", "xa-b"]);
+
+    let profile = find_profile(profiles, "xa-a").unwrap();
+    assert_eq!(profile.admin_code, PREFIX.parse::<AdminCode>().unwrap());
+    assert_eq!(profile.admin_code.prefix(), PREFIX);
+    assert_eq!(profile.ngay_dieutra.as_deref(), Some("15/09/2024"));
+    assert_eq!(profile.base_url.as_deref(), Some("http://127.0.0.1:8080"));
+    assert_eq!(profile.on_error, Some(ErrorPolicy::Stop));
+    assert_eq!(profile.invalid_rows, Some(InvalidRowPolicy::Skip));
+
+    let profile = find_profile(load_profiles(&file).unwrap(), "xa-b").unwrap();
+    assert_eq!(profile.on_error, Some(ErrorPolicy::Retry(5)));
+    assert_eq!(profile.invalid_rows, None);
+
+    let error = find_profile(load_profiles(&file).unwrap(), "xa-c").unwrap_err();
+    assert!(error.contains("xa-a, xa-b"), "{}", error);
+}
+
+#[test]
+fn missing_profiles_file_has_no_profiles() {
+    let dir = temp_dir("profiles-missing");
+    assert!(load_profiles(&dir.join("profiles.json")).unwrap().is_empty());
+}
+
+#[test]
+fn inconsistent_profiles_are_rejected() {
+    let dir = temp_dir("profiles-invalid");
+    let file = dir.join("profiles.json");
+
+    let cases = [
+        (r#""ma_tinh": "01", "ma_quanhuyen": "01_002", "ma_phuongxa": "01_001_00001", "ma_thonxom": "01_001_00001_1""#, "ma_quanhuyen"),
+        (r#""ma_tinh": "01", "ma_quanhuyen": "01_001", "ma_phuongxa": "01_001_00001", "ma_thonxom": "01_001_00001""#, "thiếu mã thôn"),
+        (r#""ma_tinh": "01", "ma_quanhuyen": "01_001", "ma_phuongxa": "01_001_00001", "ma_thonxom": "01_001_00001_1", "ngay_dieutra": "2024-09-15""#, "dd/mm/yyyy"),
+        (r#""ma_tinh": "01", "ma_quanhuyen": "01_001", "ma_phuongxa": "01_001_00001", "ma_thonxom": "01_001_00001_1", "base_url": "ftp://pcgd""#, "http://"),
+    ];
+
+    for (fields, message) in cases {
+        fs::write(&file, format!("{{ \"xa-a\": {{ {} }} }}", fields)).unwrap();
+
+        let error = load_profiles(&file).unwrap_err();
+        assert!(error.contains("xa-a") && error.contains(message), "{}", error);
+    }
+}
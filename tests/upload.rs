@@ -0,0 +1,622 @@
+mod common;
+
+use std::{cell::Cell as Counter, path::{Path, PathBuf}, rc::Rc};
+use common::{job, mapping, options, person, sample_rows, temp_dir, write_workbook, Cell, DIA_CHI, DIEN_THOAI, GHI_CHU, TOKEN};
+use common::mock_server::MockServer;
+use pcgd_bulk::backup::{backup_members, read_backup, restore_members, MemberBackup};
+use pcgd_bulk::checkpoint::Checkpoint;
+use pcgd_bulk::journal::read_journal;
+use pcgd_bulk::merge::MemberPolicy;
+use pcgd_bulk::pcgd_client::PcgdClient;
+use pcgd_bulk::session::Session;
+use pcgd_bulk::upload::{upload_households, ErrorPolicy, UploadOptions};
+use pcgd_bulk::workbook::{journal_replayer, workbook_reader, RunMode, RunOutcome};
+
+fn start() -> (MockServer, PcgdClient) {
+    let server = MockServer::start("127.0.0.1:0", TOKEN).unwrap();
+    let client = PcgdClient::new(&server.base_url, "PHPSESSID=test", TOKEN);
+    (server, client)
+}
+
+fn sample_workbook(dir: &Path) -> PathBuf {
+    let workbook = dir.join("MauNhapLieu.xlsx");
+    write_workbook(&workbook, &sample_rows());
+    workbook
+}
+
+/// Số thành viên của từng phiếu trên cổng giả lập, theo số phiếu.
+fn member_counts(server: &MockServer) -> Vec<(String, usize)> {
+    let state = server.state.lock().unwrap();
+    let mut counts: Vec<(String, usize)> = state.phieu.iter()
+        .map(|phieu| (phieu.fields["so_phieu"].clone(), state.doituong_of(&phieu.ma_phieu).len()))
+        .collect();
+    counts.sort();
+    counts
+}
+
+fn member_ids(server: &MockServer) -> Vec<String> {
+    server.state.lock().unwrap().doituong.iter().map(|doituong| doituong.id.clone()).collect()
+}
+
+#[test]
+fn uploads_new_households() {
+    let dir = temp_dir("new-households");
+    let workbook = sample_workbook(&dir);
+    let (server, client) = start();
+    let mapping = mapping();
+
+    workbook_reader(&job(workbook, &mapping, RunMode::Upload, &dir, TOKEN), Some(&client)).unwrap();
+
+    assert_eq!(member_counts(&server), vec![("0001".to_owned(), 3), ("0002".to_owned(), 2)]);
+
+    let state = server.state.lock().unwrap();
+    let phieu = state.phieu_by_so_phieu("0001").unwrap();
+    assert_eq!(phieu.fields["chuho_hodem"], "Nguyễn Văn");
+    assert_eq!(phieu.fields["chuho_ten"], "An");
+    assert_eq!(phieu.fields["ma_thonxom"], "01_001_00001_1");
+    assert_eq!(phieu.fields["ngay_dieutra"], "2024-09-15");
+
+    let members = state.doituong_of(&phieu.ma_phieu);
+    assert_eq!(members[1].fields["ho_ten"], "Trần Thị Bình");
+    assert_eq!(members[1].fields["ngay_sinh"], "1982-04-03");
+    assert_eq!(members[1].fields["dien_thoai"], "0912345678");
+    assert_eq!(members[1].education_2024["ma_tinh"], "01");
+}
+
+#[test]
+fn commune_wide_workbook_takes_village_from_so_phieu() {
+    let dir = temp_dir("commune-wide");
+    let workbook = dir.join("MauNhapLieu.xlsx");
+    write_workbook(&workbook, &[
+        person("01_001_00001_1_0001", "Nguyễn Văn", "An", (1, 2, 1980), "Chủ hộ"),
+        person("01_001_00001_1_0001", "Trần Thị", "Bình", (3, 4, 1982), "Vợ"),
+        person("01_001_00001_2_0002", "Lê Thị", "Dung", (7, 8, 1975), "Chủ hộ"),
+        person("0003", "Phạm Văn", "Giang", (1, 1, 1990), "Chủ hộ"),
+    ]);
+    let (server, client) = start();
+    let mapping = mapping();
+
+    let mut job = job(workbook, &mapping, RunMode::Upload, &dir, TOKEN);
+    job.admin_code = "01_001_00001".parse().unwrap();
+    workbook_reader(&job, Some(&client)).unwrap();
+
+    let state = server.state.lock().unwrap();
+    assert_eq!(state.phieu.len(), 2, "hộ không xác định được thôn phải bị bỏ qua");
+    assert_eq!(state.phieu_by_so_phieu("01_001_00001_1_0001").unwrap().fields["ma_thonxom"], "01_001_00001_1");
+    assert_eq!(state.phieu_by_so_phieu("01_001_00001_2_0002").unwrap().fields["ma_thonxom"], "01_001_00001_2");
+    assert_eq!(state.phieu_by_so_phieu("01_001_00001_2_0002").unwrap().fields["ma_phuongxa"], "01_001_00001");
+}
+
+#[test]
+fn rerun_replaces_members_of_existing_households() {
+    let dir = temp_dir("rerun");
+    let workbook = sample_workbook(&dir);
+    let (server, client) = start();
+    let mapping = mapping();
+
+    workbook_reader(&job(workbook.clone(), &mapping, RunMode::Upload, &dir, TOKEN), Some(&client)).unwrap();
+    let first_ids = member_ids(&server);
+
+    std::fs::remove_file(dir.join("checkpoint.json")).unwrap();
+    let mut replace = job(workbook, &mapping, RunMode::Upload, &dir, TOKEN);
+    replace.options.member_policy = MemberPolicy::Replace;
+    workbook_reader(&replace, Some(&client)).unwrap();
+
+    assert_eq!(member_counts(&server), vec![("0001".to_owned(), 3), ("0002".to_owned(), 2)]);
+    assert!(member_ids(&server).iter().all(|id| !first_ids.contains(id)));
+}
+
+#[test]
+fn dry_run_then_replay_matches_direct_upload() {
+    let dir = temp_dir("dry-run");
+    let workbook = sample_workbook(&dir);
+    let journal = dir.join("requests.jsonl");
+    let (server, client) = start();
+    let mapping = mapping();
+
+    workbook_reader(&job(workbook, &mapping, RunMode::DryRun(journal.clone()), &dir, "{{pcgd-csrf-token}}"), None).unwrap();
+
+    assert!(server.state.lock().unwrap().phieu.is_empty());
+    assert_eq!(std::fs::read_to_string(&journal).unwrap().lines().count(), 7);
+
+    journal_replayer(&journal, &client, TOKEN, &dir.join("checkpoint.json"), &options(&dir));
+
+    assert_eq!(member_counts(&server), vec![("0001".to_owned(), 3), ("0002".to_owned(), 2)]);
+}
+
+#[test]
+fn journal_edits_must_go_into_decoded() {
+    let dir = temp_dir("journal-edit");
+    let workbook = sample_workbook(&dir);
+    let journal = dir.join("requests.jsonl");
+    let mapping = mapping();
+
+    workbook_reader(&job(workbook, &mapping, RunMode::DryRun(journal.clone()), &dir, "{{pcgd-csrf-token}}"), None).unwrap();
+
+    let mut entries: Vec<serde_json::Value> = std::fs::read_to_string(&journal).unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    entries[1]["decoded"]["data1"]["ho_ten"] = serde_json::Value::from("Trần Thị Bích");
+    let write = |entries: &[serde_json::Value]| {
+        let lines: Vec<String> = entries.iter().map(|entry| entry.to_string()).collect();
+        std::fs::write(&journal, lines.join("\n")).unwrap();
+    };
+
+    write(&entries);
+    let error = read_journal(&journal, TOKEN).unwrap_err();
+    assert!(error.starts_with("Dòng 2: form không khớp với decoded (data1)"), "{}", error);
+
+    entries[1].as_object_mut().unwrap().remove("form");
+    write(&entries);
+    let households = read_journal(&journal, TOKEN).unwrap();
+    assert_eq!(households[0].residents[0].0.ho_ten, "Trần Thị Bích");
+}
+
+#[test]
+fn workbook_without_the_template_sheet_is_an_error() {
+    let dir = temp_dir("no-sheet");
+    let workbook = dir.join("Other.xlsx");
+    let mut other = rust_xlsxwriter::Workbook::new();
+    other.add_worksheet().write_string(0, 0, "Họ đệm").unwrap();
+    other.save(&workbook).unwrap();
+    let (server, client) = start();
+    let mapping = mapping();
+
+    let error = workbook_reader(&job(workbook, &mapping, RunMode::Upload, &dir, TOKEN), Some(&client)).unwrap_err();
+
+    assert!(error.to_string().contains("MauNhapLieu"), "{}", error);
+    assert!(server.state.lock().unwrap().phieu.is_empty());
+}
+
+#[test]
+fn wrong_csrf_token_creates_nothing() {
+    let dir = temp_dir("wrong-token");
+    let workbook = sample_workbook(&dir);
+    let server = MockServer::start("127.0.0.1:0", TOKEN).unwrap();
+    let client = PcgdClient::new(&server.base_url, "PHPSESSID=test", "expired-token");
+    let mapping = mapping();
+
+    let outcome = workbook_reader(&job(workbook, &mapping, RunMode::Upload, &dir, "expired-token"), Some(&client)).unwrap();
+
+    assert_eq!(outcome, RunOutcome::Stopped);
+    let state = server.state.lock().unwrap();
+    assert!(state.phieu.is_empty());
+    assert!(state.doituong.is_empty());
+}
+
+#[test]
+fn expired_session_is_renewed_and_upload_continues() {
+    let dir = temp_dir("renew-session");
+    let workbook = sample_workbook(&dir);
+    let server = MockServer::start("127.0.0.1:0", TOKEN).unwrap();
+    let renewals = Rc::new(Counter::new(0));
+    let counter = renewals.clone();
+    let client = PcgdClient::new(&server.base_url, "PHPSESSID=old", "expired-token")
+        .with_session_renewer(Box::new(move || {
+            counter.set(counter.get() + 1);
+            Some(Session { pcgd_csrf_token: TOKEN.to_owned(), cookies: "PHPSESSID=new".to_owned() })
+        }));
+    let mapping = mapping();
+
+    let outcome = workbook_reader(&job(workbook, &mapping, RunMode::Upload, &dir, "expired-token"), Some(&client)).unwrap();
+
+    assert_eq!(outcome, RunOutcome::Done);
+    assert_eq!(renewals.get(), 1);
+    assert_eq!(client.pcgd_csrf_token(), TOKEN);
+    assert_eq!(member_counts(&server), vec![("0001".to_owned(), 3), ("0002".to_owned(), 2)]);
+}
+
+#[test]
+fn resumed_run_keeps_uploaded_members() {
+    let dir = temp_dir("resume");
+    let workbook = sample_workbook(&dir);
+    let (server, client) = start();
+    let mapping = mapping();
+
+    workbook_reader(&job(workbook.clone(), &mapping, RunMode::Upload, &dir, TOKEN), Some(&client)).unwrap();
+    let first_ids = member_ids(&server);
+
+    workbook_reader(&job(workbook, &mapping, RunMode::Upload, &dir, TOKEN), Some(&client)).unwrap();
+
+    assert_eq!(member_ids(&server), first_ids);
+    assert_eq!(server.state.lock().unwrap().phieu.len(), 2);
+}
+
+#[test]
+fn existing_household_lookup_matches_so_phieu_exactly() {
+    let dir = temp_dir("exact-lookup");
+    let workbook = dir.join("MauNhapLieu.xlsx");
+    let (server, client) = start();
+    let mapping = mapping();
+
+    write_workbook(&workbook, &[
+        person("112", "Phạm Văn", "Giang", (1, 1, 1970), "Chủ hộ"),
+        person("112", "Phạm Thị", "Hoa", (2, 2, 2000), "Con"),
+        person("12", "Đỗ Văn", "Khánh", (3, 3, 1972), "Chủ hộ"),
+    ]);
+    workbook_reader(&job(workbook.clone(), &mapping, RunMode::Upload, &dir, TOKEN), Some(&client)).unwrap();
+    let first_ids = member_ids(&server);
+
+    std::fs::remove_file(dir.join("checkpoint.json")).unwrap();
+    write_workbook(&workbook, &[
+        person("12", "Đỗ Văn", "Khánh", (3, 3, 1972), "Chủ hộ"),
+        person("12", "Đỗ Thị", "Lan", (4, 4, 2001), "Con"),
+    ]);
+    workbook_reader(&job(workbook, &mapping, RunMode::Upload, &dir, TOKEN), Some(&client)).unwrap();
+
+    assert_eq!(member_counts(&server), vec![("112".to_owned(), 2), ("12".to_owned(), 2)]);
+    assert!(first_ids[..2].iter().all(|id| member_ids(&server).contains(id)));
+}
+
+#[test]
+fn member_list_query_is_percent_encoded() {
+    let dir = temp_dir("encoded-phieu");
+    let workbook = sample_workbook(&dir);
+    let (server, client) = start();
+    let mapping = mapping();
+    workbook_reader(&job(workbook, &mapping, RunMode::Upload, &dir, TOKEN), Some(&client)).unwrap();
+
+    let ma_phieu = server.state.lock().unwrap().phieu_by_so_phieu("0001").unwrap().ma_phieu.clone();
+    assert_eq!(client.list_doituong(&ma_phieu).unwrap().len(), 3);
+    assert!(client.list_doituong(&format!("{}&phieu=x", ma_phieu)).unwrap().is_empty());
+    assert!(client.list_doituong(&format!("{}#", ma_phieu)).unwrap().is_empty());
+}
+
+#[test]
+fn ambiguous_existing_household_is_left_untouched() {
+    let dir = temp_dir("ambiguous-lookup");
+    let workbook = sample_workbook(&dir);
+    let (server, client) = start();
+    let mapping = mapping();
+
+    workbook_reader(&job(workbook.clone(), &mapping, RunMode::Upload, &dir, TOKEN), Some(&client)).unwrap();
+    {
+        let mut state = server.state.lock().unwrap();
+        let mut copy = state.phieu_by_so_phieu("0001").unwrap().clone();
+        copy.ma_phieu = "900".to_owned();
+        copy.fields.insert("ma_phieu".to_owned(), "900".to_owned());
+        state.phieu.push(copy);
+    }
+    let first_ids = member_ids(&server);
+
+    std::fs::remove_file(dir.join("checkpoint.json")).unwrap();
+    workbook_reader(&job(workbook, &mapping, RunMode::Upload, &dir, TOKEN), Some(&client)).unwrap();
+
+    let state = server.state.lock().unwrap();
+    let ma_phieu = &state.phieu_by_so_phieu("0001").unwrap().ma_phieu;
+    let members: Vec<&String> = state.doituong_of(ma_phieu).iter().map(|doituong| &doituong.id).collect();
+    assert_eq!(members, first_ids[..3].iter().collect::<Vec<&String>>());
+    assert!(state.doituong_of("900").is_empty());
+}
+
+#[test]
+fn merge_reports_changed_members_and_replaces_them_only_when_asked() {
+    let dir = temp_dir("merge");
+    let workbook = sample_workbook(&dir);
+    let (server, client) = start();
+    let mapping = mapping();
+
+    workbook_reader(&job(workbook.clone(), &mapping, RunMode::Upload, &dir, TOKEN), Some(&client)).unwrap();
+    let first_ids = member_ids(&server);
+
+    std::fs::remove_file(dir.join("checkpoint.json")).unwrap();
+    write_workbook(&workbook, &[
+        person("0001", "Nguyễn Văn", "An", (1, 2, 1980), "Chủ hộ"),
+        person("0001", "Trần Thị", "Bình", (3, 4, 1982), "Con dâu"),
+        person("0001", "Nguyễn Thị", "Giang", (2, 2, 2015), "Con"),
+    ]);
+    let outcome = workbook_reader(&job(workbook.clone(), &mapping, RunMode::Upload, &dir, TOKEN), Some(&client)).unwrap();
+    assert_eq!(outcome, RunOutcome::Incomplete(1));
+
+    {
+        let state = server.state.lock().unwrap();
+        let ma_phieu = &state.phieu_by_so_phieu("0001").unwrap().ma_phieu;
+        let members = state.doituong_of(ma_phieu);
+        let by_name = |ho_ten: &str| *members.iter().find(|doituong| doituong.fields["ho_ten"] == ho_ten).unwrap();
+
+        assert_eq!(members.len(), 4);
+        assert_eq!(by_name("Trần Thị Bình").id, first_ids[1]);
+        assert_eq!(by_name("Trần Thị Bình").fields["qh_chu_ho"], "Vợ");
+        assert!(!dir.join("backups").exists());
+    }
+
+    let mut ask = job(workbook, &mapping, RunMode::Upload, &dir, TOKEN);
+    ask.options.member_policy = MemberPolicy::MergeAskDelete;
+    assert_eq!(workbook_reader(&ask, Some(&client)).unwrap(), RunOutcome::Done);
+
+    let state = server.state.lock().unwrap();
+    let ma_phieu = &state.phieu_by_so_phieu("0001").unwrap().ma_phieu;
+    let members = state.doituong_of(ma_phieu);
+    let by_name = |ho_ten: &str| *members.iter().find(|doituong| doituong.fields["ho_ten"] == ho_ten).unwrap();
+
+    assert_eq!(by_name("Nguyễn Văn An").id, first_ids[0]);
+    assert!(!first_ids.contains(&by_name("Trần Thị Bình").id));
+    assert_eq!(by_name("Trần Thị Bình").fields["qh_chu_ho"], "Con dâu");
+
+    let backups: Vec<PathBuf> = std::fs::read_dir(dir.join("backups")).unwrap().map(|entry| entry.unwrap().path()).collect();
+    assert_eq!(backups.len(), 1);
+}
+
+#[test]
+fn merge_deletes_only_marked_or_confirmed_members() {
+    let dir = temp_dir("merge-delete");
+    let workbook = sample_workbook(&dir);
+    let (server, client) = start();
+    let mapping = mapping();
+
+    workbook_reader(&job(workbook.clone(), &mapping, RunMode::Upload, &dir, TOKEN), Some(&client)).unwrap();
+
+    std::fs::remove_file(dir.join("checkpoint.json")).unwrap();
+    let mut cuong = person("0001", "Nguyễn Văn", "Cường", (5, 6, 2010), "Con");
+    cuong[GHI_CHU] = Cell::Text("Xoá".to_owned());
+    write_workbook(&workbook, &[
+        person("0001", "Nguyễn Văn", "An", (1, 2, 1980), "Chủ hộ"),
+        cuong,
+        person("0002", "Lê Thị", "Dung", (7, 8, 1975), "Chủ hộ"),
+    ]);
+    workbook_reader(&job(workbook.clone(), &mapping, RunMode::Upload, &dir, TOKEN), Some(&client)).unwrap();
+
+    assert_eq!(member_counts(&server), vec![("0001".to_owned(), 2), ("0002".to_owned(), 2)]);
+
+    std::fs::remove_file(dir.join("checkpoint.json")).unwrap();
+    let mut ask_delete = job(workbook, &mapping, RunMode::Upload, &dir, TOKEN);
+    ask_delete.options.member_policy = MemberPolicy::MergeAskDelete;
+    workbook_reader(&ask_delete, Some(&client)).unwrap();
+
+    assert_eq!(member_counts(&server), vec![("0001".to_owned(), 1), ("0002".to_owned(), 1)]);
+}
+
+#[test]
+fn deleted_members_are_backed_up_and_restorable() {
+    let dir = temp_dir("backup");
+    let workbook = sample_workbook(&dir);
+    let (server, client) = start();
+    let mapping = mapping();
+
+    workbook_reader(&job(workbook.clone(), &mapping, RunMode::Upload, &dir, TOKEN), Some(&client)).unwrap();
+
+    std::fs::remove_file(dir.join("checkpoint.json")).unwrap();
+    write_workbook(&workbook, &[person("0001", "Nguyễn Văn", "An", (1, 2, 1980), "Chủ hộ")]);
+    let mut replace = job(workbook, &mapping, RunMode::Upload, &dir, TOKEN);
+    replace.options.member_policy = MemberPolicy::Replace;
+    workbook_reader(&replace, Some(&client)).unwrap();
+
+    assert_eq!(member_counts(&server), vec![("0001".to_owned(), 1), ("0002".to_owned(), 2)]);
+
+    let backups: Vec<PathBuf> = std::fs::read_dir(dir.join("backups")).unwrap().map(|entry| entry.unwrap().path()).collect();
+    assert_eq!(backups.len(), 1);
+
+    let backup = read_backup(&backups[0]).unwrap();
+    let names: Vec<String> = backup.members.iter().map(|row| row.text("ho_ten")).collect();
+    assert_eq!(backup.so_phieu, "0001");
+    assert_eq!(names, vec!["Nguyễn Văn An", "Trần Thị Bình", "Nguyễn Văn Cường"]);
+
+    let summary = restore_members(&client, &backup, &backup.ma_phieu, &options(&dir));
+    assert_eq!((summary.restored, summary.present, summary.skipped.len()), (2, 1, 0));
+
+    let state = server.state.lock().unwrap();
+    let restored = state.doituong_of(&backup.ma_phieu);
+    assert_eq!(restored.len(), 3);
+    assert_eq!(restored[2].fields["ngay_sinh"], "2010-06-05");
+    assert_eq!(restored[2].fields["qh_chu_ho"], "Con");
+}
+
+#[test]
+fn restored_members_get_template_defaults_for_missing_columns() {
+    let dir = temp_dir("restore-defaults");
+    let workbook = sample_workbook(&dir);
+    let (server, client) = start();
+    let mapping = mapping();
+    workbook_reader(&job(workbook, &mapping, RunMode::Upload, &dir, TOKEN), Some(&client)).unwrap();
+
+    let ma_phieu = server.state.lock().unwrap().phieu_by_so_phieu("0002").unwrap().ma_phieu.clone();
+    let backup: MemberBackup = serde_json::from_value(serde_json::json!({
+        "so_phieu": "0002",
+        "ma_phieu": ma_phieu,
+        "created_at": 0,
+        "members": [{ "id": 99, "ho_ten": "Lê Thị Hoa", "ngay_sinh": "2001-03-04", "qh_chu_ho": "Con" }],
+    })).unwrap();
+
+    let summary = restore_members(&client, &backup, &ma_phieu, &options(&dir));
+    assert_eq!((summary.restored, summary.present), (1, 0));
+
+    let state = server.state.lock().unwrap();
+    let restored = state.doituong_of(&ma_phieu).into_iter().find(|doituong| doituong.fields["ho_ten"] == "Lê Thị Hoa").unwrap();
+    assert_eq!(restored.fields["gioi_tinh"], "1");
+    assert_eq!(restored.education["bac_tn_nghe"], "0");
+    assert_eq!(restored.education["bohoc_lop"], "0");
+    assert_eq!(restored.education_2024["nam_hoc_re"], "2024");
+}
+
+#[test]
+fn failed_restores_are_reported_by_policy() {
+    let cases = [
+        (ErrorPolicy::SkipResident, 2, vec![Some("Nguyễn Văn An")]),
+        (ErrorPolicy::Stop, 0, vec![Some("Nguyễn Văn An"), Some("Trần Thị Bình"), Some("Nguyễn Văn Cường")]),
+    ];
+
+    for (policy, restored, skipped) in cases {
+        let dir = temp_dir(&format!("restore-{}", policy));
+        let workbook = sample_workbook(&dir);
+        let (server, client) = start();
+        let mapping = mapping();
+        workbook_reader(&job(workbook, &mapping, RunMode::Upload, &dir, TOKEN), Some(&client)).unwrap();
+
+        let ma_phieu = server.state.lock().unwrap().phieu_by_so_phieu("0001").unwrap().ma_phieu.clone();
+        let members = client.list_doituong(&ma_phieu).unwrap();
+        let path = backup_members(&dir.join("backups"), "0001", &ma_phieu, &members).unwrap();
+        let backup = read_backup(&path).unwrap();
+        let ids: Vec<String> = members.iter().map(|member| member.id.clone()).collect();
+        client.delete_doituong(&ids).unwrap();
+
+        server.state.lock().unwrap().failures.insert("/doing/doituong/add".to_owned(), 1);
+        let summary = restore_members(&client, &backup, &ma_phieu, &UploadOptions { on_error: policy, ..options(&dir) });
+
+        // Lần thêm đầu tiên (thành viên đầu của file sao lưu) gặp lỗi.
+        let skipped_names: Vec<Option<&str>> = summary.skipped.iter().map(|item| item.ho_ten.as_deref()).collect();
+        assert_eq!(summary.restored, restored, "{}", policy);
+        assert_eq!(skipped_names, skipped, "{}", policy);
+    }
+}
+
+#[test]
+fn existing_owner_is_updated_with_its_ma_phieu() {
+    let dir = temp_dir("owner-update");
+    let workbook = sample_workbook(&dir);
+    let (server, client) = start();
+    let mapping = mapping();
+
+    workbook_reader(&job(workbook.clone(), &mapping, RunMode::Upload, &dir, TOKEN), Some(&client)).unwrap();
+    let ma_phieu = server.state.lock().unwrap().phieu_by_so_phieu("0001").unwrap().ma_phieu.clone();
+
+    std::fs::remove_file(dir.join("checkpoint.json")).unwrap();
+    let mut owner = person("0001", "Nguyễn Văn", "An", (1, 2, 1980), "Chủ hộ");
+    owner[DIA_CHI] = Cell::Text("Thôn 2".to_owned());
+    owner[DIEN_THOAI] = Cell::Text("0987654321".to_owned());
+    write_workbook(&workbook, &[owner]);
+    workbook_reader(&job(workbook, &mapping, RunMode::Upload, &dir, TOKEN), Some(&client)).unwrap();
+
+    let state = server.state.lock().unwrap();
+    let phieu = state.phieu_by_so_phieu("0001").unwrap();
+    assert_eq!(state.phieu.len(), 2);
+    assert_eq!(phieu.ma_phieu, ma_phieu);
+    assert_eq!(phieu.fields["dia_chi"], "Thôn 2");
+    assert_eq!(phieu.fields["dien_thoai"], "0987654321");
+    assert_eq!(phieu.fields["chuho_ten"], "An");
+}
+
+#[test]
+fn error_policy_decides_what_is_skipped() {
+    let cases = [
+        (ErrorPolicy::SkipResident, vec![("0001".to_owned(), 2), ("0002".to_owned(), 2)]),
+        (ErrorPolicy::SkipHousehold, vec![("0001".to_owned(), 0), ("0002".to_owned(), 2)]),
+        (ErrorPolicy::Stop, vec![("0001".to_owned(), 0)]),
+    ];
+
+    for (policy, expected) in cases {
+        let dir = temp_dir(&format!("on-error-{}", policy));
+        let workbook = sample_workbook(&dir);
+        let (server, client) = start();
+        let mapping = mapping();
+        server.state.lock().unwrap().failures.insert("/doing/doituong/add".to_owned(), 1);
+
+        let mut job = job(workbook, &mapping, RunMode::Upload, &dir, TOKEN);
+        job.options.on_error = policy;
+        workbook_reader(&job, Some(&client)).unwrap();
+
+        assert_eq!(member_counts(&server), expected, "{}", policy);
+    }
+}
+
+#[test]
+fn households_with_skipped_members_are_counted_as_partial() {
+    let dir = temp_dir("on-error-partial");
+    let workbook = sample_workbook(&dir);
+    let journal = dir.join("requests.jsonl");
+    let (server, client) = start();
+    let mapping = mapping();
+    server.state.lock().unwrap().failures.insert("/doing/doituong/add".to_owned(), 1);
+
+    workbook_reader(&job(workbook, &mapping, RunMode::DryRun(journal.clone()), &dir, "{{pcgd-csrf-token}}"), None).unwrap();
+    let households = read_journal(&journal, TOKEN).unwrap();
+    let mut checkpoint = Checkpoint::load(&dir.join("checkpoint.json"), &journal).unwrap();
+    let options = UploadOptions { on_error: ErrorPolicy::SkipResident, ..options(&dir) };
+
+    let summary = upload_households(&client, households, &mut checkpoint, &options);
+
+    assert_eq!((summary.households, summary.partial, summary.residents), (1, 1, 4));
+    assert_eq!(summary.skipped.len(), 1);
+}
+
+#[test]
+fn skipped_members_are_added_on_the_next_run() {
+    let dir = temp_dir("on-error-rerun");
+    let workbook = sample_workbook(&dir);
+    let (server, client) = start();
+    let mapping = mapping();
+    server.state.lock().unwrap().failures.insert("/doing/doituong/add".to_owned(), 1);
+
+    let mut first = job(workbook.clone(), &mapping, RunMode::Upload, &dir, TOKEN);
+    first.options.on_error = ErrorPolicy::SkipResident;
+    assert_eq!(workbook_reader(&first, Some(&client)).unwrap(), RunOutcome::Incomplete(1));
+    assert_eq!(member_counts(&server), vec![("0001".to_owned(), 2), ("0002".to_owned(), 2)]);
+
+    assert_eq!(workbook_reader(&job(workbook, &mapping, RunMode::Upload, &dir, TOKEN), Some(&client)).unwrap(), RunOutcome::Done);
+    assert_eq!(member_counts(&server), vec![("0001".to_owned(), 3), ("0002".to_owned(), 2)]);
+}
+
+#[test]
+fn transient_errors_are_retried() {
+    let dir = temp_dir("on-error-retry");
+    let workbook = sample_workbook(&dir);
+    let (server, client) = start();
+    let mapping = mapping();
+    {
+        let mut state = server.state.lock().unwrap();
+        state.failures.insert("/doing/phieudieutra/update".to_owned(), 1);
+        state.failures.insert("/doing/doituong/add".to_owned(), 2);
+    }
+
+    let mut job = job(workbook, &mapping, RunMode::Upload, &dir, TOKEN);
+    job.options.on_error = "retry:2".parse().unwrap();
+    workbook_reader(&job, Some(&client)).unwrap();
+
+    assert_eq!(member_counts(&server), vec![("0001".to_owned(), 3), ("0002".to_owned(), 2)]);
+}
+
+#[test]
+fn writes_that_landed_before_an_error_are_not_sent_again() {
+    let dir = temp_dir("on-error-lost-reply");
+    let workbook = sample_workbook(&dir);
+    let (server, client) = start();
+    let mapping = mapping();
+    {
+        let mut state = server.state.lock().unwrap();
+        state.lost_replies.insert("/doing/phieudieutra/update".to_owned(), 1);
+        state.lost_replies.insert("/doing/doituong/add".to_owned(), 2);
+    }
+
+    let mut job = job(workbook, &mapping, RunMode::Upload, &dir, TOKEN);
+    job.options.on_error = "retry:2".parse().unwrap();
+    assert_eq!(workbook_reader(&job, Some(&client)).unwrap(), RunOutcome::Done);
+
+    assert_eq!(member_counts(&server), vec![("0001".to_owned(), 3), ("0002".to_owned(), 2)]);
+    assert_eq!(server.state.lock().unwrap().doituong.len(), 5);
+}
+
+#[test]
+fn existing_household_is_merged_when_creating_it_fails() {
+    let dir = temp_dir("create-existing");
+    let workbook = sample_workbook(&dir);
+    let (server, client) = start();
+    let mapping = mapping();
+    workbook_reader(&job(workbook.clone(), &mapping, RunMode::Upload, &dir, TOKEN), Some(&client)).unwrap();
+
+    std::fs::remove_file(dir.join("checkpoint.json")).unwrap();
+    server.state.lock().unwrap().failures.insert("/doing/phieudieutra/update".to_owned(), 1);
+
+    let mut job = job(workbook, &mapping, RunMode::Upload, &dir, TOKEN);
+    job.options.on_error = "retry:1".parse().unwrap();
+    assert_eq!(workbook_reader(&job, Some(&client)).unwrap(), RunOutcome::Done);
+
+    assert_eq!(member_counts(&server), vec![("0001".to_owned(), 3), ("0002".to_owned(), 2)]);
+    assert_eq!(server.state.lock().unwrap().phieu.len(), 2);
+}
+
+#[test]
+fn expired_session_while_listing_members_stops_the_run() {
+    let dir = temp_dir("expired-members");
+    let workbook = sample_workbook(&dir);
+    let (server, client) = start();
+    let mapping = mapping();
+    workbook_reader(&job(workbook.clone(), &mapping, RunMode::Upload, &dir, TOKEN), Some(&client)).unwrap();
+
+    std::fs::remove_file(dir.join("checkpoint.json")).unwrap();
+    server.state.lock().unwrap().expired.insert("/doing/doituong/lay_doituong".to_owned(), 1);
+
+    let mut job = job(workbook, &mapping, RunMode::Upload, &dir, TOKEN);
+    job.options.on_error = ErrorPolicy::SkipHousehold;
+    assert_eq!(workbook_reader(&job, Some(&client)).unwrap(), RunOutcome::Stopped);
+    assert!(server.state.lock().unwrap().expired.values().all(|remaining| *remaining == 0));
+}